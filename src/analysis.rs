@@ -1,35 +1,184 @@
 // Core analysis passes for ND taint propagation and CALM verification
 
-use crate::model::{Edge, EdgeAnalysis, HydroIr, Node, NodeAnalysis, OverallAnalysis};
-use std::collections::{HashMap, HashSet};
-
-// String constants for analysis results
-const ND_DETERMINISTIC: &str = "Deterministic";
-const ND_LOCALLY_NONDET: &str = "LocallyNonDet";
-const ND_EXTERNAL_NONDET: &str = "ExternalNonDet";
-const CALM_SAFE: &str = "CalmSafe";
-const CALM_UNSAFE: &str = "CalmUnsafe";
+use crate::config::AnalysisConfig;
+use crate::model::{
+    Edge, EdgeAnalysis, HydroIr, Issue, LocationConvergence, Node, NodeAnalysis, OverallAnalysis,
+    SinkVerdict,
+};
+use crate::parallel::par_map;
+use crate::pass::{AnalysisPass, PassContext, PassRegistry};
+use crate::semantics::{Classifier, NdKinds, OpSemantics, Seal};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Values of `NodeAnalysis::nd_effect`
+pub const ND_DETERMINISTIC: &str = "Deterministic";
+pub const ND_LOCALLY_NONDET: &str = "LocallyNonDet";
+pub const ND_EXTERNAL_NONDET: &str = "ExternalNonDet";
+/// Values of `EdgeAnalysis::calm`
+pub const CALM_SAFE: &str = "CalmSafe";
+pub const CALM_UNSAFE: &str = "CalmUnsafe";
 
 /// Combined analysis results
 pub struct AnalysisResult {
-    pub node_analyses: HashMap<String, NodeAnalysis>,
-    pub edge_analyses: HashMap<String, EdgeAnalysis>,
+    pub node_analyses: BTreeMap<String, NodeAnalysis>,
+    pub edge_analyses: BTreeMap<String, EdgeAnalysis>,
     pub overall: OverallAnalysis,
+    /// Profile and environment model the passes classified operators with
+    pub classifier: Classifier,
+    /// Consistency verdicts for the program's outputs, in node order
+    pub verdicts: Vec<SinkVerdict>,
+    /// Replica convergence of each cluster location, by location ID
+    pub convergence: Vec<LocationConvergence>,
+}
+
+impl AnalysisResult {
+    /// Results before any pass has run: every node deterministic, every edge CALM-safe
+    pub(crate) fn initial(ir: &HydroIr, config: &AnalysisConfig, classifier: Classifier) -> Self {
+        let frame_filter = &config.frame_filter;
+        let node_analyses = ir
+            .nodes
+            .iter()
+            .map(|node| {
+                let classification = classifier.classify(node);
+                (
+                    node.id.clone(),
+                    NodeAnalysis {
+                        nd_effect: ND_DETERMINISTIC.into(),
+                        monotone: classification.semantics.monotone
+                            != crate::semantics::Monotonicity::Never,
+                        semantics_source: classification.source.to_string(),
+                        env_dependent: false,
+                        nd_kinds: Vec::new(),
+                        sealed: None,
+                        issues: Vec::new(),
+                        source_location: node.extract_source_location_with(frame_filter),
+                        backtrace: if config.full_backtrace {
+                            node.user_backtrace(frame_filter)
+                        } else {
+                            Vec::new()
+                        },
+                        pass_results: BTreeMap::new(),
+                    },
+                )
+            })
+            .collect();
+
+        let edge_analyses = ir
+            .edges
+            .iter()
+            .map(|edge| {
+                (
+                    edge.id.clone(),
+                    EdgeAnalysis {
+                        is_lattice: crate::semantics::is_lattice_type(edge.label.as_deref()),
+                        calm: CALM_SAFE.into(),
+                        issues: Vec::new(),
+                        pass_results: BTreeMap::new(),
+                    },
+                )
+            })
+            .collect();
+
+        AnalysisResult {
+            node_analyses,
+            edge_analyses,
+            overall: OverallAnalysis {
+                deterministic: true,
+                calm_safe: true,
+                deterministic_modulo_environment: true,
+                environment_dependent: false,
+            },
+            classifier,
+            verdicts: Vec::new(),
+            convergence: Vec::new(),
+        }
+    }
+
+    /// Put every emitted collection in canonical order, so the output only
+    /// depends on the input: issues by `Issue::canonical_cmp`, verdicts by
+    /// sink node order and convergence verdicts by location
+    pub(crate) fn canonicalize(&mut self, ir: &HydroIr) {
+        let issues = self
+            .node_analyses
+            .values_mut()
+            .map(|analysis| &mut analysis.issues)
+            .chain(
+                self.edge_analyses
+                    .values_mut()
+                    .map(|analysis| &mut analysis.issues),
+            );
+        for issues in issues {
+            issues.sort_by(Issue::canonical_cmp);
+        }
+
+        let order: HashMap<&str, usize> = ir
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| (node.id.as_str(), idx))
+            .collect();
+        let position = |id: &str| order.get(id).copied().unwrap_or(usize::MAX);
+        self.verdicts.sort_by_key(|verdict| position(&verdict.sink));
+        for verdict in &mut self.verdicts {
+            verdict.sealed_by.sort_by_key(|id| position(id));
+        }
+        self.convergence
+            .sort_by_key(|convergence| convergence.location);
+    }
+
+    /// Semantics of a node under the profile used for this run
+    pub fn semantics(&self, node: &Node) -> OpSemantics {
+        self.classifier.semantics(node)
+    }
+
+    /// Attach a pass-specific result to a node under `pass_results.<pass>.<key>`
+    pub fn attach_node_result(
+        &mut self,
+        pass: &str,
+        node_id: &str,
+        key: &str,
+        value: serde_json::Value,
+    ) {
+        if let Some(analysis) = self.node_analyses.get_mut(node_id) {
+            analysis
+                .pass_results
+                .entry(pass.to_string())
+                .or_default()
+                .insert(key.to_string(), value);
+        }
+    }
+
+    /// Attach a pass-specific result to an edge under `pass_results.<pass>.<key>`
+    pub fn attach_edge_result(
+        &mut self,
+        pass: &str,
+        edge_id: &str,
+        key: &str,
+        value: serde_json::Value,
+    ) {
+        if let Some(analysis) = self.edge_analyses.get_mut(edge_id) {
+            analysis
+                .pass_results
+                .entry(pass.to_string())
+                .or_default()
+                .insert(key.to_string(), value);
+        }
+    }
 }
 
 /// Graph representation for analysis
-struct Graph {
+pub(crate) struct Graph {
     /// Map from node ID to index
     node_id_to_idx: HashMap<String, usize>,
     /// Forward adjacency list: node index -> list of (target index, edge id)
-    forward: Vec<Vec<(usize, String)>>,
+    pub(crate) forward: Vec<Vec<(usize, String)>>,
     /// Backward adjacency list: node index -> list of (source index, edge id)
-    backward: Vec<Vec<(usize, String)>>,
+    pub(crate) backward: Vec<Vec<(usize, String)>>,
 }
 
 impl Graph {
     /// Build graph from nodes and edges
-    fn build(nodes: &[Node], edges: &[Edge]) -> Self {
+    pub(crate) fn build(nodes: &[Node], edges: &[Edge]) -> Self {
         let n = nodes.len();
 
         // Build node ID to index mapping
@@ -62,7 +211,7 @@ impl Graph {
     }
 
     /// Get node index from ID
-    fn get_idx(&self, node_id: &str) -> Option<usize> {
+    pub(crate) fn get_idx(&self, node_id: &str) -> Option<usize> {
         self.node_id_to_idx.get(node_id).copied()
     }
 }
@@ -71,6 +220,12 @@ impl Graph {
 struct NdResults {
     /// Map from node ID to ND effect string
     nd_effects: HashMap<String, String>,
+    /// Map from node ID to the kinds of nondeterminism reaching it
+    nd_kinds: HashMap<String, NdKinds>,
+    /// Nodes downstream of an environment origin (or origins themselves)
+    env_dependent: HashSet<String>,
+    /// Sealing nodes that stopped taint, with the seal reason
+    sealed: HashMap<String, &'static str>,
 }
 
 /// CALM analysis results
@@ -82,95 +237,178 @@ struct CalmResults {
 }
 
 /// Run ND taint propagation pass
-fn run_nd_pass(graph: &Graph, nodes: &[Node]) -> NdResults {
-    use crate::semantics::{NdEffect, get_node_semantics};
-
-    // Identify seed nodes (non-deterministic nodes)
-    let mut tainted = HashSet::new();
-    let mut nd_effects = HashMap::new();
+///
+/// Local and environment nondeterminism are propagated separately, each as a
+/// set of `NdKind`s. A node tainted by both reports `LocallyNonDet`, since
+/// the program itself is racy there regardless of the environment. Taint
+/// stops at sealing operators (see `Classifier::seal`); order-only seals
+/// stop just the `Ordering` kind.
+fn run_nd_pass(
+    graph: &Graph,
+    nodes: &[Node],
+    edges: &[Edge],
+    classifier: &Classifier,
+) -> NdResults {
+    use crate::semantics::NdEffect;
+
+    // Classify every node's inputs once, for ND kinds and seals
+    let edges_by_id: HashMap<&str, &Edge> = edges.iter().map(|e| (e.id.as_str(), e)).collect();
+    let indices: Vec<usize> = (0..nodes.len()).collect();
+    let classified = par_map(&indices, |&idx| {
+        let node = &nodes[idx];
+        let inputs: Vec<(&Edge, &Node)> = graph.backward[idx]
+            .iter()
+            .filter_map(|(source_idx, edge_id)| {
+                Some((*edges_by_id.get(edge_id.as_str())?, &nodes[*source_idx]))
+            })
+            .collect();
+        let nd = classifier.semantics(node).nd;
+        let kinds = match nd {
+            NdEffect::Deterministic => NdKinds::EMPTY,
+            _ => classifier.nd_kinds(node, &inputs),
+        };
+        (nd, kinds, classifier.seal(node, &inputs))
+    });
+
+    let mut local_roots = vec![NdKinds::EMPTY; nodes.len()];
+    let mut env_roots = vec![NdKinds::EMPTY; nodes.len()];
+    let mut seals: HashMap<usize, Seal> = HashMap::new();
+    for (idx, (nd, kinds, seal)) in classified.into_iter().enumerate() {
+        match nd {
+            NdEffect::Deterministic => {}
+            NdEffect::LocallyNonDet => local_roots[idx] = kinds,
+            NdEffect::ExternalNonDet => env_roots[idx] = kinds,
+        }
+        if let Some(seal) = seal {
+            seals.insert(idx, seal);
+        }
+    }
 
-    for (idx, node) in nodes.iter().enumerate() {
-        let semantics = get_node_semantics(node);
+    let mut sealed = HashSet::new();
+    let local = propagate_nd_kinds(graph, local_roots, &seals, &mut sealed);
+    let env = propagate_nd_kinds(graph, env_roots, &seals, &mut sealed);
+
+    let nd_effects = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| {
+            let effect = if !local[idx].is_empty() {
+                ND_LOCALLY_NONDET
+            } else if !env[idx].is_empty() {
+                ND_EXTERNAL_NONDET
+            } else {
+                ND_DETERMINISTIC
+            };
+            (node.id.clone(), effect.into())
+        })
+        .collect();
 
-        match semantics.nd {
-            NdEffect::Deterministic => {
-                // Not a seed, will be marked later if tainted
-            }
-            NdEffect::LocallyNonDet => {
-                tainted.insert(idx);
-                nd_effects.insert(node.id.clone(), ND_LOCALLY_NONDET.into());
-            }
-            NdEffect::ExternalNonDet => {
-                tainted.insert(idx);
-                nd_effects.insert(node.id.clone(), ND_EXTERNAL_NONDET.into());
-            }
-        }
+    NdResults {
+        nd_effects,
+        nd_kinds: nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| (node.id.clone(), local[idx].join(env[idx])))
+            .collect(),
+        env_dependent: nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !env[*idx].is_empty())
+            .map(|(_, node)| node.id.clone())
+            .collect(),
+        sealed: sealed
+            .into_iter()
+            .map(|idx| (nodes[idx].id.clone(), seals[&idx].reason))
+            .collect(),
     }
+}
 
-    // Compute transitive closure over outgoing edges
-    let mut worklist: Vec<usize> = tainted.iter().copied().collect();
+/// Propagate ND kinds from their roots over outgoing edges to a fixpoint
+///
+/// Returns the kinds reaching each node, roots included. A seal keeps the
+/// kinds it stops from entering its node; seals that stopped anything are
+/// added to `sealed`.
+fn propagate_nd_kinds(
+    graph: &Graph,
+    roots: Vec<NdKinds>,
+    seals: &HashMap<usize, Seal>,
+    sealed: &mut HashSet<usize>,
+) -> Vec<NdKinds> {
+    let mut kinds = roots;
+    let mut worklist: Vec<usize> = (0..kinds.len())
+        .filter(|&idx| !kinds[idx].is_empty())
+        .collect();
 
     while let Some(node_idx) = worklist.pop() {
-        // For each successor of this tainted node
         for &(successor_idx, _) in &graph.forward[node_idx] {
-            if !tainted.contains(&successor_idx) {
-                // Mark successor as tainted
-                tainted.insert(successor_idx);
-
-                // Determine the ND effect for the successor
-                // If it's tainted by propagation, use the original semantics or inherit
-                let successor_id = &nodes[successor_idx].id;
-                let semantics = get_node_semantics(&nodes[successor_idx]);
-
-                let effect = match semantics.nd {
-                    NdEffect::Deterministic => ND_LOCALLY_NONDET, // Tainted by propagation
-                    NdEffect::LocallyNonDet => ND_LOCALLY_NONDET,
-                    NdEffect::ExternalNonDet => ND_EXTERNAL_NONDET,
-                };
-
-                nd_effects.insert(successor_id.clone(), effect.into());
+            let mut incoming = kinds[node_idx];
+            if let Some(seal) = seals.get(&successor_idx) {
+                let passed = incoming.without(seal.stops());
+                if passed != incoming {
+                    sealed.insert(successor_idx);
+                }
+                incoming = passed;
+            }
+            let joined = kinds[successor_idx].join(incoming);
+            if joined != kinds[successor_idx] {
+                kinds[successor_idx] = joined;
                 worklist.push(successor_idx);
             }
         }
     }
 
-    // Mark untainted nodes as Deterministic
-    for node in nodes {
-        nd_effects
-            .entry(node.id.clone())
-            .or_insert_with(|| ND_DETERMINISTIC.into());
-    }
+    kinds
+}
+
+/// Whether an edge is CALM-critical: it crosses the network or feeds a sink
+pub(crate) fn is_calm_critical(edge: &Edge, target: Option<&Node>) -> bool {
+    let is_network = edge
+        .semantic_tags
+        .as_ref()
+        .map(|tags| tags.iter().any(|tag| tag == "Network"))
+        .unwrap_or(false);
+    let targets_sink = target.map(|node| node.node_type == "Sink").unwrap_or(false);
 
-    NdResults { nd_effects }
+    is_network || targets_sink
 }
 
 /// Run CALM analysis pass
-fn run_calm_pass(graph: &Graph, nodes: &[Node], edges: &[Edge]) -> CalmResults {
+///
+/// The check only depends on the critical edge's target, so edges into the
+/// same node share one check. In incremental runs, edges whose upstream is
+/// unchanged take their status from the cache instead.
+fn run_calm_pass(ctx: &PassContext<'_>) -> CalmResults {
+    let ir = ctx.ir;
     let mut calm_status = HashMap::new();
     let mut all_calm_safe = true;
 
-    // Identify CALM-critical edges
-    let mut critical_edges = Vec::new();
-    for edge in edges {
-        let is_network = edge
-            .semantic_tags
-            .as_ref()
-            .map(|tags| tags.iter().any(|tag| tag == "Network"))
-            .unwrap_or(false);
-        let targets_sink = graph
-            .get_idx(&edge.target)
-            .and_then(|idx| nodes.get(idx))
-            .map(|node| node.node_type == "Sink")
-            .unwrap_or(false);
-
-        if is_network || targets_sink {
-            critical_edges.push(edge);
-        }
-    }
-
-    // For each critical edge, check all paths to it
-    for edge in critical_edges {
-        let edge_safe = check_edge_calm_safe(graph, nodes, edges, edge);
+    // Critical edges with their target, if it exists; edges into a missing
+    // target are considered safe
+    let critical: Vec<(&Edge, usize)> = ir
+        .edges
+        .iter()
+        .filter(|edge| is_calm_critical(edge, ctx.node(&edge.target)))
+        .filter_map(|edge| Some((edge, ctx.graph.get_idx(&edge.target)?)))
+        .collect();
+    let mut targets: Vec<usize> = critical
+        .iter()
+        .filter(|(edge, _)| ctx.cached_calm(edge).is_none())
+        .map(|&(_, target_idx)| target_idx)
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let checked: HashMap<usize, bool> = targets
+        .iter()
+        .copied()
+        .zip(par_map(&targets, |&target_idx| {
+            check_edge_calm_safe(ctx, target_idx)
+        }))
+        .collect();
+
+    for (edge, target_idx) in critical {
+        let edge_safe = ctx
+            .cached_calm(edge)
+            .unwrap_or_else(|| checked[&target_idx]);
 
         let status = if edge_safe {
             CALM_SAFE
@@ -183,7 +421,7 @@ fn run_calm_pass(graph: &Graph, nodes: &[Node], edges: &[Edge]) -> CalmResults {
     }
 
     // Mark non-critical edges as CalmSafe by default
-    for edge in edges {
+    for edge in &ir.edges {
         calm_status
             .entry(edge.id.clone())
             .or_insert_with(|| CALM_SAFE.into());
@@ -195,237 +433,359 @@ fn run_calm_pass(graph: &Graph, nodes: &[Node], edges: &[Edge]) -> CalmResults {
     }
 }
 
-/// Check if an edge is CALM safe by verifying all paths to it
-fn check_edge_calm_safe(graph: &Graph, nodes: &[Node], edges: &[Edge], target_edge: &Edge) -> bool {
-    use crate::semantics::{Monotonicity, get_node_semantics, is_lattice_type};
-
-    // Get the target node index
-    let target_idx = match graph.get_idx(&target_edge.target) {
-        Some(idx) => idx,
-        None => return true, // If target doesn't exist, consider safe
-    };
-
-    // Build edge lookup map
-    let mut edge_map = HashMap::new();
-    for edge in edges {
-        if let (Some(src), Some(tgt)) = (graph.get_idx(&edge.source), graph.get_idx(&edge.target)) {
-            edge_map.insert((src, tgt), edge);
+/// Edges leaving a node that lie on a path to `target_idx`
+///
+/// Every edge is returned, so parallel edges between the same two nodes
+/// (e.g. the `first` and `second` inputs of a `cross_product` fed from one
+/// operator) are each checked.
+fn edges_on_paths_to<'a>(
+    ctx: &PassContext<'a>,
+    node_idx: usize,
+    target_idx: usize,
+) -> impl Iterator<Item = &'a Edge> {
+    ctx.graph.forward[node_idx]
+        .iter()
+        .filter(move |(successor_idx, _)| ctx.reach.reaches(*successor_idx, target_idx))
+        .filter_map(|(_, edge_id)| ctx.edge(edge_id))
+}
+
+/// How a node breaks monotonicity, if it does
+///
+/// Operators with per-port semantics (e.g. `anti_join`) only break it when a
+/// growing input, one not tagged `Bounded`, feeds a port they aren't monotone
+/// in; those inputs are returned. Otherwise the operator's overall
+/// monotonicity decides, and a non-monotone operator returns no inputs.
+fn non_monotone_inputs<'a>(ctx: &PassContext<'a>, node_idx: usize) -> Option<Vec<&'a Edge>> {
+    use crate::semantics::Monotonicity;
+
+    let semantics = ctx.classifier.semantics(&ctx.ir.nodes[node_idx]);
+    let whole = || (semantics.monotone == Monotonicity::Never).then(Vec::new);
+    if semantics.ports.is_empty() {
+        return whole();
+    }
+
+    let mut offending = Vec::new();
+    for (_, edge_id) in &ctx.graph.backward[node_idx] {
+        let Some(edge) = ctx.edge(edge_id) else {
+            continue;
+        };
+        match edge.port().and_then(|port| semantics.port_monotone(port)) {
+            Some(Monotonicity::Always | Monotonicity::Depends) => {}
+            Some(Monotonicity::Never | Monotonicity::Antitone) => {
+                let bounded = edge.semantic_tags.iter().flatten().any(|t| t == "Bounded");
+                if !bounded {
+                    offending.push(edge);
+                }
+            }
+            // An input without a known port could be any of them
+            None => return whole(),
         }
     }
+    (!offending.is_empty()).then_some(offending)
+}
 
-    // Compute backward reachability from target node
-    let reachable = compute_backward_reachable(graph, target_idx);
+/// Check if the edges into a node are CALM safe by verifying all paths to it
+fn check_edge_calm_safe(ctx: &PassContext<'_>, target_idx: usize) -> bool {
+    use crate::semantics::is_lattice_type;
 
     // Check all paths: verify monotonicity and lattice types
-    for &node_idx in &reachable {
-        let node = &nodes[node_idx];
-        let semantics = get_node_semantics(node);
-
+    for node_idx in ctx.reach.upstream(target_idx) {
         // Check if node is non-monotone
-        if semantics.monotone == Monotonicity::Never {
+        if non_monotone_inputs(ctx, node_idx).is_some() {
             return false;
         }
 
         // Check outgoing edges from this node (that are on paths to target)
-        for &(successor_idx, _) in &graph.forward[node_idx] {
-            if reachable.contains(&successor_idx) || successor_idx == target_idx {
-                // This edge is on a path to target
-                if let Some(edge) = edge_map.get(&(node_idx, successor_idx))
-                    && !is_lattice_type(edge.label.as_deref())
-                {
-                    return false;
-                }
-            }
+        if edges_on_paths_to(ctx, node_idx, target_idx)
+            .any(|edge| !is_lattice_type(edge.label.as_deref()))
+        {
+            return false;
         }
     }
 
     true
 }
 
-/// Compute backward reachable nodes from a target
-fn compute_backward_reachable(graph: &Graph, target_idx: usize) -> HashSet<usize> {
-    let mut reachable = HashSet::new();
-    let mut worklist = vec![target_idx];
-    reachable.insert(target_idx);
-
-    while let Some(node_idx) = worklist.pop() {
-        for &(predecessor_idx, _) in &graph.backward[node_idx] {
-            if !reachable.contains(&predecessor_idx) {
-                reachable.insert(predecessor_idx);
-                worklist.push(predecessor_idx);
+/// Shortest upstream path from a root cause to `target`, as node indices
+/// ending at `target`. Root causes are intrinsic ND operators, or
+/// non-monotone operators for NonMonotone issues. The path only takes the
+/// edges `(pred, node)` for which `can_step` holds.
+pub(crate) fn witness_path(
+    ir: &HydroIr,
+    graph: &Graph,
+    target: usize,
+    is_root: &dyn Fn(&Node) -> bool,
+    can_step: &dyn Fn(usize, usize) -> bool,
+) -> Option<Vec<usize>> {
+    let mut parent: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([target]);
+    parent.insert(target, target);
+    while let Some(idx) = queue.pop_front() {
+        if is_root(&ir.nodes[idx]) {
+            let mut path = vec![idx];
+            let mut cur = idx;
+            while cur != target {
+                cur = parent[&cur];
+                path.push(cur);
+            }
+            return Some(path);
+        }
+        for &(pred, _) in &graph.backward[idx] {
+            if !can_step(pred, idx) {
+                continue;
+            }
+            if let std::collections::hash_map::Entry::Vacant(e) = parent.entry(pred) {
+                e.insert(idx);
+                queue.push_back(pred);
             }
         }
     }
-
-    reachable
+    None
 }
 
 /// Extract issues from analysis results
 fn extract_issues(
-    ir: &HydroIr,
-    graph: &Graph,
-    nd_results: &NdResults,
-    calm_results: &CalmResults,
-    node_analyses: &mut HashMap<String, NodeAnalysis>,
-    edge_analyses: &mut HashMap<String, EdgeAnalysis>,
+    ctx: &PassContext<'_>,
+    node_analyses: &mut BTreeMap<String, NodeAnalysis>,
+    edge_analyses: &mut BTreeMap<String, EdgeAnalysis>,
 ) {
-    use crate::model::Issue;
-    use crate::semantics::{Monotonicity, get_node_semantics, is_lattice_type};
+    let ir = ctx.ir;
 
-    // Generate NonDet issues for tainted nodes
+    // Generate NonDet issues for locally tainted nodes; depending on the
+    // environment is not a defect of the program
     for node in &ir.nodes {
-        if let Some(nd_effect) = nd_results.nd_effects.get(&node.id)
-            && nd_effect != ND_DETERMINISTIC
-            && let Some(analysis) = node_analyses.get_mut(&node.id)
+        if let Some(analysis) = node_analyses.get_mut(&node.id)
+            && analysis.nd_effect == ND_LOCALLY_NONDET
         {
+            let message = format!(
+                "Node '{}' is nondeterministic ({})",
+                node.id, analysis.nd_effect
+            );
             analysis.issues.push(Issue {
                 kind: "NonDet".to_string(),
-                message: format!("Node '{}' is nondeterministic ({})", node.id, nd_effect),
+                subject: node.id.clone(),
+                message,
+                affected_critical_edges: Vec::new(),
             });
         }
     }
 
-    // Build edge lookup map for CALM path checking
-    let mut edge_map = HashMap::new();
-    for edge in &ir.edges {
-        if let (Some(src), Some(tgt)) = (graph.get_idx(&edge.source), graph.get_idx(&edge.target)) {
-            edge_map.insert((src, tgt), edge);
+    // Generate NonMonotone and NonLattice issues for CALM-critical edges,
+    // one per root cause listing every unsafe edge it affects
+    let unsafe_edges: Vec<(&Edge, usize)> = ir
+        .edges
+        .iter()
+        .filter(|edge| {
+            edge_analyses
+                .get(&edge.id)
+                .is_some_and(|analysis| analysis.calm == CALM_UNSAFE)
+        })
+        .filter_map(|edge| Some((edge, ctx.graph.get_idx(&edge.target)?)))
+        .collect();
+    // Violations only depend on the edge's target, so edges into the same
+    // node share them
+    let mut targets: Vec<usize> = unsafe_edges
+        .iter()
+        .map(|&(_, target_idx)| target_idx)
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let violations: HashMap<usize, CalmViolations> = targets
+        .iter()
+        .copied()
+        .zip(par_map(&targets, |&target_idx| {
+            calm_violations(ctx, target_idx)
+        }))
+        .collect();
+
+    let mut non_monotone: BTreeMap<usize, (Vec<&Edge>, Vec<String>)> = BTreeMap::new();
+    let mut non_lattice: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for &(critical, target_idx) in &unsafe_edges {
+        let violations = &violations[&target_idx];
+        for (node_idx, inputs) in &violations.nodes {
+            let (_, affected) = non_monotone
+                .entry(*node_idx)
+                .or_insert_with(|| (inputs.clone(), Vec::new()));
+            affected.push(critical.id.clone());
+        }
+        for &path_edge in &violations.edges {
+            non_lattice
+                .entry(path_edge.id.as_str())
+                .or_default()
+                .push(critical.id.clone());
         }
     }
 
-    // Generate NonMonotone and NonLattice issues for CALM-critical edges
-    for edge in &ir.edges {
-        if let Some(calm_status) = calm_results.calm_status.get(&edge.id)
-            && calm_status == CALM_UNSAFE
-        {
-            // This edge is CALM-unsafe, find the violations
-            let target_idx = match graph.get_idx(&edge.target) {
-                Some(idx) => idx,
-                None => continue,
-            };
+    for (node_idx, (inputs, affected)) in non_monotone {
+        let node = &ir.nodes[node_idx];
+        let Some(analysis) = node_analyses.get_mut(&node.id) else {
+            continue;
+        };
+        let growing = if inputs.is_empty() {
+            String::new()
+        } else {
+            let ports: Vec<String> = inputs
+                .iter()
+                .map(|input| {
+                    format!(
+                        "'{}' (edge '{}')",
+                        input.label.as_deref().unwrap_or_default(),
+                        input.id
+                    )
+                })
+                .collect();
+            format!(" in growing input {}", ports.join(", "))
+        };
+        analysis.issues.push(Issue {
+            kind: "NonMonotone".to_string(),
+            subject: node.id.clone(),
+            message: format!(
+                "Node '{}' is non-monotone{} on {}",
+                node.id,
+                growing,
+                critical_paths(&affected)
+            ),
+            affected_critical_edges: affected,
+        });
+    }
 
-            // Compute backward reachable nodes
-            let reachable = compute_backward_reachable(graph, target_idx);
-
-            // Check for non-monotone operators on paths
-            for &node_idx in &reachable {
-                let node = &ir.nodes[node_idx];
-                let semantics = get_node_semantics(node);
-
-                if semantics.monotone == Monotonicity::Never
-                    && let Some(analysis) = node_analyses.get_mut(&node.id)
-                {
-                    analysis.issues.push(Issue {
-                        kind: "NonMonotone".to_string(),
-                        message: format!(
-                            "Node '{}' is non-monotone on CALM-critical path to edge '{}'",
-                            node.id, edge.id
-                        ),
-                    });
-                }
+    for (edge_id, affected) in non_lattice {
+        if let Some(analysis) = edge_analyses.get_mut(edge_id) {
+            analysis.issues.push(Issue {
+                kind: "NonLattice".to_string(),
+                subject: edge_id.to_string(),
+                message: format!(
+                    "Edge '{}' is non-lattice on {}",
+                    edge_id,
+                    critical_paths(&affected)
+                ),
+                affected_critical_edges: affected,
+            });
+        }
+    }
+}
 
-                // Check outgoing edges for non-lattice types
-                for &(successor_idx, _) in &graph.forward[node_idx] {
-                    if (reachable.contains(&successor_idx) || successor_idx == target_idx)
-                        && let Some(path_edge) = edge_map.get(&(node_idx, successor_idx))
-                        && !is_lattice_type(path_edge.label.as_deref())
-                        && let Some(analysis) = edge_analyses.get_mut(&path_edge.id)
-                    {
-                        analysis.issues.push(Issue {
-                            kind: "NonLattice".to_string(),
-                            message: format!(
-                                "Edge '{}' is non-lattice on CALM-critical path to edge '{}'",
-                                path_edge.id, edge.id
-                            ),
-                        });
-                    }
-                }
+/// "CALM-critical path(s) to ..." for the edges an issue affects
+fn critical_paths(affected: &[String]) -> String {
+    match affected {
+        [edge] => format!("CALM-critical path to edge '{}'", edge),
+        edges => format!("CALM-critical paths to {} edges", edges.len()),
+    }
+}
+
+/// Root causes of one CALM-critical edge being unsafe
+#[derive(Default)]
+struct CalmViolations<'a> {
+    /// Non-monotone nodes, by index, with the growing inputs they are
+    /// non-monotone in (empty if the whole operator is)
+    nodes: Vec<(usize, Vec<&'a Edge>)>,
+    /// Non-lattice edges
+    edges: Vec<&'a Edge>,
+}
+
+/// Violations on the paths to a CALM-unsafe edge into `target_idx`
+fn calm_violations<'a>(ctx: &PassContext<'a>, target_idx: usize) -> CalmViolations<'a> {
+    use crate::semantics::is_lattice_type;
+
+    let mut violations = CalmViolations::default();
+
+    // Check for non-monotone operators on paths
+    for node_idx in ctx.reach.upstream(target_idx) {
+        if let Some(inputs) = non_monotone_inputs(ctx, node_idx) {
+            violations.nodes.push((node_idx, inputs));
+        }
+
+        // Check outgoing edges for non-lattice types
+        for path_edge in edges_on_paths_to(ctx, node_idx, target_idx) {
+            if !is_lattice_type(path_edge.label.as_deref()) {
+                violations.edges.push(path_edge);
             }
         }
     }
+
+    violations
 }
 
-/// Run all analysis passes on the input IR
-pub fn run_analysis(ir: &HydroIr) -> AnalysisResult {
-    // Build graph
-    let graph = Graph::build(&ir.nodes, &ir.edges);
+/// ND taint propagation as a registered pass
+pub(crate) struct NdPass;
 
-    // Run ND pass
-    let nd_results = run_nd_pass(&graph, &ir.nodes);
+impl AnalysisPass for NdPass {
+    fn name(&self) -> &str {
+        "nd"
+    }
 
-    // Run CALM pass
-    let calm_results = run_calm_pass(&graph, &ir.nodes, &ir.edges);
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let nd_results = run_nd_pass(&ctx.graph, &ctx.ir.nodes, &ctx.ir.edges, &ctx.classifier);
 
-    // Compute overall deterministic status
-    let overall_deterministic = nd_results
-        .nd_effects
-        .values()
-        .all(|effect| effect == ND_DETERMINISTIC);
+        for (node_id, effect) in nd_results.nd_effects {
+            if let Some(analysis) = results.node_analyses.get_mut(&node_id) {
+                analysis.env_dependent = nd_results.env_dependent.contains(&node_id);
+                analysis.sealed = nd_results.sealed.get(&node_id).map(|r| r.to_string());
+                analysis.nd_kinds = nd_results.nd_kinds[&node_id]
+                    .iter()
+                    .map(|kind| kind.to_string())
+                    .collect();
+                analysis.nd_effect = effect;
+            }
+        }
 
-    // Create node analyses
-    let mut node_analyses = HashMap::new();
-    for node in &ir.nodes {
-        let nd_effect = nd_results
-            .nd_effects
-            .get(&node.id)
-            .cloned()
-            .unwrap_or_else(|| ND_DETERMINISTIC.into());
-
-        let semantics = crate::semantics::get_node_semantics(node);
-        let monotone = semantics.monotone != crate::semantics::Monotonicity::Never;
-        let source_location = node.extract_source_location();
-
-        node_analyses.insert(
-            node.id.clone(),
-            NodeAnalysis {
-                nd_effect,
-                monotone,
-                issues: Vec::new(),
-                source_location,
-            },
-        );
+        results.overall.deterministic = results
+            .node_analyses
+            .values()
+            .all(|analysis| analysis.nd_effect == ND_DETERMINISTIC);
+        results.overall.deterministic_modulo_environment = results
+            .node_analyses
+            .values()
+            .all(|analysis| analysis.nd_effect != ND_LOCALLY_NONDET);
+        results.overall.environment_dependent = !nd_results.env_dependent.is_empty();
     }
+}
 
-    // Create edge analyses
-    let mut edge_analyses = HashMap::new();
-    for edge in &ir.edges {
-        let is_lattice = crate::semantics::is_lattice_type(edge.label.as_deref());
-        let calm = calm_results
-            .calm_status
-            .get(&edge.id)
-            .cloned()
-            .unwrap_or_else(|| CALM_SAFE.into());
-
-        edge_analyses.insert(
-            edge.id.clone(),
-            EdgeAnalysis {
-                is_lattice,
-                calm,
-                issues: Vec::new(),
-            },
-        );
+/// CALM verification of critical edges as a registered pass
+pub(crate) struct CalmPass;
+
+impl AnalysisPass for CalmPass {
+    fn name(&self) -> &str {
+        "calm"
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let calm_results = run_calm_pass(ctx);
+
+        for (edge_id, status) in calm_results.calm_status {
+            if let Some(analysis) = results.edge_analyses.get_mut(&edge_id) {
+                analysis.calm = status;
+            }
+        }
+
+        results.overall.calm_safe = calm_results.overall_calm_safe;
+    }
+}
+
+/// Issue extraction from ND and CALM results as a registered pass
+pub(crate) struct IssuePass;
+
+impl AnalysisPass for IssuePass {
+    fn name(&self) -> &str {
+        "issues"
+    }
+
+    fn dependencies(&self) -> &[&str] {
+        &["nd", "calm"]
     }
 
-    // Extract issues
-    extract_issues(
-        ir,
-        &graph,
-        &nd_results,
-        &calm_results,
-        &mut node_analyses,
-        &mut edge_analyses,
-    );
-
-    AnalysisResult {
-        node_analyses,
-        edge_analyses,
-        overall: OverallAnalysis {
-            deterministic: overall_deterministic,
-            calm_safe: calm_results.overall_calm_safe,
-        },
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        extract_issues(ctx, &mut results.node_analyses, &mut results.edge_analyses);
     }
 }
 
+/// Run all analysis passes on the input IR
+pub fn run_analysis(ir: &HydroIr) -> AnalysisResult {
+    PassRegistry::with_builtin_passes()
+        .run(ir)
+        .expect("built-in passes have satisfiable dependencies")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -459,6 +819,7 @@ mod tests {
             edge_style_config: None,
             node_type_config: None,
             legend: None,
+            metadata: None,
         };
 
         let result = run_analysis(&ir);
@@ -511,6 +872,7 @@ mod tests {
             edge_style_config: None,
             node_type_config: None,
             legend: None,
+            metadata: None,
         };
 
         let result = run_analysis(&ir);
@@ -546,6 +908,233 @@ mod tests {
         assert!(!result.node_analyses.get("3").unwrap().issues.is_empty());
     }
 
+    #[test]
+    fn test_environment_nondeterminism_propagates_separately() {
+        use crate::builder::IrBuilder;
+        use crate::environment::EnvironmentModel;
+
+        // source_stream -> map -> for_each, and source_stream -> observenondet -> for_each
+        let mut b = IrBuilder::new();
+        let src = b.node("source_stream");
+        let map = b.node("map");
+        let nondet = b.node("observenondet");
+        let sink = b.node("for_each");
+        let racy_sink = b.node("for_each");
+        b.edge(&src, &map, &[]);
+        b.edge(&map, &sink, &[]);
+        b.edge(&src, &nondet, &[]);
+        b.edge(&nondet, &racy_sink, &[]);
+        let ir = b.build();
+
+        let result = run_analysis(&ir);
+        let node = |id: &str| &result.node_analyses[id];
+        assert_eq!(node(&src).nd_effect, "ExternalNonDet");
+        assert_eq!(node(&sink).nd_effect, "ExternalNonDet");
+        assert!(node(&sink).env_dependent && node(&sink).issues.is_empty());
+        // Local nondeterminism wins, but environment dependence is still recorded
+        assert_eq!(node(&racy_sink).nd_effect, "LocallyNonDet");
+        assert!(node(&racy_sink).env_dependent);
+        assert_eq!(node(&racy_sink).issues[0].kind, "NonDet");
+        assert!(!result.overall.deterministic);
+        assert!(!result.overall.deterministic_modulo_environment);
+        assert!(result.overall.environment_dependent);
+
+        // Environment taint alone is not deterministic, but is not a race either
+        let mut b = IrBuilder::new();
+        let src = b.node("source_stream");
+        let sink = b.node("for_each");
+        b.edge(&src, &sink, &[]);
+        let result = run_analysis(&b.build());
+        assert!(!result.overall.deterministic);
+        assert!(result.overall.deterministic_modulo_environment);
+
+        // Without an environment model, inputs are deterministic again
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.set_config(AnalysisConfig {
+            environment: EnvironmentModel::none(),
+            ..Default::default()
+        });
+        let result = registry.run(&ir).unwrap();
+        assert_eq!(result.node_analyses[&sink].nd_effect, "Deterministic");
+        assert!(!result.overall.environment_dependent);
+    }
+
+    #[test]
+    fn test_sampling_is_racy_by_default() {
+        use crate::builder::IrBuilder;
+        use crate::environment::EnvironmentModel;
+
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let sample = b.node("sample_every");
+        let sink = b.node("for_each");
+        b.edge(&src, &sample, &[]);
+        b.edge(&sample, &sink, &[]);
+        let ir = b.build();
+
+        let result = run_analysis(&ir);
+        let analysis = &result.node_analyses[&sample];
+        assert_eq!(analysis.nd_effect, "LocallyNonDet");
+        assert!(!analysis.env_dependent);
+        assert_eq!(analysis.issues[0].kind, "NonDet");
+        assert!(!result.overall.deterministic);
+        assert!(!result.overall.deterministic_modulo_environment);
+
+        // Opting in to sampling makes the sample depend on the environment instead
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.set_config(AnalysisConfig {
+            environment: EnvironmentModel {
+                sampling: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let result = registry.run(&ir).unwrap();
+        let analysis = &result.node_analyses[&sample];
+        assert_eq!(analysis.nd_effect, "ExternalNonDet");
+        assert!(analysis.issues.iter().all(|issue| issue.kind != "NonDet"));
+        assert!(!result.overall.deterministic);
+        assert!(result.overall.deterministic_modulo_environment);
+    }
+
+    #[test]
+    fn test_sealing_operators_stop_taint() {
+        use crate::builder::IrBuilder;
+
+        let mut b = IrBuilder::new();
+        // observenondet over a NoOrder batch -> fold_commutative_idempotent -> for_each
+        let racy = b.node("observenondet");
+        let aci = b.node("fold_commutative_idempotent");
+        let aci_sink = b.node("for_each");
+        b.edge(&racy, &aci, &[]);
+        b.edge(&aci, &aci_sink, &[]);
+        // observenondet -> fold merging an unbounded lattice stream -> for_each
+        let merge = b.node("fold");
+        let merge_sink = b.node("for_each");
+        b.typed_edge(&racy, &merge, &["Unbounded"], "SetUnion<u32>");
+        b.edge(&merge, &merge_sink, &[]);
+        // The same lattice stream folded without merging is not sealed
+        let insert = b.node("fold");
+        let insert_sink = b.node("for_each");
+        b.typed_edge(&racy, &insert, &["Unbounded"], "SetUnion<u32>");
+        b.edge(&insert, &insert_sink, &[]);
+        // network -> sort -> collect_vec seals arrival order only
+        let network = b.node("network");
+        let sort = b.node("sort");
+        let collect = b.node("collect_vec");
+        let sorted_sink = b.node("for_each");
+        b.edge(&network, &sort, &[]);
+        b.edge(&sort, &collect, &[]);
+        b.edge(&collect, &sorted_sink, &[]);
+        b.edge(&racy, &sort, &[]);
+        let batch = b.node("source_iter");
+        b.edge(&batch, &racy, &["Stream", "NoOrder", "Bounded"]);
+        let mut ir = b.build();
+        ir.nodes[3].full_label =
+            Some("fold(q!(| | SetUnionHashSet :: default ()), q!(Merge :: merge))".to_string());
+        ir.nodes[5].full_label = Some(
+            "fold(q!(| | SetUnionHashSet :: default ()), q!(| acc , x | { acc . insert (x) ; }))"
+                .to_string(),
+        );
+
+        let result = run_analysis(&ir);
+        let node = |id: &str| &result.node_analyses[id];
+        assert_eq!(
+            node(&aci).sealed.as_deref(),
+            Some("commutative_idempotent_fold")
+        );
+        assert_eq!(node(&aci_sink).nd_effect, ND_DETERMINISTIC);
+        assert_eq!(node(&merge).sealed.as_deref(), Some("lattice_merge"));
+        assert_eq!(node(&merge_sink).nd_effect, ND_DETERMINISTIC);
+        assert_eq!(node(&insert).sealed, None);
+        assert_eq!(node(&insert_sink).nd_effect, ND_LOCALLY_NONDET);
+        // Local taint through sort is not sealed, network order is
+        assert_eq!(node(&collect).sealed.as_deref(), Some("sorted_collect"));
+        assert_eq!(node(&sorted_sink).nd_effect, ND_LOCALLY_NONDET);
+        assert!(!node(&sorted_sink).env_dependent);
+        assert!(node(&sort).env_dependent);
+    }
+
+    #[test]
+    fn test_seals_pass_input_and_timing() {
+        use crate::builder::IrBuilder;
+
+        // source_stream, sample_every and a network all feed a CI fold
+        let mut b = IrBuilder::new();
+        let input = b.node("source_stream");
+        let ticks = b.node("source_iter");
+        let sample = b.node("sample_every");
+        let network = b.node("network");
+        let fold = b.node("fold_commutative_idempotent");
+        let sink = b.node("for_each");
+        b.edge(&input, &fold, &[]);
+        b.edge(&ticks, &sample, &[]);
+        b.edge(&sample, &fold, &[]);
+        b.edge(&network, &fold, &[]);
+        b.edge(&fold, &sink, &[]);
+        let ir = b.build();
+
+        // Only arrival order is sealed; the fold still depends on what was
+        // sent and on when the timer fired
+        let result = run_analysis(&ir);
+        let node = |id: &str| &result.node_analyses[id];
+        assert_eq!(
+            node(&fold).sealed.as_deref(),
+            Some("commutative_idempotent_fold")
+        );
+        assert_eq!(node(&sink).nd_kinds, vec!["timing", "input"]);
+        assert_eq!(node(&sink).nd_effect, ND_LOCALLY_NONDET);
+        assert!(node(&sink).env_dependent);
+    }
+
+    #[test]
+    fn test_nd_kinds_propagate_as_sets() {
+        use crate::builder::IrBuilder;
+
+        // network -> observenondet over a batched NoOrder stream -> sink,
+        // joined with a timer
+        let mut b = IrBuilder::new();
+        let network = b.node("network");
+        let batch = b.node("batch");
+        let nondet = b.node("observenondet");
+        let timer = b.node("source_interval");
+        let join = b.node("cross_singleton");
+        let sink = b.node("for_each");
+        b.edge(&network, &batch, &["Stream", "NoOrder", "Unbounded"]);
+        b.edge(&batch, &nondet, &["Stream", "NoOrder", "Bounded"]);
+        b.edge(&nondet, &join, &["Stream", "TotalOrder", "Bounded"]);
+        b.edge(&timer, &join, &["Singleton", "Unbounded"]);
+        b.edge(&join, &sink, &[]);
+        // A manual batch is a batching origin, one made by the networking
+        // layer is not
+        let src = b.node("source_iter");
+        let manual = b.node("batch");
+        let network_batch = b.node("batch");
+        b.edge(&src, &manual, &["Stream", "TotalOrder", "Unbounded"]);
+        b.edge(&src, &network_batch, &["Stream", "TotalOrder", "Unbounded"]);
+        b.set_backtrace(
+            &network_batch,
+            vec![crate::backtrace::Frame::new(
+                "hydro_lang/src/networking.rs",
+                "send",
+                10,
+            )],
+        );
+        let ir = b.build();
+
+        let result = run_analysis(&ir);
+        let kinds = |id: &str| result.node_analyses[id].nd_kinds.clone();
+        assert_eq!(kinds(&manual), vec!["batching"]);
+        assert_eq!(result.node_analyses[&manual].nd_effect, ND_LOCALLY_NONDET);
+        assert!(kinds(&network_batch).is_empty());
+        assert_eq!(kinds(&network), vec!["ordering"]);
+        assert_eq!(kinds(&nondet), vec!["ordering", "batching"]);
+        assert_eq!(kinds(&timer), vec!["timing"]);
+        assert_eq!(kinds(&sink), vec!["ordering", "batching", "timing"]);
+        assert_eq!(result.node_analyses[&sink].nd_effect, ND_LOCALLY_NONDET);
+        assert_eq!(kinds(&batch), vec!["ordering", "batching"]);
+    }
+
     #[test]
     fn test_calm_safe_network_edge() {
         // Create a graph with a Network edge that is CALM safe
@@ -567,6 +1156,7 @@ mod tests {
             edge_style_config: None,
             node_type_config: None,
             legend: None,
+            metadata: None,
         };
 
         let result = run_analysis(&ir);
@@ -579,6 +1169,163 @@ mod tests {
         assert!(result.overall.calm_safe);
     }
 
+    #[test]
+    fn test_parallel_edges_are_checked_separately() {
+        use crate::builder::IrBuilder;
+
+        // Both inputs of a cross product come from the same operator; only
+        // the first carries lattice values
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let cross = b.node("cross_product");
+        let network = b.node("network");
+        let first = b.port_edge(&src, &cross, &["Local"], "first");
+        let lattice = b.typed_edge(&src, &cross, &["Local"], "SetUnion<u32>");
+        let critical = b.typed_edge(&cross, &network, &["Network"], "SetUnion<u32>");
+        let ir = b.build();
+
+        let result = run_analysis(&ir);
+        assert_eq!(result.edge_analyses[&critical].calm, "CalmUnsafe");
+        assert!(
+            result.edge_analyses[&first]
+                .issues
+                .iter()
+                .any(|issue| issue.kind == "NonLattice")
+        );
+        assert!(result.edge_analyses[&lattice].issues.is_empty());
+        assert_eq!(
+            ir.edges[0].port(),
+            Some(crate::model::Port::First),
+            "port labels are parsed"
+        );
+        assert_eq!(ir.edges[1].port(), None);
+    }
+
+    #[test]
+    fn test_full_backtrace_is_opt_in() {
+        use crate::backtrace::Frame;
+        use crate::builder::IrBuilder;
+
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        b.set_backtrace(
+            &src,
+            vec![
+                Frame::new("/r/tokio-1.40.0/src/runtime/park.rs", "block_on", 285),
+                Frame::new("src/plumbing.rs", "plumb", 74),
+                Frame::new("examples/kvs.rs", "main", 12),
+            ],
+        );
+        let ir = b.build();
+
+        let result = run_analysis(&ir);
+        let analysis = &result.node_analyses[&src];
+        assert_eq!(
+            analysis.source_location.as_ref().unwrap().file,
+            "src/plumbing.rs"
+        );
+        assert!(analysis.backtrace.is_empty());
+
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.set_config(AnalysisConfig {
+            full_backtrace: true,
+            ..Default::default()
+        });
+        let result = registry.run(&ir).unwrap();
+        let files: Vec<&str> = result.node_analyses[&src]
+            .backtrace
+            .iter()
+            .map(|frame| frame.file.as_str())
+            .collect();
+        assert_eq!(files, vec!["src/plumbing.rs", "examples/kvs.rs"]);
+    }
+
+    #[test]
+    fn test_one_issue_per_root_cause() {
+        use crate::builder::IrBuilder;
+
+        // One sort feeding three network edges through a non-lattice edge
+        let mut b = IrBuilder::new();
+        let src = b.node("source_stream");
+        let sort = b.node("sort");
+        let fan_out = b.node("tee");
+        let mut critical = Vec::new();
+        b.edge(&src, &sort, &["Unbounded"]);
+        let shared = b.edge(&sort, &fan_out, &["Local"]);
+        for _ in 0..3 {
+            let network = b.node("network");
+            critical.push(b.edge(&fan_out, &network, &["Network"]));
+        }
+        let ir = b.build();
+        let result = run_analysis(&ir);
+
+        let issues: Vec<&Issue> = result.node_analyses[&sort]
+            .issues
+            .iter()
+            .filter(|issue| issue.kind == "NonMonotone")
+            .collect();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].subject, sort);
+        assert_eq!(issues[0].affected_critical_edges, critical);
+        assert_eq!(
+            issues[0].message,
+            format!(
+                "Node '{}' is non-monotone on CALM-critical paths to 3 edges",
+                sort
+            )
+        );
+
+        let issues = &result.edge_analyses[&shared].issues;
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, "NonLattice");
+        assert_eq!(issues[0].affected_critical_edges, critical);
+
+        // Issues outside CALM carry no affected edges and omit the field
+        let json = serde_json::to_value(&result.node_analyses[&sort].issues).unwrap();
+        assert!(json.as_array().unwrap().iter().all(|issue| {
+            issue.get("affected_critical_edges").is_some() == (issue["kind"] == "NonMonotone")
+        }));
+    }
+
+    #[test]
+    fn test_anti_join_is_non_monotone_only_in_its_negated_port() {
+        use crate::builder::IrBuilder;
+
+        // `neg_port: None` feeds the second input through an edge labelled
+        // with its type instead of a port
+        let non_monotone = |neg_tags: &[&str], neg_port: Option<&str>| {
+            let mut b = IrBuilder::new();
+            let pos = b.node("source_stream");
+            let neg = b.node("source_iter");
+            let anti_join = b.node("anti_join");
+            let network = b.node("network");
+            b.port_edge(&pos, &anti_join, &["Unbounded"], "pos");
+            match neg_port {
+                Some(port) => b.port_edge(&neg, &anti_join, neg_tags, port),
+                None => b.typed_edge(&neg, &anti_join, neg_tags, "SetUnion<u32>"),
+            };
+            b.edge(&anti_join, &network, &["Network"]);
+            let result = run_analysis(&b.build());
+            result.node_analyses[&anti_join]
+                .issues
+                .iter()
+                .filter(|issue| issue.kind == "NonMonotone")
+                .map(|issue| issue.message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // A growing stream into the negated port retracts earlier output
+        let issues = non_monotone(&["Unbounded"], Some("neg"));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("'neg' (edge 'e1')"), "{}", issues[0]);
+        assert_eq!(non_monotone(&["Unbounded"], Some("right")).len(), 1);
+        // A per-tick batch does not, and neither does growth of the positive input
+        assert!(non_monotone(&["Bounded"], Some("neg")).is_empty());
+        assert!(non_monotone(&["Bounded"], Some("right")).is_empty());
+        // Without port labels the whole operator counts
+        assert_eq!(non_monotone(&["Bounded"], None).len(), 1);
+    }
+
     // **Feature: hydro-static-analysis, Property 8: CALM Safety Path Verification**
     // **Validates: Requirements 5.2, 5.3**
     //
@@ -671,6 +1418,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -827,6 +1575,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -1005,6 +1754,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -1119,6 +1869,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -1281,6 +2032,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -1339,15 +2091,27 @@ mod tests {
                             node.node_type
                         );
 
-                        // Verify there's at least one CALM-unsafe edge that this node affects
-                        let has_calm_unsafe_edge = result.edge_analyses.values()
-                            .any(|edge_analysis| edge_analysis.calm == "CalmUnsafe");
-
+                        // Verify every edge the node affects is CALM-unsafe
                         prop_assert!(
-                            has_calm_unsafe_edge,
-                            "Node {} has a NonMonotone issue but no CALM-unsafe edges exist",
+                            !issue.affected_critical_edges.is_empty(),
+                            "Node {} has a NonMonotone issue but affects no edges",
                             node.id
                         );
+                        for affected in &issue.affected_critical_edges {
+                            prop_assert_eq!(
+                                &result.edge_analyses[affected].calm,
+                                "CalmUnsafe",
+                                "Node {} has a NonMonotone issue on CALM-safe edge {}",
+                                node.id,
+                                affected
+                            );
+                        }
+
+                        // One issue per root cause
+                        prop_assert_eq!(
+                            analysis.issues.iter().filter(|i| i.kind == "NonMonotone").count(),
+                            1
+                        );
                     }
                 }
             }
@@ -1366,15 +2130,21 @@ mod tests {
                             edge.id
                         );
 
-                        // Verify there's at least one CALM-unsafe edge
-                        let has_calm_unsafe_edge = result.edge_analyses.values()
-                            .any(|edge_analysis| edge_analysis.calm == "CalmUnsafe");
-
+                        // Verify every edge it affects is CALM-unsafe
                         prop_assert!(
-                            has_calm_unsafe_edge,
-                            "Edge {} has a NonLattice issue but no CALM-unsafe edges exist",
+                            !issue.affected_critical_edges.is_empty(),
+                            "Edge {} has a NonLattice issue but affects no edges",
                             edge.id
                         );
+                        for affected in &issue.affected_critical_edges {
+                            prop_assert_eq!(
+                                &result.edge_analyses[affected].calm,
+                                "CalmUnsafe",
+                                "Edge {} has a NonLattice issue on CALM-safe edge {}",
+                                edge.id,
+                                affected
+                            );
+                        }
                     }
                 }
             }
@@ -1440,6 +2210,7 @@ mod tests {
                 edge_style_config: None,
                 node_type_config: None,
                 legend: None,
+                metadata: None,
             };
 
             // Run analysis
@@ -1,13 +1,14 @@
 // Merge analysis results into annotated JSON output
 
-use crate::analysis::AnalysisResult;
-use crate::model::{AnnotatedEdge, AnnotatedHydroIr, AnnotatedNode, HydroIr};
+use crate::analysis::{AnalysisResult, ND_LOCALLY_NONDET};
+use crate::model::{
+    AnnotatedEdge, AnnotatedHydroIr, AnnotatedNode, Edge, EdgeAnalysis, HydroIr, Node,
+};
+use crate::semantics::NdEffect;
 use anyhow::Result;
 
 /// Merge analysis results into the original IR structure
 pub fn annotate(ir: &HydroIr, results: &AnalysisResult) -> AnnotatedHydroIr {
-    use crate::semantics::{NdEffect, get_node_semantics};
-
     // Convert nodes to annotated nodes with semantic tags
     let annotated_nodes: Vec<AnnotatedNode> = ir
         .nodes
@@ -15,31 +16,7 @@ pub fn annotate(ir: &HydroIr, results: &AnalysisResult) -> AnnotatedHydroIr {
         .map(|node| {
             let analysis = results.node_analyses.get(&node.id);
 
-            // Keep original node type but add semantic tags for styling
-            let mut semantic_tags = vec![node.node_type.clone()];
-
-            if let Some(analysis) = analysis {
-                let semantics = get_node_semantics(node);
-                let is_root_cause = semantics.nd != NdEffect::Deterministic;
-
-                if is_root_cause {
-                    // This node is a root cause of nondeterminism
-                    semantic_tags.push("NonDetRoot".to_string());
-                } else if analysis.nd_effect != "Deterministic" {
-                    // This node inherits nondeterminism
-                    semantic_tags.push("NonDetInherited".to_string());
-                } else {
-                    // Deterministic node
-                    semantic_tags.push("Deterministic".to_string());
-                }
-
-                // Add monotonicity tag
-                if analysis.monotone {
-                    semantic_tags.push("Monotone".to_string());
-                } else {
-                    semantic_tags.push("NonMonotone".to_string());
-                }
-            }
+            let semantic_tags = node_semantic_tags(node, results);
 
             AnnotatedNode {
                 id: node.id.clone(),
@@ -61,20 +38,7 @@ pub fn annotate(ir: &HydroIr, results: &AnalysisResult) -> AnnotatedHydroIr {
         .map(|edge| {
             let analysis = results.edge_analyses.get(&edge.id);
 
-            // Add analysis-based semantic tags
-            let mut enhanced_tags = edge.semantic_tags.clone().unwrap_or_default();
-
-            if let Some(analysis) = analysis {
-                // Add lattice/non-lattice tag
-                if analysis.is_lattice {
-                    enhanced_tags.push("Lattice".to_string());
-                } else {
-                    enhanced_tags.push("NonLattice".to_string());
-                }
-
-                // Add CALM status tag
-                enhanced_tags.push(analysis.calm.clone());
-            }
+            let enhanced_tags = edge_semantic_tags(edge, analysis);
 
             AnnotatedEdge {
                 id: edge.id.clone(),
@@ -97,30 +61,77 @@ pub fn annotate(ir: &HydroIr, results: &AnalysisResult) -> AnnotatedHydroIr {
         nodes: annotated_nodes,
         edges: annotated_edges,
         overall: Some(results.overall.clone()),
+        verdicts: results.verdicts.clone(),
+        convergence: results.convergence.clone(),
         hierarchy_choices: ir.hierarchy_choices.clone(),
         node_assignments: ir.node_assignments.clone(),
         selected_hierarchy: ir.selected_hierarchy.clone(),
         edge_style_config: Some(enhanced_edge_config),
         node_type_config: Some(enhanced_node_config),
         legend: ir.legend.clone(),
+        metadata: ir.metadata.clone(),
     }
 }
 
-/// Create enhanced node type config with analysis-specific semantic mappings
-fn create_enhanced_node_config(original: Option<&serde_json::Value>) -> serde_json::Value {
-    let mut config = original.cloned().unwrap_or_else(|| {
-        serde_json::json!({
-            "defaultType": "Transform",
-            "types": [],
-            "semanticMappings": {}
-        })
-    });
+/// Semantic tags for a node: its node type plus the analysis-derived styling
+/// tags that `node_analysis_mappings` assigns visual styles to
+pub fn node_semantic_tags(node: &Node, results: &AnalysisResult) -> Vec<String> {
+    // Keep original node type but add semantic tags for styling
+    let mut semantic_tags = vec![node.node_type.clone()];
+
+    if let Some(analysis) = results.node_analyses.get(&node.id) {
+        let semantics = results.semantics(node);
+
+        if semantics.nd == NdEffect::LocallyNonDet {
+            // This node is a root cause of nondeterminism
+            semantic_tags.push("NonDetRoot".to_string());
+        } else if analysis.nd_effect == ND_LOCALLY_NONDET {
+            // This node inherits nondeterminism
+            semantic_tags.push("NonDetInherited".to_string());
+        } else if semantics.nd == NdEffect::ExternalNonDet {
+            // This node observes the environment
+            semantic_tags.push("EnvNonDetRoot".to_string());
+        } else if analysis.env_dependent {
+            // This node depends on the environment, but is not racy
+            semantic_tags.push("EnvDependent".to_string());
+        } else {
+            // Deterministic node
+            semantic_tags.push("Deterministic".to_string());
+        }
 
-    // Don't override node type colors - let Hydroscope handle that based on nodeType
-    // We only add semantic mappings for analysis-specific styling
+        // Add monotonicity tag
+        if analysis.monotone {
+            semantic_tags.push("Monotone".to_string());
+        } else {
+            semantic_tags.push("NonMonotone".to_string());
+        }
+    }
 
-    // Add analysis-specific semantic mappings for node styling
-    let analysis_mappings = serde_json::json!({
+    semantic_tags
+}
+
+/// Semantic tags for an edge: its original tags plus lattice and CALM status
+pub fn edge_semantic_tags(edge: &Edge, analysis: Option<&EdgeAnalysis>) -> Vec<String> {
+    let mut enhanced_tags = edge.semantic_tags.clone().unwrap_or_default();
+
+    if let Some(analysis) = analysis {
+        // Add lattice/non-lattice tag
+        if analysis.is_lattice {
+            enhanced_tags.push("Lattice".to_string());
+        } else {
+            enhanced_tags.push("NonLattice".to_string());
+        }
+
+        // Add CALM status tag
+        enhanced_tags.push(analysis.calm.clone());
+    }
+
+    enhanced_tags
+}
+
+/// Analysis-specific semantic groups for node styling, keyed by group then tag
+pub fn node_analysis_mappings() -> serde_json::Value {
+    serde_json::json!({
         "NondeterminismGroup": {
             "NonDetRoot": {
                 "color-token": "warning",
@@ -130,6 +141,14 @@ fn create_enhanced_node_config(original: Option<&serde_json::Value>) -> serde_js
                 "color-token": "warning-light",
                 "border-style": "dashed"
             },
+            "EnvNonDetRoot": {
+                "color-token": "info",
+                "border-width": 3
+            },
+            "EnvDependent": {
+                "color-token": "info-light",
+                "border-style": "dashed"
+            },
             "Deterministic": {
                 "color-token": "default"
             }
@@ -142,8 +161,49 @@ fn create_enhanced_node_config(original: Option<&serde_json::Value>) -> serde_js
                 "badge": "⚠"
             }
         }
+    })
+}
+
+/// Analysis-specific semantic groups for edge styling, keyed by group then tag
+pub fn edge_analysis_mappings() -> serde_json::Value {
+    serde_json::json!({
+        "LatticeGroup": {
+            "Lattice": {
+                "color-token": "success"
+            },
+            "NonLattice": {
+                "color-token": "danger"
+            }
+        },
+        "CALMGroup": {
+            "CalmSafe": {
+                "line-pattern": "solid",
+                "line-width": 2
+            },
+            "CalmUnsafe": {
+                "line-pattern": "dashed",
+                "line-width": 3
+            }
+        }
+    })
+}
+
+/// Create enhanced node type config with analysis-specific semantic mappings
+fn create_enhanced_node_config(original: Option<&serde_json::Value>) -> serde_json::Value {
+    let mut config = original.cloned().unwrap_or_else(|| {
+        serde_json::json!({
+            "defaultType": "Transform",
+            "types": [],
+            "semanticMappings": {}
+        })
     });
 
+    // Don't override node type colors - let Hydroscope handle that based on nodeType
+    // We only add semantic mappings for analysis-specific styling
+
+    // Add analysis-specific semantic mappings for node styling
+    let analysis_mappings = node_analysis_mappings();
+
     if let Some(mappings) = config
         .get_mut("semanticMappings")
         .and_then(|m| m.as_object_mut())
@@ -168,26 +228,7 @@ fn create_enhanced_edge_config(original: Option<&serde_json::Value>) -> serde_js
         .unwrap_or_else(|| serde_json::json!({"semanticMappings": {}}));
 
     // Add analysis-specific semantic mappings with distinct visual styles
-    let analysis_mappings = serde_json::json!({
-        "LatticeGroup": {
-            "Lattice": {
-                "color-token": "success"
-            },
-            "NonLattice": {
-                "color-token": "danger"
-            }
-        },
-        "CALMGroup": {
-            "CalmSafe": {
-                "line-pattern": "solid",
-                "line-width": 2
-            },
-            "CalmUnsafe": {
-                "line-pattern": "dashed",
-                "line-width": 3
-            }
-        }
-    });
+    let analysis_mappings = edge_analysis_mappings();
 
     if let Some(mappings) = config
         .get_mut("semanticMappings")
@@ -228,12 +269,12 @@ mod tests {
         (
             prop::option::of(any::<usize>()),
             prop::option::of(prop::string::string_regex("[A-Za-z]+").unwrap()),
-            prop::collection::vec(any::<i32>(), 0..3),
+            model::tests::arb_backtrace(),
         )
-            .prop_map(|(location_id, location_type, backtrace_data)| NodeData {
+            .prop_map(|(location_id, location_type, backtrace)| NodeData {
                 location_id,
                 location_type,
-                backtrace: serde_json::json!(backtrace_data),
+                backtrace,
             })
     }
 
@@ -347,6 +388,7 @@ mod tests {
                     edge_style_config,
                     node_type_config,
                     legend,
+                    metadata: None,
                 },
             )
     }
@@ -552,6 +594,7 @@ mod tests {
             edge_style_config: None,
             node_type_config: None,
             legend: None,
+            metadata: None,
         };
 
         // Run analysis
@@ -604,6 +647,7 @@ mod tests {
             edge_style_config: Some(serde_json::json!({"default": "solid"})),
             node_type_config: Some(serde_json::json!({"defaultType": "Transform"})),
             legend: Some(serde_json::json!({"show": true})),
+            metadata: None,
         };
 
         // Run analysis
@@ -737,6 +781,7 @@ mod tests {
             edge_style_config: None,
             node_type_config: None,
             legend: None,
+            metadata: None,
         };
 
         // Run analysis
@@ -769,4 +814,30 @@ mod tests {
 
         assert_eq!(json_str, json_str2, "JSON serialization should be stable");
     }
+
+    /// Annotated output of the sample export, checked in as
+    /// `test-data/replicated_kvs_analyzed.json`. Run with `UPDATE_GOLDEN=1`
+    /// to rewrite it after an intended change to the output.
+    #[test]
+    fn test_golden_replicated_kvs() {
+        let dir = format!("{}/test-data", env!("CARGO_MANIFEST_DIR"));
+        let input = std::fs::read_to_string(format!("{}/replicated_kvs.json", dir)).unwrap();
+        let ir: HydroIr = serde_json::from_str(&input).unwrap();
+        let output = annotate_and_serialize(&ir, &run_analysis(&ir)).unwrap();
+
+        // Output only depends on the input
+        let again = annotate_and_serialize(&ir, &run_analysis(&ir)).unwrap();
+        assert_eq!(output, again);
+
+        let golden = format!("{}/replicated_kvs_analyzed.json", dir);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden, &output).unwrap();
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap();
+        assert!(
+            output == expected,
+            "annotated output differs from {}; rerun with UPDATE_GOLDEN=1 if intended",
+            golden
+        );
+    }
 }
@@ -0,0 +1,597 @@
+// Typed backtraces and frame filtering
+//
+// Hydro records the full construction-time backtrace for every operator,
+// most of which is framework or runtime code. `Backtrace` is the single API
+// for inspecting those frames; `FrameFilter` decides which frames belong to
+// the user's program and which one best identifies the operator in source.
+
+use crate::model::SourceLocation;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// One backtrace frame
+///
+/// Hydro emits each field under two spellings (`file`/`filename`,
+/// `fn`/`function`, `line`/`lineNumber`); either is accepted on input, and
+/// both are written on output so annotated JSON stays compatible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawFrame", into = "RawFrame")]
+pub struct Frame {
+    pub file: String,
+    pub function: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl Frame {
+    pub fn new(file: &str, function: &str, line: u32) -> Self {
+        Frame {
+            file: file.to_string(),
+            function: Some(function.to_string()),
+            line: Some(line),
+            column: None,
+        }
+    }
+}
+
+/// Wire format of a frame with every accepted spelling
+#[derive(Serialize, Deserialize)]
+struct RawFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(default, rename = "fn", skip_serializing_if = "Option::is_none")]
+    fn_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(
+        default,
+        rename = "lineNumber",
+        skip_serializing_if = "Option::is_none"
+    )]
+    line_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    column: Option<u32>,
+    #[serde(
+        default,
+        rename = "columnNumber",
+        skip_serializing_if = "Option::is_none"
+    )]
+    column_number: Option<u32>,
+}
+
+/// Merge two spellings of a field, rejecting conflicting values
+fn either<T: PartialEq + std::fmt::Debug>(
+    a: Option<T>,
+    a_name: &str,
+    b: Option<T>,
+    b_name: &str,
+) -> Result<Option<T>, String> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Err(format!(
+            "backtrace frame has conflicting `{}` ({:?}) and `{}` ({:?})",
+            a_name, a, b_name, b
+        )),
+        (a, b) => Ok(a.or(b)),
+    }
+}
+
+impl TryFrom<RawFrame> for Frame {
+    type Error = String;
+
+    fn try_from(raw: RawFrame) -> Result<Self, String> {
+        let file = either(raw.file, "file", raw.filename, "filename")?
+            .ok_or_else(|| "backtrace frame has no `file` or `filename`".to_string())?;
+        Ok(Frame {
+            file,
+            function: either(raw.fn_name, "fn", raw.function, "function")?,
+            line: either(raw.line, "line", raw.line_number, "lineNumber")?,
+            column: either(raw.column, "column", raw.column_number, "columnNumber")?,
+        })
+    }
+}
+
+impl From<Frame> for RawFrame {
+    fn from(frame: Frame) -> Self {
+        RawFrame {
+            file: Some(frame.file.clone()),
+            filename: Some(frame.file),
+            fn_name: frame.function.clone(),
+            function: frame.function,
+            line: frame.line,
+            line_number: frame.line,
+            column: frame.column,
+            column_number: frame.column,
+        }
+    }
+}
+
+/// One element of a backtrace as given in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BacktraceEntry {
+    Frame(Frame),
+    /// A value that is not a valid frame, kept as given
+    Invalid {
+        value: serde_json::Value,
+        error: String,
+    },
+}
+
+impl Serialize for BacktraceEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BacktraceEntry::Frame(frame) => frame.serialize(serializer),
+            BacktraceEntry::Invalid { value, .. } => value.serialize(serializer),
+        }
+    }
+}
+
+/// An operator's construction-time backtrace, innermost frame first
+///
+/// Parsing is lenient: entries that are not valid frames, and a backtrace
+/// that is not an array at all, are kept as given so the input round-trips
+/// and `validate` can point at them, but every query skips them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    entries: Vec<BacktraceEntry>,
+    /// The input value when it is neither an array nor null
+    malformed: Option<serde_json::Value>,
+}
+
+impl Backtrace {
+    /// The valid frames, innermost first
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.entries.iter().filter_map(|entry| match entry {
+            BacktraceEntry::Frame(frame) => Some(frame),
+            BacktraceEntry::Invalid { .. } => None,
+        })
+    }
+
+    /// Whether there are no valid frames
+    pub fn is_empty(&self) -> bool {
+        self.frames().next().is_none()
+    }
+
+    /// Problems found while parsing, with the index of the offending entry
+    /// (`None` if the backtrace itself is not an array)
+    pub fn problems(&self) -> Vec<(Option<usize>, String)> {
+        if self.malformed.is_some() {
+            return vec![(None, "backtrace is not an array of frames".to_string())];
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| match entry {
+                BacktraceEntry::Invalid { error, .. } => Some((Some(idx), error.clone())),
+                BacktraceEntry::Frame(_) => None,
+            })
+            .collect()
+    }
+
+    /// Whether any frame's file path contains `needle`
+    pub fn has_file_containing(&self, needle: &str) -> bool {
+        self.frames().any(|f| f.file.contains(needle))
+    }
+
+    /// Whether any frame's function name contains `needle`
+    pub fn has_function_containing(&self, needle: &str) -> bool {
+        self.frames().any(|f| {
+            f.function
+                .as_deref()
+                .is_some_and(|func| func.contains(needle))
+        })
+    }
+}
+
+impl From<Vec<Frame>> for Backtrace {
+    fn from(frames: Vec<Frame>) -> Self {
+        Backtrace {
+            entries: frames.into_iter().map(BacktraceEntry::Frame).collect(),
+            malformed: None,
+        }
+    }
+}
+
+impl Serialize for Backtrace {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.malformed {
+            Some(value) => value.serialize(serializer),
+            None => serializer.collect_seq(&self.entries),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Backtrace {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Null => Backtrace::default(),
+            serde_json::Value::Array(values) => Backtrace {
+                entries: values
+                    .into_iter()
+                    .map(|value| match serde_json::from_value(value.clone()) {
+                        Ok(frame) => BacktraceEntry::Frame(frame),
+                        Err(err) => BacktraceEntry::Invalid {
+                            value,
+                            error: err.to_string(),
+                        },
+                    })
+                    .collect(),
+                malformed: None,
+            },
+            other => Backtrace {
+                entries: Vec::new(),
+                malformed: Some(other),
+            },
+        })
+    }
+}
+
+/// Configurable rules for picking user frames out of a backtrace
+///
+/// Patterns are globs over the (mapped) file path where `*` matches any
+/// sequence of characters, including `/` (so `**` is the same as `*`), and
+/// `?` matches one character.
+/// A frame is kept if it matches an `include` pattern, or otherwise if it
+/// matches no `exclude` pattern.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrameFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Path prefix rewrites applied before matching, e.g. mapping an
+    /// absolute checkout path to a path relative to the repository.
+    /// The longest matching prefix wins.
+    pub crate_roots: BTreeMap<String, String>,
+    /// Prefer kept frames under this (mapped) path over earlier kept frames
+    pub prefer: Option<String>,
+}
+
+impl Default for FrameFilter {
+    /// Skips Hydro, DFIR and stageleft internals and the tokio runtime
+    ///
+    /// Exports give crate-relative paths, so Hydro's and tokio's own modules
+    /// are also matched by their exact paths within their crates.
+    fn default() -> Self {
+        FrameFilter {
+            include: Vec::new(),
+            exclude: [
+                "**hydro_lang**",
+                "**dfir_**",
+                "**stageleft**",
+                "src/location/**",
+                "src/compile/**",
+                "src/live_collections/**",
+                "**/tokio*/**",
+                "src/runtime/park.rs",
+                "src/runtime/runtime.rs",
+                "src/runtime/context/**",
+                "src/runtime/scheduler/**",
+            ]
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+            crate_roots: BTreeMap::new(),
+            prefer: None,
+        }
+    }
+}
+
+/// Match `text` against a glob where `*` matches any sequence within one
+/// path component, `**` any sequence across components and `?` any one
+/// character other than `/`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no directory at all
+            (rest.first() == Some(&'/') && glob_match_chars(&rest[1..], text))
+                || (0..=text.len()).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            let component = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=component).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => {
+            text.first().is_some_and(|&c| c != '/') && glob_match_chars(rest, &text[1..])
+        }
+        [c, rest @ ..] => text.first() == Some(c) && glob_match_chars(rest, &text[1..]),
+    }
+}
+
+impl FrameFilter {
+    /// Rewrite a file path using the longest matching crate root
+    pub fn map_path(&self, file: &str) -> String {
+        self.crate_roots
+            .iter()
+            .filter(|(prefix, _)| file.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, root)| format!("{}{}", root, &file[prefix.len()..]))
+            .unwrap_or_else(|| file.to_string())
+    }
+
+    /// Whether a (mapped) file path belongs to user code
+    pub fn is_user_frame(&self, file: &str) -> bool {
+        self.include.iter().any(|p| glob_match(p, file))
+            || !self.exclude.iter().any(|p| glob_match(p, file))
+    }
+
+    /// All frames with a line number, mapped, in backtrace order
+    fn frames(&self, backtrace: &Backtrace) -> Vec<SourceLocation> {
+        backtrace
+            .frames()
+            .filter_map(|frame| {
+                Some(SourceLocation {
+                    file: self.map_path(&frame.file),
+                    line: frame.line?,
+                    function: frame.function.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The user frames of a backtrace, innermost first
+    pub fn filter(&self, backtrace: &Backtrace) -> Vec<SourceLocation> {
+        self.frames(backtrace)
+            .into_iter()
+            .filter(|frame| self.is_user_frame(&frame.file))
+            .collect()
+    }
+
+    /// The frame that best identifies an operator: the first user frame
+    /// under `prefer`, else the first user frame, else the first frame
+    pub fn select(&self, backtrace: &Backtrace) -> Option<SourceLocation> {
+        let user = self.filter(backtrace);
+        if let Some(prefer) = &self.prefer
+            && let Some(frame) = user.iter().find(|f| f.file.starts_with(prefer.as_str()))
+        {
+            return Some(frame.clone());
+        }
+        user.into_iter()
+            .next()
+            .or_else(|| self.frames(backtrace).into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backtrace() -> Backtrace {
+        serde_json::from_value(serde_json::json!([
+            {"file": "src/location/mod.rs", "fn": "forward_ref", "line": 819},
+            {"file": "src/runtime/park.rs", "fn": "block_on", "line": 285},
+            {"file": "src/plumbing.rs", "fn": "plumb_kvs_dataflow", "line": 74},
+            {"filename": "examples/replicated.rs", "function": "main", "lineNumber": 39},
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_frame_spellings() {
+        let frames = backtrace();
+        let frames: Vec<&Frame> = frames.frames().collect();
+        assert_eq!(
+            frames[0],
+            &Frame::new("src/location/mod.rs", "forward_ref", 819)
+        );
+        assert_eq!(frames[3], &Frame::new("examples/replicated.rs", "main", 39));
+
+        // Hydro's own format repeats every field under both spellings
+        let both = serde_json::json!({
+            "file": "src/a.rs", "filename": "src/a.rs",
+            "fn": "f", "function": "f",
+            "line": 3, "lineNumber": 3
+        });
+        let frame: Frame = serde_json::from_value(both.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&frame).unwrap(), both);
+
+        let column: Frame =
+            serde_json::from_value(serde_json::json!({"file": "src/a.rs", "columnNumber": 7}))
+                .unwrap();
+        assert_eq!((column.line, column.column), (None, Some(7)));
+    }
+
+    #[test]
+    fn test_malformed_frames_are_rejected() {
+        let cases = [
+            (serde_json::json!({"fn": "f", "line": 1}), "no `file`"),
+            (
+                serde_json::json!({"file": "a.rs", "line": 1, "lineNumber": 2}),
+                "conflicting `line` (1) and `lineNumber` (2)",
+            ),
+            (
+                serde_json::json!({"file": "a.rs", "filename": "b.rs"}),
+                "conflicting `file`",
+            ),
+            (
+                serde_json::json!({"file": "a.rs", "line": "12"}),
+                "invalid type",
+            ),
+            (serde_json::json!(42), "invalid type"),
+        ];
+        for (value, expected) in cases {
+            let err = serde_json::from_value::<Frame>(value.clone())
+                .unwrap_err()
+                .to_string();
+            assert!(
+                err.contains(expected),
+                "{} should mention {:?}, got {:?}",
+                value,
+                expected,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_frames_are_kept() {
+        let input = serde_json::json!([
+            {"fn": "f", "line": 1},
+            {"file": "src/a.rs", "fn": "g", "line": 2},
+            42,
+        ]);
+        let backtrace: Backtrace = serde_json::from_value(input.clone()).unwrap();
+        let files: Vec<&str> = backtrace.frames().map(|f| f.file.as_str()).collect();
+        assert_eq!(files, vec!["src/a.rs"]);
+        let problems = backtrace.problems();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].0, Some(0));
+        assert!(problems[0].1.contains("no `file`"), "{}", problems[0].1);
+        assert_eq!(problems[1].0, Some(2));
+
+        // Invalid entries are written back as given, valid ones in both spellings
+        let output = serde_json::to_value(&backtrace).unwrap();
+        assert_eq!(output[0], input[0]);
+        assert_eq!(output[1]["filename"], "src/a.rs");
+        assert_eq!(output[2], input[2]);
+
+        // So is a backtrace that is not an array
+        let backtrace: Backtrace = serde_json::from_value(serde_json::json!("elided")).unwrap();
+        assert!(backtrace.is_empty());
+        assert_eq!(backtrace.problems()[0].0, None);
+        assert_eq!(
+            serde_json::to_value(&backtrace).unwrap(),
+            serde_json::json!("elided")
+        );
+        assert!(
+            serde_json::from_value::<Backtrace>(serde_json::Value::Null)
+                .unwrap()
+                .problems()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_backtrace_queries() {
+        let frames = backtrace();
+        assert!(frames.has_file_containing("location/mod.rs"));
+        assert!(!frames.has_file_containing("networking.rs"));
+        assert!(frames.has_function_containing("plumb_"));
+        assert!(!Backtrace::default().has_function_containing(""));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("**hydro_lang**", "/home/u/hydro_lang/src/x.rs"));
+        assert!(!glob_match("*hydro_lang*", "/home/u/hydro_lang/src/x.rs"));
+        assert!(glob_match("src/location/*", "src/location/mod.rs"));
+        assert!(!glob_match("src/location/*", "src/location/cluster/mod.rs"));
+        assert!(glob_match("src/location/**", "src/location/cluster/mod.rs"));
+        assert!(!glob_match("src/*location/*", "src/a/location/b.rs"));
+        assert!(glob_match("**/tokio*/**", "tokio-1.40.0/src/park.rs"));
+        assert!(glob_match("src/?.rs", "src/a.rs"));
+        assert!(!glob_match("src/?.rs", "src/ab.rs"));
+        assert!(!glob_match("src?a.rs", "src/a.rs"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn test_default_filter() {
+        let filter = FrameFilter::default();
+        let selected = filter.select(&backtrace()).unwrap();
+        // Neither Hydro's nor tokio's runtime frames count as user code
+        assert_eq!(
+            (selected.file.as_str(), selected.line),
+            ("src/plumbing.rs", 74)
+        );
+        assert_eq!(filter.filter(&backtrace()).len(), 2);
+        assert!(!filter.is_user_frame("/home/u/.cargo/registry/src/tokio-1.40.0/src/park.rs"));
+        assert!(!filter.is_user_frame("src/location/tick.rs"));
+        assert!(!filter.is_user_frame("src/runtime/scheduler/multi_thread/mod.rs"));
+        // User modules whose paths merely resemble Hydro's or tokio's are kept
+        assert!(filter.is_user_frame("src/allocation/mod.rs"));
+        assert!(filter.is_user_frame("src/runtime/handlers.rs"));
+
+        let user: Backtrace = serde_json::from_value(serde_json::json!([
+            {"file": "src/location/mod.rs", "fn": "source_iter", "line": 163},
+            {"file": "src/allocation/mod.rs", "fn": "allocate", "line": 12},
+            {"file": "src/plumbing.rs", "fn": "plumb_kvs_dataflow", "line": 74},
+        ]))
+        .unwrap();
+        assert_eq!(filter.select(&user).unwrap().file, "src/allocation/mod.rs");
+    }
+
+    #[test]
+    fn test_include_overrides_exclude() {
+        let filter = FrameFilter {
+            include: vec!["src/location/*".to_string()],
+            ..FrameFilter::default()
+        };
+        let selected = filter.select(&backtrace()).unwrap();
+        assert_eq!(
+            (selected.file.as_str(), selected.line),
+            ("src/location/mod.rs", 819)
+        );
+    }
+
+    #[test]
+    fn test_exclude_and_prefer() {
+        let mut filter = FrameFilter::default();
+        filter.exclude.push("src/plumbing.rs".to_string());
+        let selected = filter.select(&backtrace()).unwrap();
+        assert_eq!(selected.file, "examples/replicated.rs");
+
+        filter.exclude.pop();
+        let selected = filter.select(&backtrace()).unwrap();
+        assert_eq!(selected.file, "src/plumbing.rs");
+        assert_eq!(selected.function.as_deref(), Some("plumb_kvs_dataflow"));
+
+        filter.prefer = Some("examples/".to_string());
+        let selected = filter.select(&backtrace()).unwrap();
+        assert_eq!(
+            (selected.file.as_str(), selected.line),
+            ("examples/replicated.rs", 39)
+        );
+        assert_eq!(selected.function.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_crate_roots() {
+        let filter = FrameFilter {
+            crate_roots: BTreeMap::from([
+                ("src/".to_string(), "kvs_zoo/src/".to_string()),
+                ("src/runtime/".to_string(), "tokio/src/runtime/".to_string()),
+            ]),
+            exclude: vec!["tokio/**".to_string()],
+            ..FrameFilter::default()
+        };
+        let files: Vec<String> = filter
+            .filter(&backtrace())
+            .into_iter()
+            .map(|f| f.file)
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "kvs_zoo/src/location/mod.rs",
+                "kvs_zoo/src/plumbing.rs",
+                "examples/replicated.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_first_frame() {
+        let filter = FrameFilter {
+            exclude: vec!["**".to_string()],
+            ..FrameFilter::default()
+        };
+        assert!(filter.filter(&backtrace()).is_empty());
+        assert_eq!(
+            filter.select(&backtrace()).unwrap().file,
+            "src/location/mod.rs"
+        );
+        assert!(filter.select(&Backtrace::default()).is_none());
+    }
+}
@@ -1,40 +1,417 @@
 // CLI entry point for Hydrolysis static analysis tool
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use hydrolysis::cache::AnalysisCache;
+use hydrolysis::config::AnalysisConfig;
+use hydrolysis::facts::FactFormat;
+use hydrolysis::pass::PassRegistry;
+use hydrolysis::render::RenderFormat;
+use hydrolysis::rules::{RulePass, RuleSet};
+use hydrolysis::validate::{Diagnostic, Severity};
 use std::env;
 use std::fs;
+use std::path::Path;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// What to write to the output file
+#[derive(Default, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// Annotated JSON for Hydroscope
+    #[default]
+    Json,
+    Graph(RenderFormat),
+    Html,
+}
+
+/// Options that set up the pass registry, shared by analysis and `export`
+#[derive(Default)]
+struct RegistryOptions {
+    disabled_passes: Vec<String>,
+    rule_files: Vec<String>,
+    config_path: Option<String>,
+    profile: Option<String>,
+}
+
+impl RegistryOptions {
+    /// Consume `arg` and its value if it is a registry option
+    fn parse<'a>(
+        &mut self,
+        arg: &str,
+        iter: &mut impl Iterator<Item = &'a String>,
+    ) -> Result<bool> {
+        match arg {
+            "--disable-pass" => {
+                let name = iter.next().context("--disable-pass requires a pass name")?;
+                self.disabled_passes.push(name.clone());
+            }
+            "--config" => {
+                let path = iter.next().context("--config requires a file path")?;
+                self.config_path = Some(path.clone());
+            }
+            "--profile" => {
+                let name = iter.next().context("--profile requires a profile name")?;
+                self.profile = Some(name.clone());
+            }
+            "--rules" => {
+                let path = iter.next().context("--rules requires a file path")?;
+                self.rule_files.push(path.clone());
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Built-in passes with the configured settings, profile and lint rules
+    /// registered; passes are not disabled yet so they can still be listed
+    fn registry(&self) -> Result<PassRegistry> {
+        let mut registry = PassRegistry::with_builtin_passes();
+        if let Some(path) = &self.config_path {
+            registry.set_config(AnalysisConfig::load(Path::new(path))?);
+        }
+        if let Some(name) = &self.profile {
+            let mut config = registry.config().clone();
+            config.profile = Some(name.clone());
+            registry.set_config(config);
+        }
+        if !self.rule_files.is_empty() {
+            let rules = RuleSet::load_files(&self.rule_files)?;
+            registry.register(Box::new(RulePass::new(rules)));
+        }
+        Ok(registry)
+    }
+
+    fn disable_passes(&self, registry: &mut PassRegistry) -> Result<()> {
+        for name in &self.disabled_passes {
+            registry.disable(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parsed command-line options
+#[derive(Default)]
+struct Options {
+    input_path: String,
+    output_path: String,
+    registry: RegistryOptions,
+    cache_path: Option<String>,
+    format: OutputFormat,
+    issue: Option<String>,
+    source_root: String,
+    list_passes: bool,
+    strict: bool,
+}
+
+fn usage(program: &str) -> String {
+    format!(
+        "Usage: {program} [options] <input.json> <output.json>\n\
+         \x20      {program} export [--format souffle|csv] [--config <file>] [--profile <name>]\n\
+         \x20             [--rules <file>] [--disable-pass <name>] [--strict]\n\
+         \x20             <input.json> <outdir>\n\
+         \x20      {program} validate [--strict] <input.json>\n\
+         \n\
+         Options:\n\
+         \x20 --disable-pass <name>  Skip an analysis pass (repeatable)\n\
+         \x20 --config <file>        Load analysis settings (e.g. frame filters) from JSON\n\
+         \x20 --cache <file>         Reuse CALM results for unchanged parts of the graph\n\
+         \x20                        across runs, and update the file\n\
+         \x20 --profile <name>       Operator semantics profile: compat, hydro_lang-0.13 or\n\
+         \x20                        hydro_lang-0.14 (default: detected from the input)\n\
+         \x20 --rules <file>         Load lint rules from a file (repeatable)\n\
+         \x20 --format <format>      Output format: json (default), dot, mermaid or html\n\
+         \x20 --issue <id>           With dot/mermaid, render only the subgraph around\n\
+         \x20                        the issues on this node or edge\n\
+         \x20 --source-root <prefix> With html, prefix for source links (e.g. ../)\n\
+         \x20 --strict               Abort if input validation reports any problem;\n\
+         \x20                        without it only errors abort\n\
+         \x20 --list-passes          List available analysis passes and exit"
+    )
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if options.registry.parse(arg, &mut iter)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--cache" => {
+                let path = iter.next().context("--cache requires a file path")?;
+                options.cache_path = Some(path.clone());
+            }
+            "--format" => {
+                let format = iter
+                    .next()
+                    .context("--format requires json, dot, mermaid or html")?;
+                options.format = match format.as_str() {
+                    "json" => OutputFormat::Json,
+                    "html" => OutputFormat::Html,
+                    other => OutputFormat::Graph(other.parse()?),
+                };
+            }
+            "--issue" => {
+                let id = iter.next().context("--issue requires a node or edge ID")?;
+                options.issue = Some(id.clone());
+            }
+            "--source-root" => {
+                let prefix = iter
+                    .next()
+                    .context("--source-root requires a path prefix")?;
+                options.source_root = prefix.clone();
+            }
+            "--list-passes" => options.list_passes = true,
+            "--strict" => options.strict = true,
+            flag if flag.starts_with("--") => bail!("Unknown option: {}", flag),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if options.issue.is_some() && !matches!(options.format, OutputFormat::Graph(_)) {
+        bail!("--issue requires --format dot or --format mermaid");
+    }
+
+    if !options.list_passes {
+        match positional.as_slice() {
+            [input, output] => {
+                options.input_path = input.clone();
+                options.output_path = output.clone();
+            }
+            _ => bail!("Expected an input and an output path"),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parsed options for the `export` subcommand
+struct ExportOptions {
+    format: FactFormat,
+    registry: RegistryOptions,
+    strict: bool,
+    input_path: String,
+    output_dir: String,
+}
+
+fn parse_export_args(args: &[String]) -> Result<ExportOptions> {
+    let mut format = FactFormat::Souffle;
+    let mut registry = RegistryOptions::default();
+    let mut strict = false;
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if registry.parse(arg, &mut iter)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--format" => {
+                format = iter
+                    .next()
+                    .context("--format requires souffle or csv")?
+                    .parse()?;
+            }
+            "--strict" => strict = true,
+            flag if flag.starts_with("--") => bail!("Unknown option: {}", flag),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    match positional.as_slice() {
+        [input, output] => Ok(ExportOptions {
+            format,
+            registry,
+            strict,
+            input_path: input.clone(),
+            output_dir: output.clone(),
+        }),
+        _ => bail!("Expected an input path and an output directory"),
+    }
+}
+
+/// Parsed options for the `validate` subcommand
+struct ValidateOptions {
+    strict: bool,
+    input_path: String,
+}
+
+fn parse_validate_args(args: &[String]) -> Result<ValidateOptions> {
+    let mut strict = false;
+    let mut positional = Vec::new();
 
-    if args.len() != 3 {
-        eprintln!("Usage: {} <input.json> <output.json>", args[0]);
-        std::process::exit(1);
+    for arg in args {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            flag if flag.starts_with("--") => bail!("Unknown option: {}", flag),
+            _ => positional.push(arg.clone()),
+        }
     }
 
-    let input_path = &args[1];
-    let output_path = &args[2];
+    match positional.as_slice() {
+        [input] => Ok(ValidateOptions {
+            strict,
+            input_path: input.clone(),
+        }),
+        _ => bail!("Expected an input path"),
+    }
+}
 
-    // Read input JSON
+fn read_ir(input_path: &str) -> Result<hydrolysis::model::HydroIr> {
     let input_json = fs::read_to_string(input_path)
         .with_context(|| format!("Failed to read input file: {}", input_path))?;
+    serde_json::from_str(&input_json).with_context(|| "Failed to parse input JSON")
+}
+
+/// Write the graph and analysis results as Datalog facts, derived with the
+/// same settings an analysis run with these options would use
+fn export(options: &ExportOptions) -> Result<()> {
+    let mut registry = options.registry.registry()?;
+    options.registry.disable_passes(&mut registry)?;
+    let ir = read_ir(&options.input_path)?;
+    check_input(&ir, options.strict)?;
+    let results = registry.run(&ir)?;
+    let facts = hydrolysis::facts::extract_facts(&ir, &results);
+
+    let written =
+        hydrolysis::facts::write_facts(&facts, options.format, Path::new(&options.output_dir))?;
+    println!("Wrote {} files to {}", written.len(), options.output_dir);
+    Ok(())
+}
+
+/// Fail if any diagnostic is an error or, with `strict`, if there are any
+fn check_diagnostics(diagnostics: &[Diagnostic], strict: bool) -> Result<()> {
+    let fatal = diagnostics
+        .iter()
+        .filter(|d| strict || d.severity == Severity::Error)
+        .count();
+    if fatal > 0 {
+        bail!("Input validation failed with {} problem(s)", fatal);
+    }
+    Ok(())
+}
+
+/// Validate the input before analyzing it, failing exactly when `validate`
+/// would. Only the diagnostics that make it fail are printed (to stderr);
+/// `hydrolysis validate` lists the warnings.
+fn check_input(ir: &hydrolysis::model::HydroIr, strict: bool) -> Result<()> {
+    let diagnostics = hydrolysis::validate::validate(ir);
+    for diagnostic in &diagnostics {
+        if strict || diagnostic.severity == Severity::Error {
+            eprintln!("{}", diagnostic);
+        }
+    }
+    check_diagnostics(&diagnostics, strict)
+}
+
+/// Report structural problems in the input without analyzing it
+fn validate(options: &ValidateOptions) -> Result<()> {
+    let ir = read_ir(&options.input_path)?;
+    let diagnostics = hydrolysis::validate::validate(&ir);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    check_diagnostics(&diagnostics, options.strict)?;
+    if diagnostics.is_empty() {
+        println!("{}: no problems found", options.input_path);
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(String::as_str) == Some("export") {
+        let options = match parse_export_args(&args[2..]) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, usage(&args[0]));
+                std::process::exit(1);
+            }
+        };
+        return export(&options);
+    }
+
+    if args.get(1).map(String::as_str) == Some("validate") {
+        let options = match parse_validate_args(&args[2..]) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, usage(&args[0]));
+                std::process::exit(1);
+            }
+        };
+        return validate(&options);
+    }
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, usage(&args[0]));
+            std::process::exit(1);
+        }
+    };
+
+    let mut registry = options.registry.registry()?;
+
+    if options.list_passes {
+        for name in registry.pass_names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    options.registry.disable_passes(&mut registry)?;
+
+    let input_path = &options.input_path;
+    let output_path = &options.output_path;
 
-    // Parse Hydro IR
-    let ir: hydrolysis::model::HydroIr =
-        serde_json::from_str(&input_json).with_context(|| "Failed to parse input JSON")?;
+    // Read and parse Hydro IR
+    let ir = read_ir(input_path)?;
+    check_input(&ir, options.strict)?;
 
-    // Run analysis
-    let results = hydrolysis::analysis::run_analysis(&ir);
+    // Run analysis, incrementally when a cache is given
+    let results = match &options.cache_path {
+        Some(path) => {
+            let path = Path::new(path);
+            let previous = AnalysisCache::load(path)?;
+            let (results, cache) = registry.run_incremental(&ir, &previous)?;
+            eprintln!(
+                "Reused {} of {} CALM results from {}",
+                cache.reused_from(&previous),
+                cache.len(),
+                path.display()
+            );
+            cache.save(path)?;
+            results
+        }
+        None => registry.run(&ir)?,
+    };
 
     // Generate and print report
     let report = hydrolysis::report::generate_report(&ir, &results);
     println!("{}", report);
 
-    // Annotate and serialize output
-    let output_json = hydrolysis::annotate::annotate_and_serialize(&ir, &results)?;
+    // Annotate and serialize output, or render it as a graph
+    let output = match options.format {
+        OutputFormat::Graph(format) => {
+            let focus = match &options.issue {
+                Some(id) => Some(hydrolysis::render::issue_focus(&ir, &results, id)?),
+                None => None,
+            };
+            hydrolysis::render::render(&ir, &results, format, focus.as_ref())
+        }
+        OutputFormat::Html => hydrolysis::html::generate_html_report(
+            &ir,
+            &results,
+            &hydrolysis::html::HtmlOptions {
+                source_root: options.source_root.clone(),
+            },
+        ),
+        OutputFormat::Json => hydrolysis::annotate::annotate_and_serialize(&ir, &results)?,
+    };
 
     // Write output
-    fs::write(output_path, output_json)
+    fs::write(output_path, output)
         .with_context(|| format!("Failed to write output file: {}", output_path))?;
 
     Ok(())
@@ -0,0 +1,304 @@
+// Boundedness of collections
+//
+// Edge tags say whether a collection is `Bounded` (finite, e.g. one tick's
+// batch) or `Unbounded` (grows forever). The `boundedness` pass fills in the
+// kind of untagged edges from the operators that produce them, checks the
+// tags against operator semantics, and flags blocking operators that consume
+// an unbounded stream: those wait for input that never ends and so never
+// emit, a liveness bug that the ND and CALM passes cannot see.
+
+use crate::analysis::AnalysisResult;
+use crate::model::{Edge, Issue, Node};
+use crate::pass::{AnalysisPass, PassContext};
+use crate::semantics::{Monotonicity, is_lattice_type, is_merge_combinator};
+
+const BOUNDED: &str = "Bounded";
+const UNBOUNDED: &str = "Unbounded";
+
+/// The boundedness tag on an edge, if it has one
+fn tagged(edge: &Edge) -> Option<&'static str> {
+    let tags = edge.semantic_tags.as_deref().unwrap_or_default();
+    if tags.iter().any(|tag| tag == BOUNDED) {
+        Some(BOUNDED)
+    } else if tags.iter().any(|tag| tag == UNBOUNDED) {
+        Some(UNBOUNDED)
+    } else {
+        None
+    }
+}
+
+/// The kind an operator always produces, whatever its input
+fn produces(canonical: Option<&str>) -> Option<&'static str> {
+    match canonical? {
+        "batch" | "batch_atomic" => Some(BOUNDED),
+        "all_ticks" | "all_ticks_atomic" | "persist" => Some(UNBOUNDED),
+        _ => None,
+    }
+}
+
+/// Boundedness of collection kinds as a registered pass
+pub struct BoundednessPass;
+
+impl BoundednessPass {
+    /// Boundedness of every edge: the kind its source always produces, its
+    /// tag, or else the kind its source produces given the kinds of its
+    /// inputs, iterated to a fixpoint so cycles resolve. Edges whose kind
+    /// can't be determined are None.
+    fn infer(ctx: &PassContext<'_>, canonical: &[Option<String>]) -> Vec<Option<&'static str>> {
+        let ir = ctx.ir;
+        let edge_idx = |id: &str| ctx.edge_index(id);
+        // Operators whose output kind is fixed override the tag
+        let mut kinds: Vec<Option<&'static str>> = ir
+            .edges
+            .iter()
+            .map(|edge| {
+                let source = ctx.graph.get_idx(&edge.source);
+                source
+                    .and_then(|idx| produces(canonical[idx].as_deref()))
+                    .or_else(|| tagged(edge))
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (idx, edge) in ir.edges.iter().enumerate() {
+                if kinds[idx].is_some() {
+                    continue;
+                }
+                let Some(source) = ctx.graph.get_idx(&edge.source) else {
+                    continue;
+                };
+                let inputs: Vec<Option<&'static str>> = ctx.graph.backward[source]
+                    .iter()
+                    .filter_map(|(_, id)| edge_idx(id).map(|i| kinds[i]))
+                    .collect();
+                // Sources produce unbounded streams
+                let inferred = if inputs.is_empty() || inputs.contains(&Some(UNBOUNDED)) {
+                    Some(UNBOUNDED)
+                } else if inputs.iter().all(|kind| *kind == Some(BOUNDED)) {
+                    Some(BOUNDED)
+                } else {
+                    None
+                };
+                if inferred.is_some() {
+                    kinds[idx] = inferred;
+                    changed = true;
+                }
+            }
+        }
+        kinds
+    }
+
+    /// Problems with one node given the kinds of its input and output edges
+    fn check(
+        ctx: &PassContext<'_>,
+        node: &Node,
+        canonical: Option<&str>,
+        inputs: &[(&Edge, Option<&'static str>)],
+        outputs: &[(&Edge, Option<&'static str>)],
+    ) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut issue = |kind: &str, message: String| {
+            issues.push(Issue {
+                kind: kind.to_string(),
+                subject: node.id.clone(),
+                message,
+                affected_critical_edges: Vec::new(),
+            })
+        };
+        let unbounded_input = inputs.iter().find(|(_, kind)| *kind == Some(UNBOUNDED));
+
+        match canonical {
+            Some("batch" | "batch_atomic") => {
+                for (edge, _) in outputs.iter().filter(|(e, _)| tagged(e) == Some(UNBOUNDED)) {
+                    issue(
+                        "BoundednessMismatch",
+                        format!(
+                            "Node '{}' (batch) should produce a Bounded collection, but edge '{}' is tagged Unbounded",
+                            node.id, edge.id
+                        ),
+                    );
+                }
+            }
+            Some("all_ticks" | "all_ticks_atomic") => {
+                for (edge, _) in outputs.iter().filter(|(e, _)| tagged(e) == Some(BOUNDED)) {
+                    issue(
+                        "BoundednessMismatch",
+                        format!(
+                            "Node '{}' (all_ticks) should produce an Unbounded collection, but edge '{}' is tagged Bounded",
+                            node.id, edge.id
+                        ),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        let Some((input, _)) = unbounded_input else {
+            return issues;
+        };
+        let emits_once = outputs.iter().any(|(_, kind)| *kind == Some(BOUNDED));
+        let aggregation =
+            canonical.is_some_and(|name| name.starts_with("fold") || name.starts_with("reduce"));
+        let blocking = match canonical {
+            Some("sort" | "collect_vec") => true,
+            _ => aggregation && emits_once,
+        };
+        if blocking {
+            issue(
+                "NeverEmits",
+                format!(
+                    "Node '{}' ({}) waits for all of its input, but edge '{}' is Unbounded so it never emits",
+                    node.id,
+                    canonical.unwrap_or(&node.short_label),
+                    input.id
+                ),
+            );
+        } else if aggregation
+            && ctx.classifier.semantics(node).monotone != Monotonicity::Always
+            && !is_lattice_type(input.label.as_deref())
+            && !is_merge_combinator(node.full_label.as_deref())
+        {
+            // A running aggregate of a stream that never ends is re-emitted
+            // forever, and only a monotone one never takes back a value
+            issue(
+                "UnboundedAggregation",
+                format!(
+                    "Node '{}' ({}) keeps a running aggregate of Unbounded edge '{}' but is neither monotone nor a lattice merge",
+                    node.id,
+                    canonical.unwrap_or(&node.short_label),
+                    input.id
+                ),
+            );
+        }
+        issues
+    }
+}
+
+impl AnalysisPass for BoundednessPass {
+    fn name(&self) -> &str {
+        "boundedness"
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let ir = ctx.ir;
+        let canonical: Vec<Option<String>> = ir
+            .nodes
+            .iter()
+            .map(|node| {
+                node.label
+                    .as_deref()
+                    .and_then(|label| ctx.classifier.profile.canonical_label(label))
+            })
+            .collect();
+        let kinds = Self::infer(ctx, &canonical);
+
+        for (edge, kind) in ir.edges.iter().zip(&kinds) {
+            if tagged(edge).is_none()
+                && let Some(kind) = kind
+            {
+                results.attach_edge_result(self.name(), &edge.id, "inferred", (*kind).into());
+            }
+        }
+
+        for (idx, node) in ir.nodes.iter().enumerate() {
+            let with_kind = |id: &String| {
+                let i = ctx.edge_index(id)?;
+                Some((&ir.edges[i], kinds[i]))
+            };
+            let inputs: Vec<_> = ctx.graph.backward[idx]
+                .iter()
+                .filter_map(|(_, id)| with_kind(id))
+                .collect();
+            let outputs: Vec<_> = ctx.graph.forward[idx]
+                .iter()
+                .filter_map(|(_, id)| with_kind(id))
+                .collect();
+
+            let issues = Self::check(ctx, node, canonical[idx].as_deref(), &inputs, &outputs);
+            if let Some(analysis) = results.node_analyses.get_mut(&node.id) {
+                analysis.issues.extend(issues);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+
+    fn kinds(results: &AnalysisResult, node: &str) -> Vec<String> {
+        results.node_analyses[node]
+            .issues
+            .iter()
+            .map(|issue| issue.kind.clone())
+            .filter(|kind| {
+                ["BoundednessMismatch", "NeverEmits", "UnboundedAggregation"]
+                    .contains(&kind.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_boundedness_checks() {
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let batch = b.node("batch");
+        let sort = b.node("sort");
+        let unbounded_sort = b.node("sort");
+        let fold = b.node("fold");
+        let sink = b.node("for_each");
+        let map = b.node("map");
+        let collect = b.node("collect_vec");
+        // A batch tagged Unbounded contradicts its semantics; sorting the
+        // batch is fine
+        b.edge(&src, &batch, &["Stream", "Unbounded"]);
+        b.edge(&batch, &sort, &["Stream", "Unbounded"]);
+        // Sorting the raw stream, or folding it into a value emitted once, never finishes
+        b.edge(&src, &unbounded_sort, &["Stream", "Unbounded"]);
+        b.edge(&src, &fold, &["Stream", "Unbounded"]);
+        b.edge(&fold, &sink, &["Singleton", "Bounded"]);
+        // Untagged edges inherit the kind of their source's inputs
+        b.edge(&src, &map, &["Stream", "Unbounded"]);
+        let untagged = b.edge(&map, &collect, &[]);
+        let ir = b.build();
+
+        let results = run_analysis(&ir);
+        assert_eq!(kinds(&results, &batch), vec!["BoundednessMismatch"]);
+        assert!(kinds(&results, &sort).is_empty());
+        assert_eq!(kinds(&results, &unbounded_sort), vec!["NeverEmits"]);
+        assert_eq!(kinds(&results, &fold), vec!["NeverEmits"]);
+        assert_eq!(
+            results.edge_analyses[&untagged].pass_results["boundedness"]["inferred"],
+            "Unbounded"
+        );
+        assert_eq!(kinds(&results, &collect), vec!["NeverEmits"]);
+    }
+
+    #[test]
+    fn test_unbounded_aggregation() {
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let sum = b.node("fold");
+        let aci = b.node("fold_commutative_idempotent");
+        let merge = b.node("reduce");
+        let sink = b.node("for_each");
+        // A running sum of the raw stream goes up and down as items arrive
+        b.edge(&src, &sum, &["Stream", "Unbounded"]);
+        b.edge(&sum, &sink, &["Singleton", "Unbounded"]);
+        // Commutative and idempotent folds and lattice merges only grow
+        b.edge(&src, &aci, &["Stream", "Unbounded"]);
+        b.edge(&aci, &sink, &["Singleton", "Unbounded"]);
+        b.typed_edge(&src, &merge, &["Stream", "Unbounded"], "SetUnion<u32>");
+        b.edge(&merge, &sink, &["Singleton", "Unbounded"]);
+        let ir = b.build();
+
+        let results = run_analysis(&ir);
+        assert_eq!(kinds(&results, &sum), vec!["UnboundedAggregation"]);
+        assert!(kinds(&results, &aci).is_empty());
+        assert!(kinds(&results, &merge).is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+// Persistent cache of CALM results across exports
+//
+// Re-exporting a large program after a local change leaves most of its graph
+// as it was, yet every CALM-critical edge would be re-checked against its
+// whole upstream. Each check only depends on the edge and on the operators
+// and edges upstream of it, so the cache keys it by a fingerprint of exactly
+// that: a hash of the labels, types, locations and tags involved, independent
+// of node IDs and of the order of the export. Edges whose upstream did not
+// change reuse the stored verdict; the rest are checked again.
+
+use crate::analysis::Graph;
+use crate::model::{Edge, HydroIr, Node};
+use crate::reach::Reachability;
+use crate::semantics::Classifier;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Bumped whenever the fingerprint or the meaning of a cached result changes
+const CACHE_VERSION: u32 = 2;
+
+/// 64-bit FNV-1a
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    /// Length-prefixed, so adjacent fields can't run into each other
+    fn str(&mut self, value: &str) -> &mut Self {
+        self.u64(value.len() as u64).bytes(value.as_bytes())
+    }
+
+    fn opt(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.bytes(&[1]).str(value),
+            None => self.bytes(&[0]),
+        }
+    }
+
+    fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Hash of a multiset of hashes
+    fn sorted(&mut self, mut values: Vec<u64>) -> &mut Self {
+        values.sort_unstable();
+        self.u64(values.len() as u64);
+        for value in values {
+            self.u64(value);
+        }
+        self
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn node_hash(node: &Node) -> u64 {
+    let location = node.data.as_ref();
+    let mut hash = Fnv::new();
+    hash.str(&node.node_type)
+        .str(&node.short_label)
+        .opt(node.label.as_deref())
+        .opt(node.full_label.as_deref())
+        .opt(
+            location
+                .and_then(|d| d.location_id)
+                .map(|l| l.to_string())
+                .as_deref(),
+        )
+        .opt(location.and_then(|d| d.location_type.as_deref()));
+    // Backtrace-based special cases can change how the node is classified
+    let frames: Vec<_> = location
+        .map(|d| d.backtrace.frames().collect())
+        .unwrap_or_default();
+    hash.u64(frames.len() as u64);
+    for frame in frames {
+        hash.str(&frame.file)
+            .opt(frame.function.as_deref())
+            .opt(frame.line.map(|l| l.to_string()).as_deref())
+            .opt(frame.column.map(|c| c.to_string()).as_deref());
+    }
+    hash.finish()
+}
+
+fn edge_hash(edge: &Edge) -> u64 {
+    let strings = |values: Option<&Vec<String>>| {
+        values
+            .into_iter()
+            .flatten()
+            .map(|value| Fnv::new().str(value).finish())
+            .collect()
+    };
+    Fnv::new()
+        .opt(edge.label.as_deref())
+        .sorted(strings(edge.semantic_tags.as_ref()))
+        .sorted(strings(edge.edge_properties.as_ref()))
+        .finish()
+}
+
+/// Fingerprint of every node: its own contents, its input edges and,
+/// through its strongly connected component, everything upstream of it
+pub(crate) struct Fingerprints {
+    nodes: Vec<u64>,
+}
+
+impl Fingerprints {
+    pub(crate) fn build(ir: &HydroIr, graph: &Graph, reach: &Reachability) -> Self {
+        let edges: BTreeMap<&str, &Edge> = ir.edges.iter().map(|e| (e.id.as_str(), e)).collect();
+        let node_hashes: Vec<u64> = ir.nodes.iter().map(node_hash).collect();
+        // A node with the kinds of edges feeding it and the operators they come from
+        let local: Vec<u64> = (0..ir.nodes.len())
+            .map(|idx| {
+                let inputs = graph.backward[idx]
+                    .iter()
+                    .map(|(source, id)| {
+                        Fnv::new()
+                            .u64(node_hashes[*source])
+                            .u64(edges.get(id.as_str()).map_or(0, |e| edge_hash(e)))
+                            .finish()
+                    })
+                    .collect();
+                Fnv::new().u64(node_hashes[idx]).sorted(inputs).finish()
+            })
+            .collect();
+
+        // Components are in topological order, so upstream ones are done first
+        let components = reach.components();
+        let mut component_hashes: Vec<u64> = Vec::with_capacity(components.len());
+        for (comp, members) in components.iter().enumerate() {
+            let upstream = members
+                .iter()
+                .flat_map(|&node| &graph.backward[node])
+                .map(|&(pred, _)| reach.component(pred))
+                .filter(|&pred| pred != comp)
+                .map(|pred| component_hashes[pred])
+                .collect();
+            let own = members.iter().map(|&node| local[node]).collect();
+            component_hashes.push(Fnv::new().sorted(own).sorted(upstream).finish());
+        }
+
+        Fingerprints {
+            nodes: (0..ir.nodes.len())
+                .map(|idx| {
+                    Fnv::new()
+                        .u64(component_hashes[reach.component(idx)])
+                        .u64(local[idx])
+                        .finish()
+                })
+                .collect(),
+        }
+    }
+
+    /// Key of the CALM check of an edge into the node at `target`
+    pub(crate) fn edge(&self, edge: &Edge, target: usize) -> String {
+        format!(
+            "{:016x}",
+            Fnv::new()
+                .u64(self.nodes[target])
+                .u64(edge_hash(edge))
+                .finish()
+        )
+    }
+}
+
+/// CALM results of a previous run, saved between runs with `--cache`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisCache {
+    version: u32,
+    /// Hash of the crate version and the classifier the results were computed with
+    context: String,
+    /// Whether each CALM-critical edge is CALM-safe, by edge fingerprint
+    calm: BTreeMap<String, bool>,
+}
+
+impl AnalysisCache {
+    /// An empty cache for results computed with `classifier`
+    pub(crate) fn new(classifier: &Classifier) -> Self {
+        AnalysisCache {
+            version: CACHE_VERSION,
+            context: format!(
+                "{:016x}",
+                Fnv::new()
+                    .str(env!("CARGO_PKG_VERSION"))
+                    .str(classifier.profile.name)
+                    .str(&format!("{:?}", classifier.environment))
+                    .finish()
+            ),
+            calm: BTreeMap::new(),
+        }
+    }
+
+    /// Load a cache file; a missing file or one written by another version is empty
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cache file: {}", path.display()))?;
+        let cache: AnalysisCache = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse cache file: {}", path.display()))?;
+        Ok(if cache.version == CACHE_VERSION {
+            cache
+        } else {
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write cache file: {}", path.display()))
+    }
+
+    /// Number of cached CALM results
+    pub fn len(&self) -> usize {
+        self.calm.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calm.is_empty()
+    }
+
+    /// Number of this cache's results that were carried over from `previous`
+    pub fn reused_from(&self, previous: &AnalysisCache) -> usize {
+        if self.context != previous.context {
+            return 0;
+        }
+        self.calm
+            .keys()
+            .filter(|key| previous.calm.contains_key(*key))
+            .count()
+    }
+
+    /// Whether this cache's results hold under `classifier`
+    pub(crate) fn applies_to(&self, classifier: &Classifier) -> bool {
+        self.version == CACHE_VERSION && self.context == Self::new(classifier).context
+    }
+
+    pub(crate) fn calm(&self, key: &str) -> Option<bool> {
+        self.calm.get(key).copied()
+    }
+
+    pub(crate) fn insert_calm(&mut self, key: String, safe: bool) {
+        self.calm.insert(key, safe);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+    use crate::pass::PassRegistry;
+
+    /// Two independent pipelines, each ending in a network edge and a sink
+    fn two_pipelines(second: &str) -> HydroIr {
+        let mut b = IrBuilder::new();
+        for label in ["filter", second] {
+            let src = b.node("source_iter");
+            let op = b.node(label);
+            let network = b.node("network");
+            let sink = b.node("for_each");
+            b.edge(&src, &op, &["Local"]);
+            b.edge(&op, &network, &["Network"]);
+            b.edge(&network, &sink, &["Local"]);
+        }
+        b.build()
+    }
+
+    #[test]
+    fn test_incremental_run_reuses_unchanged_edges() {
+        let registry = PassRegistry::with_builtin_passes();
+        let ir = two_pipelines("map");
+        let (_, first) = registry
+            .run_incremental(&ir, &AnalysisCache::default())
+            .unwrap();
+        assert_eq!(first.len(), 4);
+        assert_eq!(first.reused_from(&AnalysisCache::default()), 0);
+
+        // Only the second pipeline changed
+        let changed = two_pipelines("sort");
+        let (results, second) = registry.run_incremental(&changed, &first).unwrap();
+        assert_eq!(second.reused_from(&first), 2);
+        let fresh = registry.run(&changed).unwrap();
+        for edge in &changed.edges {
+            assert_eq!(
+                results.edge_analyses[&edge.id].calm,
+                fresh.edge_analyses[&edge.id].calm
+            );
+        }
+
+        // Fingerprints don't depend on node IDs or their order
+        let mut renumbered = changed.clone();
+        renumbered.nodes.reverse();
+        for node in &mut renumbered.nodes {
+            node.id = format!("n{}", node.id);
+        }
+        for edge in &mut renumbered.edges {
+            edge.source = format!("n{}", edge.source);
+            edge.target = format!("n{}", edge.target);
+        }
+        let (_, third) = registry.run_incremental(&renumbered, &second).unwrap();
+        assert_eq!(third.reused_from(&second), 4);
+
+        // Results from another profile are not reused
+        let mut other = PassRegistry::with_builtin_passes();
+        other.set_config(crate::config::AnalysisConfig {
+            profile: Some("hydro_lang-0.14".to_string()),
+            ..Default::default()
+        });
+        let (_, fourth) = other.run_incremental(&changed, &second).unwrap();
+        assert_eq!(fourth.reused_from(&second), 0);
+    }
+
+    #[test]
+    fn test_backtrace_changes_invalidate() {
+        use crate::backtrace::Frame;
+
+        // An observe_non_det inside a commutative and idempotent fold is
+        // monotone, which only its backtrace tells
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let observe = b.node("observenondet");
+        let network = b.node("network");
+        let sink = b.node("for_each");
+        b.typed_edge(&src, &observe, &["Local"], "SetUnion<u32>");
+        b.typed_edge(&observe, &network, &["Network"], "SetUnion<u32>");
+        b.typed_edge(&network, &sink, &["Local"], "SetUnion<u32>");
+        b.set_backtrace(
+            &observe,
+            vec![Frame::new(
+                "hydro_lang/src/stream.rs",
+                "fold_commutative_idempotent",
+                10,
+            )],
+        );
+        let ir = b.build();
+        let mut stripped = ir.clone();
+        stripped.nodes[1].data = None;
+
+        let registry = PassRegistry::with_builtin_passes();
+        let (_, cache) = registry
+            .run_incremental(&ir, &AnalysisCache::default())
+            .unwrap();
+        let (results, next) = registry.run_incremental(&stripped, &cache).unwrap();
+        assert_eq!(next.reused_from(&cache), 0);
+        let fresh = registry.run(&stripped).unwrap();
+        for edge in &stripped.edges {
+            assert_eq!(
+                results.edge_analyses[&edge.id].calm,
+                fresh.edge_analyses[&edge.id].calm
+            );
+        }
+        assert_eq!(
+            registry.run(&ir).unwrap().edge_analyses["e1"].calm,
+            crate::analysis::CALM_SAFE
+        );
+        assert_eq!(fresh.edge_analyses["e1"].calm, crate::analysis::CALM_UNSAFE);
+    }
+}
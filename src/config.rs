@@ -0,0 +1,122 @@
+// Analysis configuration
+//
+// Loaded from a JSON file with `--config`; every field is optional and
+// defaults to the built-in behavior.
+
+use crate::backtrace::FrameFilter;
+use crate::delivery::DeliveryConfig;
+use crate::environment::EnvironmentModel;
+use crate::model::HydroIr;
+use crate::semantics::{Classifier, PROFILES, SemanticsProfile, detect_profile};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Settings shared by all analysis passes
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalysisConfig {
+    /// Which backtrace frames count as user code
+    pub frame_filter: FrameFilter,
+    /// List every user frame in each node's `backtrace`, not only the
+    /// selected `source_location`
+    pub full_backtrace: bool,
+    /// Semantics profile name (e.g. "hydro_lang-0.14"); detected from the input when unset
+    pub profile: Option<String>,
+    /// Which operators observe the environment rather than the program
+    pub environment: EnvironmentModel,
+    /// Assumed delivery models of network edges
+    pub delivery: DeliveryConfig,
+}
+
+impl AnalysisConfig {
+    /// Load a configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// The configured semantics profile, or the one detected for `ir`
+    pub fn semantics_profile(&self, ir: &HydroIr) -> Result<&'static SemanticsProfile> {
+        match &self.profile {
+            Some(name) => match crate::semantics::profile(name) {
+                Some(profile) => Ok(profile),
+                None => bail!(
+                    "Unknown semantics profile '{}' (available: {})",
+                    name,
+                    PROFILES
+                        .iter()
+                        .map(|p| p.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            None => Ok(detect_profile(ir)),
+        }
+    }
+
+    /// Classifier for `ir` from the profile and environment settings
+    pub fn classifier(&self, ir: &HydroIr) -> Result<Classifier> {
+        Ok(Classifier {
+            profile: self.semantics_profile(ir)?,
+            environment: self.environment,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config: AnalysisConfig = serde_json::from_str(
+            r#"{"frame_filter": {"include": ["src/location/*"], "prefer": "crates/"}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.frame_filter.include, vec!["src/location/*"]);
+        assert_eq!(config.frame_filter.prefer.as_deref(), Some("crates/"));
+        assert_eq!(config.frame_filter.exclude, FrameFilter::default().exclude);
+
+        assert_eq!(
+            serde_json::from_str::<AnalysisConfig>("{}").unwrap(),
+            AnalysisConfig::default()
+        );
+        assert!(serde_json::from_str::<AnalysisConfig>(r#"{"frame_filters": {}}"#).is_err());
+        assert!(
+            serde_json::from_str::<AnalysisConfig>(r#"{"frame_filter": {"prefers": "crates/"}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_semantics_profile() {
+        let ir = crate::builder::IrBuilder::new().build();
+        let config: AnalysisConfig =
+            serde_json::from_str(r#"{"profile": "hydro_lang-0.13"}"#).unwrap();
+        assert_eq!(
+            config.semantics_profile(&ir).unwrap().name,
+            "hydro_lang-0.13"
+        );
+        assert_eq!(
+            AnalysisConfig::default()
+                .semantics_profile(&ir)
+                .unwrap()
+                .name,
+            "compat"
+        );
+
+        let config = AnalysisConfig {
+            profile: Some("hydro-9".to_string()),
+            ..Default::default()
+        };
+        let err = config.semantics_profile(&ir).unwrap_err().to_string();
+        assert!(
+            err.contains("hydro-9") && err.contains("hydro_lang-0.14"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,304 @@
+// Replica convergence for cluster locations
+//
+// Every member of a `Cluster` runs the same dataflow, so replicas fed the
+// same messages over the network converge only if the state they keep is a
+// lattice and every update to it is a merge: then the final state depends on
+// the multiset of inputs, not on the order or batching in which each replica
+// happened to receive them. The `convergence` pass finds the state
+// (`persist` and folds/reductions) that network input reaches on each
+// cluster and checks both conditions.
+
+use crate::analysis::AnalysisResult;
+use crate::model::{Issue, LocationConvergence, Node};
+use crate::pass::{AnalysisPass, PassContext};
+use crate::semantics::{SemanticsSource, is_lattice_type, is_merge_combinator};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const CONVERGES: &str = "Converges";
+pub const MAY_DIVERGE: &str = "MayDiverge";
+pub const NO_REPLICATED_STATE: &str = "NoReplicatedState";
+
+fn location(node: &Node) -> Option<(usize, &str)> {
+    let data = node.data.as_ref()?;
+    Some((data.location_id?, data.location_type.as_deref()?))
+}
+
+/// The initial state of a fold: its first `q!(..)` argument, with
+/// whitespace removed so `lattices :: set_union` in an export matches
+/// `lattices::set_union`
+fn fold_init(node: &Node) -> Option<String> {
+    let label = node.full_label.as_deref()?;
+    let start = label.find("q!(")? + "q!(".len();
+    let mut depth = 1;
+    let end = label[start..].char_indices().find_map(|(i, c)| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        (depth == 0).then_some(start + i)
+    })?;
+    Some(
+        label[start..end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect(),
+    )
+}
+
+/// Convergence check of network-fed state on each cluster, as a registered pass
+pub struct ConvergencePass;
+
+impl ConvergencePass {
+    /// Why a stateful node may not converge across replicas, if it may not
+    fn check_state(ctx: &PassContext<'_>, node: &Node, canonical: &str) -> Vec<&'static str> {
+        // `persist` keeps the union of everything it has seen
+        if canonical == "persist" {
+            return Vec::new();
+        }
+        let aci = matches!(
+            canonical,
+            "fold_commutative_idempotent"
+                | "fold_idempotent_commutative"
+                | "reduce_commutative_idempotent"
+                | "reduce_idempotent_commutative"
+        ) || ctx.classifier.classify(node).source
+            == SemanticsSource::SpecialCase("commutative_idempotent_fold");
+
+        // The state is what the node emits; when the export does not label
+        // its output edges with a type, that is the initial value of a fold
+        // or the input type of a reduce
+        let outputs = ctx.outgoing(&node.id);
+        let incoming = ctx.incoming(&node.id);
+        let lattice_state = outputs
+            .iter()
+            .any(|edge| is_lattice_type(edge.label.as_deref()))
+            || if canonical.starts_with("reduce") {
+                !incoming.is_empty()
+                    && incoming
+                        .iter()
+                        .all(|edge| is_lattice_type(edge.label.as_deref()))
+            } else {
+                is_lattice_type(fold_init(node).as_deref())
+            };
+
+        let mut problems = Vec::new();
+        if !lattice_state {
+            problems.push("its state type is not a lattice");
+        }
+        if !aci && !is_merge_combinator(node.full_label.as_deref()) {
+            problems.push("its updates are not lattice merges");
+        }
+        problems
+    }
+}
+
+impl AnalysisPass for ConvergencePass {
+    fn name(&self) -> &str {
+        "convergence"
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let ir = ctx.ir;
+
+        // Nodes on each cluster reached by network input without leaving it
+        let mut fed: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for edge in &ir.edges {
+            if !edge
+                .semantic_tags
+                .iter()
+                .flatten()
+                .any(|tag| tag == "Network")
+            {
+                continue;
+            }
+            let Some(start) = ctx.graph.get_idx(&edge.target) else {
+                continue;
+            };
+            let Some((loc, "Cluster")) = location(&ir.nodes[start]) else {
+                continue;
+            };
+            let reached = fed.entry(loc).or_default();
+            let mut worklist = vec![start];
+            while let Some(idx) = worklist.pop() {
+                if !reached.insert(idx) {
+                    continue;
+                }
+                for &(successor, _) in &ctx.graph.forward[idx] {
+                    if location(&ir.nodes[successor]).map(|(l, _)| l) == Some(loc) {
+                        worklist.push(successor);
+                    }
+                }
+            }
+        }
+
+        let clusters: BTreeSet<usize> = ir
+            .nodes
+            .iter()
+            .filter_map(location)
+            .filter(|(_, kind)| *kind == "Cluster")
+            .map(|(loc, _)| loc)
+            .collect();
+
+        let mut verdicts = Vec::new();
+        for loc in clusters {
+            let mut states = Vec::new();
+            let mut reasons = Vec::new();
+            for &idx in fed.get(&loc).into_iter().flatten() {
+                let node = &ir.nodes[idx];
+                let Some(canonical) = node
+                    .label
+                    .as_deref()
+                    .and_then(|label| ctx.classifier.profile.canonical_label(label))
+                else {
+                    continue;
+                };
+                let stateful = canonical == "persist"
+                    || canonical.starts_with("fold")
+                    || canonical.starts_with("reduce");
+                if !stateful {
+                    continue;
+                }
+                states.push(node.id.clone());
+
+                let problems = Self::check_state(ctx, node, &canonical);
+                results.attach_node_result(
+                    self.name(),
+                    &node.id,
+                    "converges",
+                    problems.is_empty().into(),
+                );
+                if problems.is_empty() {
+                    continue;
+                }
+                let message = format!(
+                    "Node '{}' ({}) holds network-fed state on cluster {}, but {}",
+                    node.id,
+                    canonical,
+                    loc,
+                    problems.join(" and ")
+                );
+                reasons.push(message.clone());
+                if let Some(analysis) = results.node_analyses.get_mut(&node.id) {
+                    analysis.issues.push(Issue {
+                        kind: "ReplicaDivergence".to_string(),
+                        subject: node.id.clone(),
+                        message,
+                        affected_critical_edges: Vec::new(),
+                    });
+                }
+            }
+
+            let verdict = if states.is_empty() {
+                NO_REPLICATED_STATE
+            } else if reasons.is_empty() {
+                CONVERGES
+            } else {
+                MAY_DIVERGE
+            };
+            verdicts.push(LocationConvergence {
+                location: loc,
+                verdict: verdict.to_string(),
+                states,
+                reasons,
+            });
+        }
+
+        results.convergence = verdicts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+    use crate::model::HydroIr;
+
+    fn cluster_ir(init: &str, combinator: &str) -> (HydroIr, String) {
+        let mut b = IrBuilder::new();
+        let leader = b.node_at("source_iter", 0, "Process");
+        let network = b.node_at("network", 1, "Cluster");
+        let fold = b.node_at("fold", 1, "Cluster");
+        let sink = b.node_at("for_each", 1, "Cluster");
+        let local = b.node_at("source_iter", 2, "Cluster");
+        let local_fold = b.node_at("fold", 2, "Cluster");
+        b.edge(&leader, &network, &["Network"]);
+        b.edge(&network, &fold, &["Local"]);
+        b.edge(&fold, &sink, &["Local"]);
+        b.edge(&local, &local_fold, &["Local"]);
+        let mut ir = b.build();
+        ir.nodes[2].full_label = Some(format!("fold(q!({}), q!({}))", init, combinator));
+        (ir, fold)
+    }
+
+    const SET_UNION: &str = "| | lattices :: set_union :: SetUnionHashSet :: default ()";
+
+    fn divergence_reasons(ir: &HydroIr) -> Vec<String> {
+        let results = run_analysis(ir);
+        assert_eq!(results.convergence[0].verdict, MAY_DIVERGE);
+        results.convergence[0].reasons.clone()
+    }
+
+    #[test]
+    fn test_lattice_state_comes_from_the_state_type() {
+        // A lattice type named only in the combinator is not the state type
+        let (ir, _) = cluster_ir(
+            "| | std :: collections :: HashSet :: new ()",
+            "| acc , x : SetUnion < u32 > | { lattices :: Merge :: merge (acc , x) ; }",
+        );
+        let reasons = divergence_reasons(&ir);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("not a lattice"), "{:?}", reasons);
+
+        // A typed output edge decides, whatever the initial value looks like
+        let (mut ir, fold) = cluster_ir(
+            "| | Default :: default ()",
+            "| acc , x | { lattices :: Merge :: merge (acc , x) ; }",
+        );
+        let output = ir
+            .edges
+            .iter_mut()
+            .find(|edge| edge.source == fold)
+            .unwrap();
+        output.label = Some("SetUnion<u32>".to_string());
+        assert_eq!(run_analysis(&ir).convergence[0].verdict, CONVERGES);
+    }
+
+    #[test]
+    fn test_only_lattice_merges_count() {
+        let (ir, _) = cluster_ir(SET_UNION, "| acc , x | { HashMap :: merge (acc , x) ; }");
+        let reasons = divergence_reasons(&ir);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("not lattice merges"), "{:?}", reasons);
+
+        let (ir, _) = cluster_ir(SET_UNION, "| acc , x | { x . merge (acc) ; }");
+        assert!(divergence_reasons(&ir)[0].contains("not lattice merges"));
+    }
+
+    #[test]
+    fn test_replica_convergence_per_cluster() {
+        let (ir, fold) = cluster_ir(
+            SET_UNION,
+            "| acc , x | { lattices :: Merge :: merge (acc , x) ; }",
+        );
+        let results = run_analysis(&ir);
+        assert_eq!(results.convergence.len(), 2);
+        assert_eq!(results.convergence[0].location, 1);
+        assert_eq!(results.convergence[0].verdict, CONVERGES);
+        assert_eq!(results.convergence[0].states, vec![fold]);
+        // Cluster 2's fold is not fed by the network
+        assert_eq!(results.convergence[1].verdict, NO_REPLICATED_STATE);
+
+        let (ir, fold) = cluster_ir(SET_UNION, "| acc , x | { acc . insert (x) ; }");
+        let results = run_analysis(&ir);
+        assert_eq!(results.convergence[0].verdict, MAY_DIVERGE);
+        assert!(results.convergence[0].reasons[0].contains("not lattice merges"));
+        assert!(
+            results.node_analyses[&fold]
+                .issues
+                .iter()
+                .any(|issue| issue.kind == "ReplicaDivergence")
+        );
+    }
+}
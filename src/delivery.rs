@@ -0,0 +1,427 @@
+// Network delivery models and anomaly sensitivity
+//
+// The `network` operator is classified as deterministic and monotone, which
+// only holds if everything downstream tolerates what the network may do to
+// messages in flight. Each network edge is given an assumed delivery model,
+// either from the `delivery` section of the config or inferred from its
+// `TotalOrder` / `NoOrder` tag, and the `delivery` pass checks that the
+// operators downstream are commutative where messages may be reordered and
+// idempotent where they may be duplicated. Loss is assumed to be masked by
+// retransmission, which is what turns it into duplication under
+// `at_least_once`.
+
+use crate::analysis::AnalysisResult;
+use crate::model::{Edge, Issue, Node};
+use crate::pass::{AnalysisPass, PassContext};
+use crate::semantics::{SemanticsSource, is_lattice_type};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+/// What the network may do to the messages on a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryModel {
+    /// Exactly-once, in send order per channel
+    Fifo,
+    /// Exactly-once, in any order
+    Unordered,
+    /// In any order, possibly more than once
+    AtLeastOnce,
+}
+
+impl DeliveryModel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryModel::Fifo => "fifo",
+            DeliveryModel::Unordered => "unordered",
+            DeliveryModel::AtLeastOnce => "at_least_once",
+        }
+    }
+
+    /// Whether messages may arrive in a different order than they were sent
+    pub fn reorders(&self) -> bool {
+        !matches!(self, DeliveryModel::Fifo)
+    }
+
+    /// Whether a message may be delivered more than once
+    pub fn duplicates(&self) -> bool {
+        matches!(self, DeliveryModel::AtLeastOnce)
+    }
+}
+
+impl std::fmt::Display for DeliveryModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Assumed delivery models for network edges
+///
+/// An edge listed in `edges` uses that model; otherwise `model` applies to
+/// every network edge. With neither, the model is inferred from the edge's
+/// ordering tag: `TotalOrder` is `fifo` and anything else `unordered`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeliveryConfig {
+    pub model: Option<DeliveryModel>,
+    /// Per-edge overrides, by edge ID
+    pub edges: BTreeMap<String, DeliveryModel>,
+}
+
+impl DeliveryConfig {
+    /// The model assumed for a network edge, and where it came from
+    /// (`edge`, `config`, `tag:TotalOrder`, `tag:NoOrder` or `default`)
+    pub fn model_for(&self, edge: &Edge) -> (DeliveryModel, &'static str) {
+        if let Some(model) = self.edges.get(&edge.id) {
+            return (*model, "edge");
+        }
+        if let Some(model) = self.model {
+            return (model, "config");
+        }
+        let has_tag = |name: &str| edge.semantic_tags.iter().flatten().any(|tag| tag == name);
+        if has_tag("TotalOrder") {
+            (DeliveryModel::Fifo, "tag:TotalOrder")
+        } else if has_tag("NoOrder") {
+            (DeliveryModel::Unordered, "tag:NoOrder")
+        } else {
+            (DeliveryModel::Unordered, "default")
+        }
+    }
+}
+
+/// A delivery anomaly that downstream operators must tolerate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Anomaly {
+    Reorder,
+    Duplicate,
+}
+
+impl Anomaly {
+    fn allowed_by(self, model: DeliveryModel) -> bool {
+        match self {
+            Anomaly::Reorder => model.reorders(),
+            Anomaly::Duplicate => model.duplicates(),
+        }
+    }
+}
+
+/// How an operator reacts to an anomaly in its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reaction {
+    /// The output is unaffected but carries the anomaly onwards
+    PassThrough,
+    /// The output no longer exhibits the anomaly (`sort`, `unique`)
+    Absorbs,
+    /// The output depends on it: a non-commutative or non-idempotent operator
+    Sensitive,
+}
+
+/// Reaction of an operator, by canonical name, to an anomaly
+fn reaction(canonical: &str, anomaly: Anomaly) -> Reaction {
+    match (anomaly, canonical) {
+        (Anomaly::Duplicate, "first" | "last") => Reaction::Absorbs,
+        (
+            _,
+            "fold"
+            | "fold_keyed"
+            | "reduce"
+            | "reduce_keyed"
+            | "reduce_keyed_watermark"
+            | "scan"
+            | "enumerate"
+            | "first"
+            | "last"
+            | "collect_vec",
+        ) => Reaction::Sensitive,
+        (Anomaly::Reorder, "fold_idempotent" | "reduce_idempotent") => Reaction::Sensitive,
+        (Anomaly::Duplicate, "fold_commutative" | "reduce_commutative" | "count") => {
+            Reaction::Sensitive
+        }
+        (Anomaly::Reorder, "sort") | (Anomaly::Duplicate, "unique") => Reaction::Absorbs,
+        _ => Reaction::PassThrough,
+    }
+}
+
+/// Annotation of network edges with delivery models and checks of the
+/// operators downstream of them, as a registered pass
+pub struct DeliveryPass;
+
+impl DeliveryPass {
+    /// Reaction of a node reached over `via`, which exempts aggregations
+    /// known to be commutative and idempotent and merges of lattice values
+    fn node_reaction(ctx: &PassContext<'_>, node: &Node, via: &Edge, anomaly: Anomaly) -> Reaction {
+        let Some(canonical) = node
+            .label
+            .as_deref()
+            .and_then(|label| ctx.classifier.profile.canonical_label(label))
+        else {
+            return Reaction::PassThrough;
+        };
+        let reaction = reaction(&canonical, anomaly);
+        let aci = ctx.classifier.classify(node).source
+            == SemanticsSource::SpecialCase("commutative_idempotent_fold")
+            || is_lattice_type(via.label.as_deref());
+        if reaction == Reaction::Sensitive && aci {
+            Reaction::PassThrough
+        } else {
+            reaction
+        }
+    }
+
+    /// Report operators downstream of `origins` that are sensitive to `anomaly`
+    ///
+    /// Each origin is a network edge whose model allows the anomaly. The
+    /// anomaly stops at the first operator that absorbs it or depends on it;
+    /// issues name the nearest origin. Whether an operator depends on it can
+    /// differ between its inputs, so every input it is reached over is
+    /// checked.
+    fn check(
+        &self,
+        ctx: &PassContext<'_>,
+        results: &mut AnalysisResult,
+        origins: &[(&Edge, DeliveryModel)],
+        anomaly: Anomaly,
+    ) {
+        let mut reached: HashSet<(usize, &str)> = HashSet::new();
+        let mut reported: HashSet<usize> = HashSet::new();
+        let mut queue: VecDeque<(usize, &Edge, usize)> = VecDeque::new();
+        for (origin, (edge, _)) in origins.iter().enumerate() {
+            if let Some(idx) = ctx.graph.get_idx(&edge.target) {
+                queue.push_back((idx, edge, origin));
+            }
+        }
+
+        while let Some((idx, via, origin)) = queue.pop_front() {
+            if !reached.insert((idx, via.id.as_str())) {
+                continue;
+            }
+
+            let node = &ctx.ir.nodes[idx];
+            match Self::node_reaction(ctx, node, via, anomaly) {
+                Reaction::Absorbs => {}
+                Reaction::Sensitive => {
+                    if !reported.insert(idx) {
+                        continue;
+                    }
+                    let (edge, model) = origins[origin];
+                    let (kind, property, effect) = match anomaly {
+                        Anomaly::Reorder => ("ReorderSensitive", "order-sensitive", "reorder"),
+                        Anomaly::Duplicate => {
+                            ("DuplicateSensitive", "duplicate-sensitive", "duplicate")
+                        }
+                    };
+                    if let Some(analysis) = results.node_analyses.get_mut(&node.id) {
+                        analysis.issues.push(Issue {
+                            kind: kind.to_string(),
+                            subject: node.id.clone(),
+                            message: format!(
+                                "Node '{}' ({}) is {} but network edge '{}' may {} messages ({})",
+                                node.id,
+                                node.label.as_ref().unwrap_or(&node.short_label),
+                                property,
+                                edge.id,
+                                effect,
+                                model
+                            ),
+                            affected_critical_edges: Vec::new(),
+                        });
+                    }
+                }
+                Reaction::PassThrough => {
+                    for (successor, edge_id) in &ctx.graph.forward[idx] {
+                        if !reached.contains(&(*successor, edge_id.as_str()))
+                            && let Some(edge) = ctx.edge(edge_id)
+                        {
+                            queue.push_back((*successor, edge, origin));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl AnalysisPass for DeliveryPass {
+    fn name(&self) -> &str {
+        "delivery"
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let mut network_edges = Vec::new();
+        for edge in &ctx.ir.edges {
+            if !edge
+                .semantic_tags
+                .iter()
+                .flatten()
+                .any(|tag| tag == "Network")
+            {
+                continue;
+            }
+            let (model, source) = ctx.config.delivery.model_for(edge);
+            results.attach_edge_result(self.name(), &edge.id, "model", model.as_str().into());
+            results.attach_edge_result(self.name(), &edge.id, "model_source", source.into());
+            network_edges.push((edge, model));
+        }
+
+        for anomaly in [Anomaly::Reorder, Anomaly::Duplicate] {
+            let origins: Vec<(&Edge, DeliveryModel)> = network_edges
+                .iter()
+                .copied()
+                .filter(|(_, model)| anomaly.allowed_by(*model))
+                .collect();
+            self.check(ctx, results, &origins, anomaly);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+    use crate::config::AnalysisConfig;
+    use crate::pass::PassRegistry;
+
+    fn issue_kinds(results: &AnalysisResult, node: &str) -> Vec<String> {
+        results.node_analyses[node]
+            .issues
+            .iter()
+            .map(|issue| issue.kind.clone())
+            .filter(|kind| kind.ends_with("Sensitive"))
+            .collect()
+    }
+
+    fn run_with(ir: &crate::model::HydroIr, config: &str) -> AnalysisResult {
+        let config: AnalysisConfig = serde_json::from_str(config).unwrap();
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.set_config(config);
+        registry.run(ir).unwrap()
+    }
+
+    #[test]
+    fn test_delivery_model_sources() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_iter", 0, "Process");
+        let ordered = b.node_at("network", 1, "Process");
+        let unordered = b.node_at("network", 1, "Process");
+        let fifo = b.edge(&src, &ordered, &["Network", "Stream", "TotalOrder"]);
+        let no_order = b.edge(&src, &unordered, &["Network", "Stream", "NoOrder"]);
+        let ir = b.build();
+
+        let results = PassRegistry::with_builtin_passes().run(&ir).unwrap();
+        let delivery = |edge: &str| results.edge_analyses[edge].pass_results["delivery"].clone();
+        assert_eq!(delivery(&fifo)["model"], "fifo");
+        assert_eq!(delivery(&fifo)["model_source"], "tag:TotalOrder");
+        assert_eq!(delivery(&no_order)["model"], "unordered");
+        assert_eq!(delivery(&no_order)["model_source"], "tag:NoOrder");
+
+        // A per-edge override wins over the configured model
+        let results = run_with(
+            &ir,
+            &format!(
+                r#"{{"delivery": {{"model": "at_least_once", "edges": {{"{}": "fifo"}}}}}}"#,
+                no_order
+            ),
+        );
+        let delivery = |edge: &str| results.edge_analyses[edge].pass_results["delivery"].clone();
+        assert_eq!(delivery(&fifo)["model"], "at_least_once");
+        assert_eq!(delivery(&fifo)["model_source"], "config");
+        assert_eq!(delivery(&no_order)["model"], "fifo");
+        assert_eq!(delivery(&no_order)["model_source"], "edge");
+    }
+
+    #[test]
+    fn test_reorder_sensitivity() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_iter", 0, "Process");
+        let ordered = b.node_at("network", 1, "Process");
+        let ordered_scan = b.node_at("scan", 1, "Process");
+        let unordered = b.node_at("network", 1, "Process");
+        let scan = b.node_at("scan", 1, "Process");
+        let count = b.node_at("count", 1, "Process");
+        let sort = b.node_at("sort", 1, "Process");
+        let sorted_first = b.node_at("first", 1, "Process");
+        let first = b.node_at("first", 1, "Process");
+        b.edge(&src, &ordered, &["Network", "Stream", "TotalOrder"]);
+        b.edge(&ordered, &ordered_scan, &["Local", "Stream", "TotalOrder"]);
+        b.edge(&src, &unordered, &["Network", "Stream", "NoOrder"]);
+        for target in [&scan, &count, &sort, &first] {
+            b.edge(&unordered, target, &["Local", "Stream", "NoOrder"]);
+        }
+        b.edge(&sort, &sorted_first, &["Local", "Stream", "TotalOrder"]);
+        let ir = b.build();
+
+        // `count` is commutative and `sort` absorbs the reordering before
+        // it reaches `first`; the FIFO channel does not reorder at all
+        let results = PassRegistry::with_builtin_passes().run(&ir).unwrap();
+        assert!(issue_kinds(&results, &ordered_scan).is_empty());
+        assert_eq!(issue_kinds(&results, &scan), vec!["ReorderSensitive"]);
+        assert!(issue_kinds(&results, &count).is_empty());
+        assert!(issue_kinds(&results, &sorted_first).is_empty());
+        assert_eq!(issue_kinds(&results, &first), vec!["ReorderSensitive"]);
+    }
+
+    #[test]
+    fn test_duplicate_sensitivity() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_iter", 0, "Process");
+        let network = b.node_at("network", 1, "Process");
+        let scan = b.node_at("scan", 1, "Process");
+        let count = b.node_at("count", 1, "Process");
+        let first = b.node_at("first", 1, "Process");
+        let last = b.node_at("last", 1, "Process");
+        let unique = b.node_at("unique", 1, "Process");
+        let unique_count = b.node_at("count", 1, "Process");
+        let channel = b.edge(&src, &network, &["Network", "Stream", "TotalOrder"]);
+        for target in [&scan, &count, &first, &last, &unique] {
+            b.edge(&network, target, &["Local", "Stream", "TotalOrder"]);
+        }
+        b.edge(&unique, &unique_count, &["Local", "Stream", "TotalOrder"]);
+        let ir = b.build();
+
+        // Exactly-once delivery duplicates nothing
+        let results = PassRegistry::with_builtin_passes().run(&ir).unwrap();
+        assert!(issue_kinds(&results, &count).is_empty());
+
+        // `first` and `last` pick one element whether or not it is repeated,
+        // and `unique` absorbs the duplicates before the second `count`
+        let results = run_with(&ir, r#"{"delivery": {"model": "at_least_once"}}"#);
+        let duplicate_kinds = |node: &str| {
+            issue_kinds(&results, node)
+                .into_iter()
+                .filter(|kind| kind == "DuplicateSensitive")
+                .count()
+        };
+        assert_eq!(duplicate_kinds(&scan), 1);
+        assert_eq!(duplicate_kinds(&count), 1);
+        assert_eq!(duplicate_kinds(&first), 0);
+        assert_eq!(duplicate_kinds(&last), 0);
+        assert_eq!(duplicate_kinds(&unique_count), 0);
+        assert!(results.node_analyses[&count].issues.iter().any(|issue| {
+            issue.message.contains(&channel) && issue.message.contains("at_least_once")
+        }));
+    }
+
+    #[test]
+    fn test_every_input_is_checked() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_iter", 0, "Process");
+        let merges = b.node_at("network", 1, "Process");
+        let values = b.node_at("network", 1, "Process");
+        let joined = b.node_at("reduce", 1, "Process");
+        b.edge(&src, &merges, &["Network", "Stream", "NoOrder"]);
+        b.edge(&src, &values, &["Network", "Stream", "NoOrder"]);
+        // Reordering lattice values is harmless, but the plain values on the
+        // other input still reach the reduce out of order
+        b.typed_edge(
+            &merges,
+            &joined,
+            &["Local", "Stream", "NoOrder"],
+            "SetUnion<u32>",
+        );
+        b.edge(&values, &joined, &["Local", "Stream", "NoOrder"]);
+        let ir = b.build();
+
+        let results = PassRegistry::with_builtin_passes().run(&ir).unwrap();
+        assert_eq!(issue_kinds(&results, &joined), vec!["ReorderSensitive"]);
+    }
+}
@@ -0,0 +1,88 @@
+// Model of environment nondeterminism
+//
+// Some operators are nondeterministic because of the world outside the
+// program rather than because the program is racy: what an external client
+// sends, the order messages arrive over the network, when a timer fires, or
+// which cluster members are up. Operators that observe the environment are
+// classified `ExternalNonDet`, and that taint is propagated separately from
+// local nondeterminism so reports can tell "depends on the environment" from
+// "program is racy".
+
+use serde::Deserialize;
+
+/// Which parts of the environment count as nondeterminism origins
+///
+/// Every category except `sampling` is enabled by default; a disabled
+/// category falls back to the operator's entry in the label table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentModel {
+    /// Streams fed from outside the program (`external_input`, `source_stream`)
+    pub external_inputs: bool,
+    /// Arrival order of messages received over the network (`network`)
+    pub network_order: bool,
+    /// Wall-clock timer sources (`source_interval`)
+    pub timers: bool,
+    /// Sampling a stream on a timer (`sample_every`, `timeout`); off by
+    /// default because which items are sampled races the program's own ticks
+    pub sampling: bool,
+    /// Cluster membership changes (`source_cluster_members`)
+    pub membership: bool,
+}
+
+impl Default for EnvironmentModel {
+    fn default() -> Self {
+        EnvironmentModel {
+            external_inputs: true,
+            network_order: true,
+            timers: true,
+            sampling: false,
+            membership: true,
+        }
+    }
+}
+
+impl EnvironmentModel {
+    /// A model in which nothing comes from the environment
+    pub fn none() -> Self {
+        EnvironmentModel {
+            external_inputs: false,
+            network_order: false,
+            timers: false,
+            sampling: false,
+            membership: false,
+        }
+    }
+
+    /// The environment category of an operator, given its canonical name, if
+    /// this model treats it as an origin
+    pub fn origin(&self, canonical: &str) -> Option<&'static str> {
+        match canonical {
+            "external_input" | "source_stream" if self.external_inputs => Some("external_inputs"),
+            "network" if self.network_order => Some("network_order"),
+            "source_interval" if self.timers => Some("timers"),
+            "sample_every" | "timeout" if self.sampling => Some("sampling"),
+            "source_cluster_members" if self.membership => Some("membership"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_categories_can_be_disabled() {
+        assert_eq!(EnvironmentModel::default().origin("timeout"), None);
+
+        let model: EnvironmentModel =
+            serde_json::from_str(r#"{"network_order": false, "sampling": true}"#).unwrap();
+        assert_eq!(model.origin("source_stream"), Some("external_inputs"));
+        assert_eq!(model.origin("source_interval"), Some("timers"));
+        assert_eq!(model.origin("timeout"), Some("sampling"));
+        assert_eq!(model.origin("network"), None);
+        assert_eq!(model.origin("map"), None);
+        assert_eq!(EnvironmentModel::none().origin("source_stream"), None);
+    }
+}
@@ -0,0 +1,311 @@
+// Relational view of the graph and its analysis results
+//
+// The same facts back the rule language in `rules.rs` and any external
+// reasoning over the graph, so both see exactly what hydrolysis derived.
+
+use crate::analysis::{AnalysisResult, is_calm_critical};
+use crate::model::HydroIr;
+use crate::semantics::{Monotonicity, NdEffect, SemanticsSource};
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A fact is a tuple of string values
+pub type Tuple = Vec<String>;
+
+/// Built-in relations with their column names
+pub const BASE_RELATIONS: &[(&str, &[&str])] = &[
+    ("node", &["id", "label", "type", "loc"]),
+    ("location", &["loc", "kind"]),
+    ("edge", &["id", "src", "dst"]),
+    ("tag", &["edge", "tag"]),
+    ("port", &["edge", "port"]),
+    ("nd_effect", &["node", "effect"]),
+    ("nd_kind", &["node", "kind"]),
+    ("nd_root", &["node"]),
+    ("env_root", &["node", "category"]),
+    ("monotone", &["node", "kind"]),
+    ("lattice", &["edge"]),
+    ("calm", &["edge", "status"]),
+    ("calm_critical", &["edge"]),
+];
+
+/// Arity of a built-in relation, if `name` is one
+pub fn base_arity(name: &str) -> Option<usize> {
+    BASE_RELATIONS
+        .iter()
+        .find(|(rel, _)| *rel == name)
+        .map(|(_, cols)| cols.len())
+}
+
+/// Extract all built-in relations from the IR and analysis results
+///
+/// Every relation in `BASE_RELATIONS` is present in the output, possibly empty.
+/// Tuples within a relation are in input order.
+pub fn extract_facts(ir: &HydroIr, results: &AnalysisResult) -> BTreeMap<&'static str, Vec<Tuple>> {
+    let mut facts: BTreeMap<&'static str, Vec<Tuple>> = BASE_RELATIONS
+        .iter()
+        .map(|(name, _)| (*name, Vec::new()))
+        .collect();
+    let mut push = |rel: &'static str, tuple: Tuple| {
+        if let Some(tuples) = facts.get_mut(rel) {
+            tuples.push(tuple);
+        }
+    };
+
+    let mut locations: BTreeMap<String, String> = BTreeMap::new();
+    let nodes_by_id: HashMap<&str, &crate::model::Node> =
+        ir.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+    for node in &ir.nodes {
+        let label = node.label.as_ref().unwrap_or(&node.short_label);
+        let loc = node
+            .data
+            .as_ref()
+            .and_then(|d| d.location_id)
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        if let Some(kind) = node.data.as_ref().and_then(|d| d.location_type.clone())
+            && !loc.is_empty()
+        {
+            locations.entry(loc.clone()).or_insert(kind);
+        }
+
+        push(
+            "node",
+            vec![node.id.clone(), label.clone(), node.node_type.clone(), loc],
+        );
+
+        let classification = results.classifier.classify(node);
+        let semantics = classification.semantics;
+        if semantics.nd == NdEffect::LocallyNonDet {
+            push("nd_root", vec![node.id.clone()]);
+        }
+        if let SemanticsSource::Environment(category) = classification.source {
+            push("env_root", vec![node.id.clone(), category.to_string()]);
+        }
+        let kind = match semantics.monotone {
+            Monotonicity::Always => "Always",
+            Monotonicity::Never => "Never",
+            Monotonicity::Depends => "Depends",
+            Monotonicity::Antitone => "Antitone",
+        };
+        push("monotone", vec![node.id.clone(), kind.to_string()]);
+
+        if let Some(analysis) = results.node_analyses.get(&node.id) {
+            push(
+                "nd_effect",
+                vec![node.id.clone(), analysis.nd_effect.clone()],
+            );
+            for kind in &analysis.nd_kinds {
+                push("nd_kind", vec![node.id.clone(), kind.clone()]);
+            }
+        }
+    }
+
+    for (loc, kind) in locations {
+        push("location", vec![loc, kind]);
+    }
+
+    for edge in &ir.edges {
+        push(
+            "edge",
+            vec![edge.id.clone(), edge.source.clone(), edge.target.clone()],
+        );
+        for tag in edge.semantic_tags.iter().flatten() {
+            push("tag", vec![edge.id.clone(), tag.clone()]);
+        }
+        if let Some(port) = edge.port() {
+            push("port", vec![edge.id.clone(), port.to_string()]);
+        }
+        if is_calm_critical(edge, nodes_by_id.get(edge.target.as_str()).copied()) {
+            push("calm_critical", vec![edge.id.clone()]);
+        }
+        if let Some(analysis) = results.edge_analyses.get(&edge.id) {
+            if analysis.is_lattice {
+                push("lattice", vec![edge.id.clone()]);
+            }
+            push("calm", vec![edge.id.clone(), analysis.calm.clone()]);
+        }
+    }
+
+    facts
+}
+
+/// On-disk format for exported facts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactFormat {
+    /// Souffle input: a `schema.dl` with `.decl`/`.input` directives and
+    /// one tab-separated `<relation>.facts` file per relation
+    Souffle,
+    /// One `<relation>.csv` file per relation, with a header row
+    Csv,
+}
+
+impl FromStr for FactFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "souffle" => Ok(FactFormat::Souffle),
+            "csv" => Ok(FactFormat::Csv),
+            other => bail!("Unknown fact format '{}' (expected souffle or csv)", other),
+        }
+    }
+}
+
+/// Write every relation in `facts` to `dir`, creating it if needed
+///
+/// Returns the paths written, in relation order.
+pub fn write_facts(
+    facts: &BTreeMap<&'static str, Vec<Tuple>>,
+    format: FactFormat,
+    dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create output directory: {}", dir.display()))?;
+
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    match format {
+        FactFormat::Souffle => {
+            let mut schema = String::new();
+            for (name, columns) in BASE_RELATIONS {
+                let params: Vec<String> =
+                    columns.iter().map(|c| format!("{}: symbol", c)).collect();
+                let _ = writeln!(schema, ".decl {}({})", name, params.join(", "));
+                let _ = writeln!(schema, ".input {}", name);
+            }
+            files.push((dir.join("schema.dl"), schema));
+
+            for (name, tuples) in facts {
+                let mut out = String::new();
+                for tuple in tuples {
+                    let fields: Vec<String> = tuple.iter().map(|v| souffle_field(v)).collect();
+                    let _ = writeln!(out, "{}", fields.join("\t"));
+                }
+                files.push((dir.join(format!("{}.facts", name)), out));
+            }
+        }
+        FactFormat::Csv => {
+            for (name, tuples) in facts {
+                let columns = BASE_RELATIONS
+                    .iter()
+                    .find(|(rel, _)| rel == name)
+                    .map(|(_, cols)| *cols)
+                    .unwrap_or_default();
+                let mut out = String::new();
+                let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+                let _ = writeln!(out, "{}", header.join(","));
+                for tuple in tuples {
+                    let fields: Vec<String> = tuple.iter().map(|v| csv_field(v)).collect();
+                    let _ = writeln!(out, "{}", fields.join(","));
+                }
+                files.push((dir.join(format!("{}.csv", name)), out));
+            }
+        }
+    }
+
+    let mut written = Vec::with_capacity(files.len());
+    for (path, contents) in files {
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to write fact file: {}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Souffle fact files are tab-separated with no quoting, so tabs and
+/// newlines inside values are replaced by spaces
+fn souffle_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+
+    fn sample() -> (HydroIr, AnalysisResult) {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_stream", 0, "Process");
+        let nondet = b.node_at("observenondet", 1, "Cluster");
+        let sink = b.node_at("for_each", 1, "Cluster");
+        b.edge(&src, &nondet, &["Network", "Unbounded"]);
+        b.port_edge(&nondet, &sink, &["Local"], "left");
+        let ir = b.build();
+        let results = run_analysis(&ir);
+        (ir, results)
+    }
+
+    fn tuple(values: &[&str]) -> Tuple {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_extract_facts() {
+        let (ir, results) = sample();
+        let facts = extract_facts(&ir, &results);
+
+        assert_eq!(facts.len(), BASE_RELATIONS.len());
+        assert_eq!(
+            facts["node"][1],
+            tuple(&["1", "observenondet", "NonDeterministic", "1"])
+        );
+        assert_eq!(
+            facts["location"],
+            vec![tuple(&["0", "Process"]), tuple(&["1", "Cluster"])]
+        );
+        assert_eq!(facts["edge"][0], tuple(&["e0", "0", "1"]));
+        assert!(facts["tag"].contains(&tuple(&["e0", "Network"])));
+        assert_eq!(facts["port"], vec![tuple(&["e1", "left"])]);
+        assert_eq!(facts["nd_root"], vec![tuple(&["1"])]);
+        assert_eq!(facts["env_root"], vec![tuple(&["0", "external_inputs"])]);
+        assert!(facts["nd_kind"].contains(&tuple(&["2", "input"])));
+        assert!(facts["monotone"].contains(&tuple(&["2", "Always"])));
+        // e0 crosses the network, e1 feeds a sink
+        assert_eq!(facts["calm_critical"], vec![tuple(&["e0"]), tuple(&["e1"])]);
+        for (name, columns) in BASE_RELATIONS {
+            assert!(facts[name].iter().all(|t| t.len() == columns.len()));
+        }
+    }
+
+    #[test]
+    fn test_write_facts() {
+        let (ir, results) = sample();
+        let facts = extract_facts(&ir, &results);
+        let dir = std::env::temp_dir().join(format!("hydrolysis-facts-{}", std::process::id()));
+
+        write_facts(&facts, FactFormat::Souffle, &dir).unwrap();
+        let schema = std::fs::read_to_string(dir.join("schema.dl")).unwrap();
+        assert!(
+            schema.contains(".decl node(id: symbol, label: symbol, type: symbol, loc: symbol)")
+        );
+        assert!(schema.contains(".input calm_critical"));
+        let edges = std::fs::read_to_string(dir.join("edge.facts")).unwrap();
+        assert_eq!(edges, "e0\t0\t1\ne1\t1\t2\n");
+
+        write_facts(&facts, FactFormat::Csv, &dir).unwrap();
+        let edges = std::fs::read_to_string(dir.join("edge.csv")).unwrap();
+        assert_eq!(edges, "id,src,dst\ne0,0,1\ne1,1,2\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("to Some(0), x"), "\"to Some(0), x\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
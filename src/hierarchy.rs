@@ -0,0 +1,358 @@
+// Typed view of `hierarchyChoices` / `nodeAssignments`
+//
+// The IR keeps both as raw JSON so they round-trip unchanged into the
+// annotated output. This module parses them into trees so that analysis
+// results can be grouped by location or by call site.
+//
+// The backtrace hierarchy is compressed: a chain of single-child frames is
+// merged into one tree node whose name joins the function names with " → ",
+// e.g. `plumb_from_process → dispatch_from_process_with_layers`.
+
+use crate::analysis::AnalysisResult;
+use crate::model::HydroIr;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Separator between function names in a compressed hierarchy node
+pub const FRAME_SEPARATOR: &str = " → ";
+
+/// A node in one of the `hierarchyChoices` trees
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HierarchyNode {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub children: Vec<HierarchyNode>,
+}
+
+impl HierarchyNode {
+    /// Function names merged into this node, outermost first
+    pub fn frames(&self) -> impl Iterator<Item = &str> {
+        self.name.split(FRAME_SEPARATOR)
+    }
+}
+
+/// Parse the `hierarchyChoices` array; malformed entries are skipped
+pub fn parse_choices(ir: &HydroIr) -> Vec<HierarchyNode> {
+    ir.hierarchy_choices
+        .as_ref()
+        .and_then(|c| c.as_array())
+        .map(|choices| {
+            choices
+                .iter()
+                .filter_map(|c| serde_json::from_value(c.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse one `nodeAssignments` map (node ID → hierarchy node ID)
+pub fn parse_assignments(ir: &HydroIr, choice: &str) -> HashMap<String, String> {
+    ir.node_assignments
+        .as_ref()
+        .and_then(|a| a.get(choice))
+        .and_then(|a| a.as_object())
+        .map(|map| {
+            map.iter()
+                .filter_map(|(node, id)| Some((node.clone(), id.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The call-site hierarchy from the `backtrace` choice, with node assignments
+#[derive(Debug, Clone)]
+pub struct CallSiteHierarchy {
+    /// Top-level call sites (children of the `backtrace` choice)
+    pub roots: Vec<HierarchyNode>,
+    /// Graph node ID → call site ID
+    pub assignments: HashMap<String, String>,
+}
+
+/// A call site with issue counts for everything assigned to it or below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallTreeNode {
+    pub id: String,
+    pub name: String,
+    /// Number of graph nodes assigned to this call site or a descendant
+    pub nodes: usize,
+    /// Issue count per kind, including descendants
+    pub issues: BTreeMap<String, usize>,
+    pub children: Vec<CallTreeNode>,
+}
+
+impl CallTreeNode {
+    /// Total issues of all kinds
+    pub fn total_issues(&self) -> usize {
+        self.issues.values().sum()
+    }
+
+    /// Innermost function name of this call site
+    pub fn function(&self) -> &str {
+        self.name
+            .rsplit(FRAME_SEPARATOR)
+            .next()
+            .unwrap_or(&self.name)
+    }
+
+    /// Call site name shortened to its last two frames
+    pub fn short_name(&self) -> String {
+        let frames: Vec<&str> = self.name.split(FRAME_SEPARATOR).collect();
+        if frames.len() > 2 {
+            format!(
+                "…{}{}",
+                FRAME_SEPARATOR,
+                frames[frames.len() - 2..].join(FRAME_SEPARATOR)
+            )
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Remove subtrees without issues
+    pub fn prune(mut self) -> Option<Self> {
+        if self.total_issues() == 0 {
+            return None;
+        }
+        self.children = self.children.into_iter().filter_map(Self::prune).collect();
+        Some(self)
+    }
+}
+
+impl CallSiteHierarchy {
+    /// Parse the call-site hierarchy, if the IR has one
+    pub fn from_ir(ir: &HydroIr) -> Option<Self> {
+        let choice = parse_choices(ir)
+            .into_iter()
+            .find(|c| c.id == "backtrace")?;
+        Some(CallSiteHierarchy {
+            roots: choice.children,
+            assignments: parse_assignments(ir, "backtrace"),
+        })
+    }
+
+    /// Call sites from the outermost down to `id`, or empty if unknown
+    pub fn path(&self, id: &str) -> Vec<&HierarchyNode> {
+        fn find<'h>(
+            nodes: &'h [HierarchyNode],
+            id: &str,
+            path: &mut Vec<&'h HierarchyNode>,
+        ) -> bool {
+            for node in nodes {
+                path.push(node);
+                if node.id == id || find(&node.children, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+        let mut path = Vec::new();
+        find(&self.roots, id, &mut path);
+        path
+    }
+
+    /// Full call stack of a graph node, outermost frame first
+    pub fn call_stack(&self, node_id: &str) -> Vec<&str> {
+        self.assignments
+            .get(node_id)
+            .map(|site| {
+                self.path(site)
+                    .into_iter()
+                    .flat_map(|n| n.frames())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Aggregate issues over the call tree
+    ///
+    /// Node issues count at the node's call site; edge issues count at the
+    /// call site of the edge's source node.
+    pub fn issue_tree(&self, ir: &HydroIr, results: &AnalysisResult) -> Vec<CallTreeNode> {
+        let mut own_nodes: HashMap<&str, usize> = HashMap::new();
+        let mut own_issues: HashMap<&str, BTreeMap<String, usize>> = HashMap::new();
+
+        for node in &ir.nodes {
+            let Some(site) = self.assignments.get(&node.id) else {
+                continue;
+            };
+            *own_nodes.entry(site).or_default() += 1;
+            if let Some(analysis) = results.node_analyses.get(&node.id) {
+                for issue in &analysis.issues {
+                    *own_issues
+                        .entry(site)
+                        .or_default()
+                        .entry(issue.kind.clone())
+                        .or_default() += 1;
+                }
+            }
+        }
+        for edge in &ir.edges {
+            let Some(site) = self.assignments.get(&edge.source) else {
+                continue;
+            };
+            if let Some(analysis) = results.edge_analyses.get(&edge.id) {
+                for issue in &analysis.issues {
+                    *own_issues
+                        .entry(site)
+                        .or_default()
+                        .entry(issue.kind.clone())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        fn build(
+            node: &HierarchyNode,
+            own_nodes: &HashMap<&str, usize>,
+            own_issues: &HashMap<&str, BTreeMap<String, usize>>,
+        ) -> CallTreeNode {
+            let children: Vec<CallTreeNode> = node
+                .children
+                .iter()
+                .map(|c| build(c, own_nodes, own_issues))
+                .collect();
+            let mut issues = own_issues
+                .get(node.id.as_str())
+                .cloned()
+                .unwrap_or_default();
+            let mut nodes = own_nodes.get(node.id.as_str()).copied().unwrap_or(0);
+            for child in &children {
+                nodes += child.nodes;
+                for (kind, count) in &child.issues {
+                    *issues.entry(kind.clone()).or_default() += count;
+                }
+            }
+            CallTreeNode {
+                id: node.id.clone(),
+                name: node.name.clone(),
+                nodes,
+                issues,
+                children,
+            }
+        }
+
+        self.roots
+            .iter()
+            .map(|root| build(root, &own_nodes, &own_issues))
+            .collect()
+    }
+}
+
+/// Format issue counts as e.g. "3 NonMonotone, 1 NonDet"
+pub fn format_issue_counts(issues: &BTreeMap<String, usize>) -> String {
+    let mut counts: Vec<(&String, &usize)> = issues.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    counts
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+
+    fn load(name: &str) -> HydroIr {
+        let path = format!("{}/test-data/{}", env!("CARGO_MANIFEST_DIR"), name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_replicated_kvs_hierarchy() {
+        let ir = load("replicated_kvs.json");
+        let hierarchy = CallSiteHierarchy::from_ir(&ir).unwrap();
+
+        // Every assigned call site exists in the tree
+        for site in hierarchy.assignments.values() {
+            assert!(
+                !hierarchy.path(site).is_empty(),
+                "unknown call site {}",
+                site
+            );
+        }
+
+        let path: Vec<&str> = hierarchy
+            .path("bt_20")
+            .iter()
+            .map(|n| n.id.as_str())
+            .collect();
+        assert_eq!(path, vec!["bt_14", "bt_19", "bt_20"]);
+        let stack = hierarchy.call_stack(
+            hierarchy
+                .assignments
+                .iter()
+                .find(|(_, site)| *site == "bt_20")
+                .map(|(node, _)| node.as_str())
+                .unwrap(),
+        );
+        assert_eq!(
+            &stack[stack.len() - 3..],
+            &[
+                "plumb_from_process",
+                "dispatch_from_process_with_layers",
+                "dispatch_from_process"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_issue_tree_counts_are_inclusive() {
+        let ir = load("replicated_kvs.json");
+        let results = run_analysis(&ir);
+        let hierarchy = CallSiteHierarchy::from_ir(&ir).unwrap();
+        let tree = hierarchy.issue_tree(&ir, &results);
+
+        let total_issues: usize = results
+            .node_analyses
+            .values()
+            .map(|a| a.issues.len())
+            .chain(results.edge_analyses.values().map(|a| a.issues.len()))
+            .sum();
+        let assigned_nodes = ir
+            .nodes
+            .iter()
+            .filter(|n| hierarchy.assignments.contains_key(&n.id))
+            .count();
+        assert_eq!(
+            tree.iter().map(|t| t.total_issues()).sum::<usize>(),
+            total_issues
+        );
+        assert_eq!(tree.iter().map(|t| t.nodes).sum::<usize>(), assigned_nodes);
+
+        fn check(node: &CallTreeNode) {
+            for child in &node.children {
+                for (kind, count) in &child.issues {
+                    assert!(node.issues[kind] >= *count);
+                }
+                check(child);
+            }
+        }
+        tree.iter().for_each(check);
+    }
+
+    #[test]
+    fn test_without_hierarchy() {
+        let mut b = IrBuilder::new();
+        b.node("source_stream");
+        assert!(CallSiteHierarchy::from_ir(&b.build()).is_none());
+    }
+
+    #[test]
+    fn test_names() {
+        let node = CallTreeNode {
+            id: "bt_1".to_string(),
+            name: "main → block_on → plumb_kvs_dataflow".to_string(),
+            nodes: 0,
+            issues: BTreeMap::from([("NonDet".to_string(), 1), ("NonMonotone".to_string(), 3)]),
+            children: Vec::new(),
+        };
+        assert_eq!(node.function(), "plumb_kvs_dataflow");
+        assert_eq!(node.short_name(), "… → block_on → plumb_kvs_dataflow");
+        assert_eq!(format_issue_counts(&node.issues), "3 NonMonotone, 1 NonDet");
+    }
+}
@@ -0,0 +1,612 @@
+// Self-contained HTML report
+//
+// A single file with no external resources: inline CSS, a few lines of
+// inline JavaScript for sortable tables, and an SVG drawing of the graph
+// laid out in layers. Suitable for attaching as a CI artifact.
+
+use crate::analysis::{AnalysisResult, CALM_UNSAFE, Graph, ND_LOCALLY_NONDET, witness_path};
+use crate::annotate::{
+    edge_analysis_mappings, edge_semantic_tags, node_analysis_mappings, node_semantic_tags,
+};
+use crate::hierarchy::{CallSiteHierarchy, CallTreeNode, format_issue_counts};
+use crate::model::{HydroIr, Node, SourceLocation};
+use crate::render::{LocationNames, resolve_style};
+use crate::semantics::{Monotonicity, NdEffect};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+// SVG layout constants
+const NODE_WIDTH: usize = 140;
+const NODE_HEIGHT: usize = 30;
+const COLUMN_GAP: usize = 20;
+const LAYER_GAP: usize = 50;
+const MARGIN: usize = 20;
+const MAX_SVG_LABEL: usize = 18;
+
+/// Options for the HTML report
+#[derive(Debug, Clone, Default)]
+pub struct HtmlOptions {
+    /// Prefix for source links, e.g. "../" or a repository blob URL;
+    /// links are `<prefix><file>#L<line>`
+    pub source_root: String,
+}
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.ok { color: #2e7d32; } .bad { color: #c62828; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; cursor: pointer; user-select: none; }
+th.asc::after { content: " ▲"; } th.desc::after { content: " ▼"; }
+td.num { text-align: right; }
+code { font-size: 90%; }
+details summary { cursor: pointer; }
+ol.path { margin: 0.3em 0; padding-left: 1.5em; }
+.call-tree details, .call-tree .leaf { margin-left: 1.2em; }
+.call-tree > details, .call-tree > .leaf { margin-left: 0; }
+.graph { overflow: auto; border: 1px solid #ccc; max-height: 80vh; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach(function (th) {
+  th.addEventListener("click", function () {
+    var table = th.closest("table");
+    var body = table.tBodies[0];
+    var asc = !th.classList.contains("asc");
+    table.querySelectorAll("th").forEach(function (h) { h.classList.remove("asc", "desc"); });
+    th.classList.add(asc ? "asc" : "desc");
+    var idx = Array.prototype.indexOf.call(th.parentNode.children, th);
+    var rows = Array.prototype.slice.call(body.rows);
+    rows.sort(function (a, b) {
+      var x = a.cells[idx].textContent, y = b.cells[idx].textContent;
+      // Numeric columns (IDs, counts) sort numerically
+      var nx = parseFloat(x), ny = parseFloat(y);
+      var cmp = (!isNaN(nx) && !isNaN(ny)) ? nx - ny : x.localeCompare(y);
+      return asc ? cmp : -cmp;
+    });
+    rows.forEach(function (r) { body.appendChild(r); });
+  });
+});
+"#;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn node_label(node: &Node) -> &str {
+    node.label.as_deref().unwrap_or(&node.short_label)
+}
+
+fn source_link(loc: Option<&SourceLocation>, options: &HtmlOptions) -> String {
+    match loc {
+        Some(loc) => format!(
+            "<a href=\"{}{}#L{}\">{}:{}</a>",
+            escape(&options.source_root),
+            escape(&loc.file),
+            loc.line,
+            escape(&loc.file),
+            loc.line
+        ),
+        None => "?".to_string(),
+    }
+}
+
+/// Generate the HTML report
+pub fn generate_html_report(
+    ir: &HydroIr,
+    results: &AnalysisResult,
+    options: &HtmlOptions,
+) -> String {
+    let graph = Graph::build(&ir.nodes, &ir.edges);
+    let locations = LocationNames::new(ir);
+    let location_of = |node: &Node| {
+        locations
+            .of(node)
+            .map(|(_, name)| name)
+            .unwrap_or_else(|| "(none)".to_string())
+    };
+    let source_of = |node_id: &str| {
+        results
+            .node_analyses
+            .get(node_id)
+            .and_then(|a| a.source_location.as_ref())
+    };
+
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>");
+    let _ = writeln!(html, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>Hydrolysis Analysis Report</title>");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(html, "<h1>Hydrolysis Analysis Report</h1>");
+
+    // Overall summary
+    let verdict = |ok: bool| {
+        if ok {
+            "<span class=\"ok\">✓ yes</span>"
+        } else {
+            "<span class=\"bad\">✗ no</span>"
+        }
+    };
+    let _ = writeln!(
+        html,
+        "<p>Deterministic: {} &middot; CALM safe: {} &middot; {} nodes, {} edges</p>",
+        verdict(results.overall.deterministic),
+        verdict(results.overall.calm_safe),
+        ir.nodes.len(),
+        ir.edges.len()
+    );
+
+    // Root causes
+    let _ = writeln!(html, "<h2>Root Causes</h2>");
+    let _ = writeln!(
+        html,
+        "<table class=\"sortable\" id=\"root-causes\">\n<thead><tr><th>ID</th><th>Label</th>\
+         <th>Type</th><th>Location</th><th>NonDet</th><th>NonMono</th><th>Source</th></tr></thead>\n<tbody>"
+    );
+    for node in &ir.nodes {
+        let semantics = results.semantics(node);
+        let is_nondet = semantics.nd == NdEffect::LocallyNonDet;
+        let is_nonmono = semantics.monotone == Monotonicity::Never;
+        if !is_nondet && !is_nonmono {
+            continue;
+        }
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&node.id),
+            escape(node.full_label.as_deref().unwrap_or(node_label(node))),
+            escape(&node.node_type),
+            escape(&location_of(node)),
+            if is_nondet { "✗" } else { "✓" },
+            if is_nonmono { "✗" } else { "✓" },
+            source_link(source_of(&node.id), options)
+        );
+    }
+    let _ = writeln!(html, "</tbody>\n</table>");
+
+    // Per-location summary
+    #[derive(Default)]
+    struct LocationSummary {
+        nodes: usize,
+        nd_roots: usize,
+        tainted: usize,
+        env_dependent: usize,
+        non_monotone: usize,
+        unsafe_edges: usize,
+        issues: usize,
+    }
+    let mut summaries: BTreeMap<String, LocationSummary> = BTreeMap::new();
+    let node_location: HashMap<&str, String> = ir
+        .nodes
+        .iter()
+        .map(|n| (n.id.as_str(), location_of(n)))
+        .collect();
+    for node in &ir.nodes {
+        let summary = summaries
+            .entry(node_location[node.id.as_str()].clone())
+            .or_default();
+        summary.nodes += 1;
+        if results.semantics(node).nd == NdEffect::LocallyNonDet {
+            summary.nd_roots += 1;
+        }
+        if let Some(analysis) = results.node_analyses.get(&node.id) {
+            if analysis.nd_effect == ND_LOCALLY_NONDET {
+                summary.tainted += 1;
+            }
+            if analysis.env_dependent {
+                summary.env_dependent += 1;
+            }
+            if !analysis.monotone {
+                summary.non_monotone += 1;
+            }
+            summary.issues += analysis.issues.len();
+        }
+    }
+    for edge in &ir.edges {
+        // Edges are attributed to the location they enter
+        let Some(location) = node_location.get(edge.target.as_str()) else {
+            continue;
+        };
+        if let Some(analysis) = results.edge_analyses.get(&edge.id)
+            && let Some(summary) = summaries.get_mut(location)
+        {
+            if analysis.calm == CALM_UNSAFE {
+                summary.unsafe_edges += 1;
+            }
+            summary.issues += analysis.issues.len();
+        }
+    }
+    let _ = writeln!(html, "<h2>Locations</h2>");
+    let _ = writeln!(
+        html,
+        "<table class=\"sortable\" id=\"locations\">\n<thead><tr><th>Location</th><th>Nodes</th>\
+         <th>ND roots</th><th>ND tainted</th><th>Env-dependent</th><th>Non-monotone</th><th>CalmUnsafe edges in</th>\
+         <th>Issues</th></tr></thead>\n<tbody>"
+    );
+    for (name, s) in &summaries {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td></tr>",
+            escape(name),
+            s.nodes,
+            s.nd_roots,
+            s.tainted,
+            s.env_dependent,
+            s.non_monotone,
+            s.unsafe_edges,
+            s.issues
+        );
+    }
+    let _ = writeln!(html, "</tbody>\n</table>");
+
+    // Issues with witness paths
+    let is_nd_root = |n: &Node| results.semantics(n).nd == NdEffect::LocallyNonDet;
+    let is_nonmono_root = |n: &Node| results.semantics(n).monotone == Monotonicity::Never;
+    let mut issue_rows = Vec::new();
+    for node in &ir.nodes {
+        let Some(analysis) = results.node_analyses.get(&node.id) else {
+            continue;
+        };
+        for issue in &analysis.issues {
+            issue_rows.push((node.id.clone(), issue, graph.get_idx(&node.id), None));
+        }
+    }
+    for edge in &ir.edges {
+        let Some(analysis) = results.edge_analyses.get(&edge.id) else {
+            continue;
+        };
+        for issue in &analysis.issues {
+            issue_rows.push((
+                edge.id.clone(),
+                issue,
+                graph.get_idx(&edge.source),
+                Some(edge),
+            ));
+        }
+    }
+
+    let _ = writeln!(html, "<h2>Issues</h2>");
+    let _ = writeln!(
+        html,
+        "<table class=\"sortable\" id=\"issues\">\n<thead><tr><th>Subject</th><th>Kind</th>\
+         <th>Message</th><th>Witness path</th></tr></thead>\n<tbody>"
+    );
+    for (subject, issue, start, edge) in &issue_rows {
+        let is_root: &dyn Fn(&Node) -> bool = if issue.kind == "NonMonotone" {
+            &is_nonmono_root
+        } else {
+            &is_nd_root
+        };
+        let path = start.and_then(|idx| witness_path(ir, &graph, idx, is_root, &|_, _| true));
+        let witness = match path {
+            Some(path) => {
+                let mut steps = String::new();
+                for idx in &path {
+                    let node = &ir.nodes[*idx];
+                    let _ = write!(
+                        steps,
+                        "<li><code>{}</code> {} &mdash; {}</li>",
+                        escape(&node.id),
+                        escape(node_label(node)),
+                        source_link(source_of(&node.id), options)
+                    );
+                }
+                if let Some(edge) = edge {
+                    let _ = write!(
+                        steps,
+                        "<li>edge <code>{}</code> &rarr; <code>{}</code></li>",
+                        escape(&edge.id),
+                        escape(&edge.target)
+                    );
+                }
+                format!(
+                    "<details><summary>{} steps</summary><ol class=\"path\">{}</ol></details>",
+                    path.len() + usize::from(edge.is_some()),
+                    steps
+                )
+            }
+            None => "no upstream root cause".to_string(),
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(subject),
+            escape(&issue.kind),
+            escape(&issue.message),
+            witness
+        );
+    }
+    let _ = writeln!(html, "</tbody>\n</table>");
+
+    // Call tree
+    if let Some(hierarchy) = CallSiteHierarchy::from_ir(ir) {
+        let tree: Vec<CallTreeNode> = hierarchy
+            .issue_tree(ir, results)
+            .into_iter()
+            .filter_map(CallTreeNode::prune)
+            .collect();
+        if !tree.is_empty() {
+            let _ = writeln!(html, "<h2>Issues by Call Site</h2>");
+            let _ = writeln!(html, "<div class=\"call-tree\">");
+            for node in &tree {
+                push_call_tree(&mut html, node, true);
+            }
+            let _ = writeln!(html, "</div>");
+        }
+    }
+
+    // Graph
+    let _ = writeln!(html, "<h2>Graph</h2>");
+    let _ = writeln!(
+        html,
+        "<div class=\"graph\">\n{}</div>",
+        graph_svg(ir, results, &graph)
+    );
+
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    html
+}
+
+fn push_call_tree(html: &mut String, node: &CallTreeNode, open: bool) {
+    let summary = format!(
+        "<span title=\"{}\"><code>{}</code></span> &mdash; {} ({} nodes)",
+        escape(&node.name),
+        escape(&node.short_name()),
+        escape(&format_issue_counts(&node.issues)),
+        node.nodes
+    );
+    if node.children.is_empty() {
+        let _ = writeln!(html, "<div class=\"leaf\">{}</div>", summary);
+        return;
+    }
+    let _ = writeln!(
+        html,
+        "<details{}><summary>{}</summary>",
+        if open { " open" } else { "" },
+        summary
+    );
+    for child in &node.children {
+        push_call_tree(html, child, false);
+    }
+    let _ = writeln!(html, "</details>");
+}
+
+/// Longest-path layering over the graph with back edges (found by DFS) ignored
+fn layers(graph: &Graph, n: usize) -> Vec<usize> {
+    // Iterative DFS producing a postorder and marking back edges by edge position
+    let mut state = vec![0u8; n]; // 0 = unvisited, 1 = on stack, 2 = done
+    let mut postorder = Vec::with_capacity(n);
+    let mut back_edges: std::collections::HashSet<(usize, usize)> = Default::default();
+    for start in 0..n {
+        if state[start] != 0 {
+            continue;
+        }
+        let mut stack = vec![(start, 0usize)];
+        state[start] = 1;
+        while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+            if let Some(&(succ, _)) = graph.forward[node].get(*next) {
+                *next += 1;
+                match state[succ] {
+                    0 => {
+                        state[succ] = 1;
+                        stack.push((succ, 0));
+                    }
+                    1 => {
+                        back_edges.insert((node, succ));
+                    }
+                    _ => {}
+                }
+            } else {
+                state[node] = 2;
+                postorder.push(node);
+                stack.pop();
+            }
+        }
+    }
+
+    let mut layer = vec![0; n];
+    for &node in postorder.iter().rev() {
+        for &(succ, _) in &graph.forward[node] {
+            if !back_edges.contains(&(node, succ)) {
+                layer[succ] = layer[succ].max(layer[node] + 1);
+            }
+        }
+    }
+    layer
+}
+
+fn graph_svg(ir: &HydroIr, results: &AnalysisResult, graph: &Graph) -> String {
+    let n = ir.nodes.len();
+    let layer = layers(graph, n);
+    let num_layers = layer.iter().copied().max().map_or(0, |m| m + 1);
+
+    // Order nodes within each layer by the average column of their predecessors
+    let mut column = vec![0usize; n];
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new(); num_layers];
+    for (l, row) in rows.iter_mut().enumerate() {
+        let mut members: Vec<(f64, usize)> = (0..n)
+            .filter(|&i| layer[i] == l)
+            .map(|i| {
+                let preds: Vec<usize> = graph.backward[i]
+                    .iter()
+                    .filter(|(p, _)| layer[*p] < l)
+                    .map(|(p, _)| column[*p])
+                    .collect();
+                let key = if preds.is_empty() {
+                    i as f64
+                } else {
+                    preds.iter().sum::<usize>() as f64 / preds.len() as f64
+                };
+                (key, i)
+            })
+            .collect();
+        members.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        for (col, &(_, i)) in members.iter().enumerate() {
+            column[i] = col;
+        }
+        *row = members.into_iter().map(|(_, i)| i).collect();
+    }
+
+    let widest = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let width = 2 * MARGIN + widest * (NODE_WIDTH + COLUMN_GAP);
+    let height = 2 * MARGIN + num_layers * (NODE_HEIGHT + LAYER_GAP);
+    let x = |i: usize| MARGIN + column[i] * (NODE_WIDTH + COLUMN_GAP);
+    let y = |i: usize| MARGIN + layer[i] * (NODE_HEIGHT + LAYER_GAP);
+
+    let node_mappings = node_analysis_mappings();
+    let edge_mappings = edge_analysis_mappings();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-size=\"11\" font-family=\"monospace\">",
+        width, height
+    );
+    let _ = writeln!(
+        svg,
+        "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"6\" markerHeight=\"6\" orient=\"auto\"><path d=\"M0,0 L10,5 L0,10 z\" fill=\"#555\"/></marker></defs>"
+    );
+
+    for edge in &ir.edges {
+        let (Some(src), Some(dst)) = (graph.get_idx(&edge.source), graph.get_idx(&edge.target))
+        else {
+            continue;
+        };
+        let style = resolve_style(
+            &edge_semantic_tags(edge, results.edge_analyses.get(&edge.id)),
+            &edge_mappings,
+        );
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\"{} marker-end=\"url(#arrow)\"><title>{}</title></line>",
+            x(src) + NODE_WIDTH / 2,
+            y(src) + NODE_HEIGHT,
+            x(dst) + NODE_WIDTH / 2,
+            y(dst),
+            style.color.unwrap_or("#555"),
+            style.width.unwrap_or(1),
+            if style.dashed {
+                " stroke-dasharray=\"5,4\""
+            } else {
+                ""
+            },
+            escape(&format!(
+                "{}: {} → {}{}",
+                edge.id,
+                edge.source,
+                edge.target,
+                results
+                    .edge_analyses
+                    .get(&edge.id)
+                    .map(|a| format!(" ({})", a.calm))
+                    .unwrap_or_default()
+            ))
+        );
+    }
+
+    for (i, node) in ir.nodes.iter().enumerate() {
+        let style = resolve_style(&node_semantic_tags(node, results), &node_mappings);
+        let label = node_label(node);
+        let text = if label.chars().count() > MAX_SVG_LABEL {
+            format!(
+                "{}…",
+                label.chars().take(MAX_SVG_LABEL - 1).collect::<String>()
+            )
+        } else {
+            label.to_string()
+        };
+        let _ = writeln!(
+            svg,
+            "<g><title>{}</title><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"5\" fill=\"{}\" stroke=\"#333\" stroke-width=\"{}\"{}/><text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{} {}</text></g>",
+            escape(&format!(
+                "{} {} ({})",
+                node.id,
+                node.full_label.as_deref().unwrap_or(label),
+                node.node_type
+            )),
+            x(i),
+            y(i),
+            NODE_WIDTH,
+            NODE_HEIGHT,
+            style.color.unwrap_or("#ffffff"),
+            style.width.unwrap_or(1),
+            if style.dashed {
+                " stroke-dasharray=\"4,3\""
+            } else {
+                ""
+            },
+            x(i) + NODE_WIDTH / 2,
+            y(i) + NODE_HEIGHT / 2 + 4,
+            escape(&text),
+            style.badge.as_deref().unwrap_or("")
+        );
+    }
+
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::backtrace::Frame;
+    use crate::builder::IrBuilder;
+
+    #[test]
+    fn test_html_report_is_self_contained() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_stream", 0, "Process");
+        let nondet = b.node_at("observenondet", 1, "Cluster");
+        let map = b.node_at("map", 1, "Cluster");
+        let sink = b.node_at("for_each", 1, "Cluster");
+        b.set_backtrace(&nondet, vec![Frame::new("examples/kvs.rs", "main", 42)]);
+        b.edge(&src, &nondet, &["Network", "Unbounded"]);
+        b.edge(&nondet, &map, &["Local"]);
+        b.edge(&map, &sink, &["Local"]);
+        // A cycle must not break the layout
+        b.edge(&sink, &map, &["Local"]);
+        let ir = b.build();
+        let results = run_analysis(&ir);
+
+        let html = generate_html_report(
+            &ir,
+            &results,
+            &HtmlOptions {
+                source_root: "../".to_string(),
+            },
+        );
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.trim_end().ends_with("</html>"));
+        // Nothing fetched from the network
+        assert!(!html.contains("src=\"http"));
+        assert!(!html.contains("<link"));
+        assert!(html.contains("<svg"));
+        assert!(html.contains("<a href=\"../examples/kvs.rs#L42\">examples/kvs.rs:42</a>"));
+        assert!(html.contains("<td>Cluster 1</td>"));
+        // map inherits nondeterminism; its witness path starts at observenondet
+        assert!(html.contains("<details><summary>2 steps</summary>"));
+        assert_eq!(html.matches("<table class=\"sortable\"").count(), 3);
+    }
+
+    #[test]
+    fn test_layers_ignore_back_edges() {
+        let mut b = IrBuilder::new();
+        let a = b.node("source_stream");
+        let c = b.node("map");
+        let d = b.node("for_each");
+        b.edge(&a, &c, &["Local"]);
+        b.edge(&c, &d, &["Local"]);
+        b.edge(&d, &c, &["Local"]);
+        let ir = b.build();
+        let graph = Graph::build(&ir.nodes, &ir.edges);
+        assert_eq!(layers(&graph, ir.nodes.len()), vec![0, 1, 2]);
+    }
+}
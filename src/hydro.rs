@@ -0,0 +1,493 @@
+// In-process conversion of `hydro_lang` IR
+//
+// With the `hydro_lang` feature, a flow can be analyzed straight from its
+// in-memory IR instead of a `hydro_lang::viz` export. The converter walks the
+// `HydroLeaf` / `HydroNode` trees and rebuilds them with `IrBuilder`, so every
+// pass sees the same `Node` / `Edge` model as for a parsed export. Unlike the
+// export it keeps each operator's full expression, its output type on the
+// edges it feeds, and the identity of cycles: a `cycle_sink` gets an edge to
+// the `cycle_source` with the same identifier, so taint flows around loops.
+
+use crate::analysis::AnalysisResult;
+use crate::backtrace::Frame;
+use crate::builder::IrBuilder;
+use crate::model::{HydroIr, Port};
+use crate::pass::PassRegistry;
+use anyhow::Result;
+#[cfg(test)]
+use hydro_lang::FlowBuilder;
+use hydro_lang::backtrace::Backtrace;
+use hydro_lang::builder::built::BuiltFlow;
+use hydro_lang::ir::{HydroIrMetadata, HydroLeaf, HydroNode, SeenTees};
+use hydro_lang::location::LocationId;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Release whose IR is converted, recorded so the matching profile is used
+const HYDRO_LANG_VERSION: &str = "0.14";
+
+/// `CamelCase` variant name to the `snake_case` label used by exports
+fn snake_case(name: &str) -> String {
+    let mut label = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                label.push('_');
+            }
+            label.extend(c.to_lowercase());
+        } else {
+            label.push(c);
+        }
+    }
+    label
+}
+
+/// Operator label from `print_root`, which renders a node as `Name(args)`,
+/// spelled as in 0.14 exports
+///
+/// Operators are lowercased IR node names (`FoldKeyed` is `foldkeyed`).
+/// Sources and sinks are `snake_case` and sources are named after their
+/// kind: `Source(Iter(..))` is `source_iter` and `Source(ExternalNetwork())`
+/// is `external_input`.
+fn label_of(printed: &str) -> String {
+    let (name, args) = printed.split_once('(').unwrap_or((printed, ""));
+    let name = name.trim();
+    let label = snake_case(name);
+    match label.as_str() {
+        "source" => {}
+        "cycle_source" | "external_input" | "for_each" | "send_external" | "dest_sink"
+        | "cycle_sink" => return label,
+        _ => return name.to_lowercase(),
+    }
+    let kind: String = args
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    match snake_case(&kind).as_str() {
+        "external_network" => "external_input".to_string(),
+        "" => label,
+        kind => format!("source_{}", kind),
+    }
+}
+
+/// The input ports of an operator, in the order of its children
+fn ports(label: &str) -> &'static [Port] {
+    match label {
+        "difference" | "antijoin" => &[Port::Pos, Port::Neg],
+        "chain" | "crossproduct" => &[Port::First, Port::Second],
+        "join" | "crosssingleton" => &[Port::Left, Port::Right],
+        _ => &[],
+    }
+}
+
+/// Location id and kind (`Process` or `Cluster`); a tick is placed at the
+/// location it runs on
+fn location(id: &LocationId) -> (usize, &'static str) {
+    match id {
+        LocationId::Process(id) => (*id, "Process"),
+        LocationId::Cluster(id) => (*id, "Cluster"),
+        LocationId::Tick(_, inner) => location(inner),
+    }
+}
+
+/// Whether a collection lives inside a tick, where it is bounded
+fn in_tick(id: &LocationId) -> bool {
+    matches!(id, LocationId::Tick(..))
+}
+
+const TOTAL_ORDER: &str = "TotalOrder";
+const NO_ORDER: &str = "NoOrder";
+
+/// Ordering of the collection `node` produces, given the orderings of its
+/// inputs in the order of its children
+///
+/// The IR only records element types, so this follows the signatures of the
+/// `Stream` methods that build each node. Singletons are totally ordered, as
+/// in exports. The type of a `forward_ref` cycle is not recorded, so a cycle
+/// source is assumed unordered.
+fn order_of(node: &HydroNode, inputs: &[(String, &'static str, HydroIrMetadata)]) -> &'static str {
+    let input = |i: usize| inputs.get(i).map_or(NO_ORDER, |(_, order, _)| *order);
+    match node {
+        HydroNode::Source { .. }
+        | HydroNode::ExternalInput { .. }
+        | HydroNode::Sort { .. }
+        | HydroNode::Fold { .. }
+        | HydroNode::Reduce { .. }
+        | HydroNode::FoldKeyed { .. }
+        | HydroNode::ReduceKeyed { .. } => TOTAL_ORDER,
+        // `union` chains two batches and drops their order, which the IR
+        // can't tell apart from `chain`, so every chain is unordered
+        HydroNode::CycleSource { .. }
+        | HydroNode::Chain { .. }
+        | HydroNode::Join { .. }
+        | HydroNode::CrossProduct { .. }
+        | HydroNode::ResolveFutures { .. } => NO_ORDER,
+        // Members of a cluster send concurrently, so their messages interleave
+        HydroNode::Network { .. }
+            if inputs
+                .iter()
+                .any(|(_, _, m)| matches!(m.location_kind.root(), LocationId::Cluster(_))) =>
+        {
+            NO_ORDER
+        }
+        // Everything else keeps the order of its first input
+        _ => input(0),
+    }
+}
+
+struct Converter {
+    builder: IrBuilder,
+    /// Node id and ordering of each tee's shared input, by the address of
+    /// its cell
+    tees: HashMap<*const RefCell<HydroNode>, (String, &'static str)>,
+    cycle_sources: BTreeMap<String, Vec<String>>,
+    cycle_sinks: BTreeMap<String, Vec<String>>,
+}
+
+impl Converter {
+    fn new() -> Self {
+        Converter {
+            builder: IrBuilder::new(),
+            tees: HashMap::new(),
+            cycle_sources: BTreeMap::new(),
+            cycle_sinks: BTreeMap::new(),
+        }
+    }
+
+    /// Add an operator, returning its id and label
+    fn operator(
+        &mut self,
+        printed: &str,
+        location_kind: &LocationId,
+        backtrace: Option<&Backtrace>,
+    ) -> (String, String) {
+        let label = label_of(printed);
+        let (loc, kind) = location(location_kind);
+        let id = self.builder.node_at(&label, loc, kind);
+        self.builder.set_full_label(&id, printed);
+        let frames: Vec<Frame> = backtrace
+            .map(Backtrace::elements)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|element| Frame {
+                file: element.filename.unwrap_or_default(),
+                function: Some(element.fn_name),
+                line: element.lineno,
+                column: None,
+            })
+            .collect();
+        if !frames.is_empty() {
+            self.builder.set_backtrace(&id, frames);
+        }
+        (id, label)
+    }
+
+    /// Add the edge from a child operator into `target`, tagged like an
+    /// export: whether it crosses the network, how the child's output is
+    /// ordered and whether it is bounded
+    fn input(
+        &mut self,
+        source: &str,
+        order: &'static str,
+        child: &HydroIrMetadata,
+        target: &str,
+        target_label: &str,
+        port: Option<Port>,
+    ) {
+        let scope = if target_label == "network" {
+            "Network"
+        } else {
+            "Local"
+        };
+        let bounded = if in_tick(&child.location_kind) {
+            "Bounded"
+        } else {
+            "Unbounded"
+        };
+        let tags = [scope, order, bounded];
+        match (port, &child.output_type) {
+            (Some(port), _) => {
+                self.builder.port_edge(source, target, &tags, port.as_str());
+            }
+            (None, Some(ty)) => {
+                self.builder
+                    .typed_edge(source, target, &tags, &format!("{:?}", ty));
+            }
+            (None, None) => {
+                self.builder.edge(source, target, &tags);
+            }
+        }
+    }
+
+    /// Convert `node` and everything upstream of it, returning its id and
+    /// the ordering of its output
+    fn node(&mut self, node: &mut HydroNode) -> (String, &'static str) {
+        if let HydroNode::Tee { inner, metadata } = node {
+            // Every tee of the same cell reads one shared operator
+            let key = std::rc::Rc::as_ptr(&inner.0);
+            let (shared, order) = match self.tees.get(&key) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let shared = self.node(&mut inner.0.borrow_mut());
+                    self.tees.insert(key, shared.clone());
+                    shared
+                }
+            };
+            let (id, label) =
+                self.operator("Tee()", &metadata.location_kind, Some(&metadata.backtrace));
+            let input = inner.0.borrow().metadata().clone();
+            self.input(&shared, order, &input, &id, &label, None);
+            return (id, order);
+        }
+
+        let metadata = node.metadata();
+        let (id, label) = self.operator(
+            &node.print_root(),
+            &metadata.location_kind,
+            Some(&metadata.backtrace),
+        );
+        if let HydroNode::CycleSource { ident, .. } = node {
+            self.cycle_sources
+                .entry(ident.to_string())
+                .or_default()
+                .push(id.clone());
+        }
+
+        let mut children = Vec::new();
+        node.transform_children(
+            |child, _| {
+                let (id, order) = self.node(child);
+                children.push((id, order, child.metadata().clone()));
+            },
+            &mut SeenTees::default(),
+        );
+        let ports = ports(&label);
+        for (i, (child, order, metadata)) in children.iter().enumerate() {
+            self.input(child, order, metadata, &id, &label, ports.get(i).copied());
+        }
+        (id, order_of(node, &children))
+    }
+
+    fn leaf(&mut self, leaf: &mut HydroLeaf) {
+        // `SendExternal` has no metadata of its own and runs where its input does
+        let (location_kind, backtrace) = match leaf {
+            HydroLeaf::SendExternal { input, .. } => (input.metadata().location_kind.clone(), None),
+            _ => {
+                let metadata = leaf.metadata();
+                (metadata.location_kind.clone(), Some(&metadata.backtrace))
+            }
+        };
+        let (id, label) = self.operator(&leaf.print_root(), &location_kind, backtrace);
+        if let HydroLeaf::CycleSink { ident, .. } = leaf {
+            self.cycle_sinks
+                .entry(ident.to_string())
+                .or_default()
+                .push(id.clone());
+        }
+        let mut inputs = Vec::new();
+        leaf.transform_children(
+            |child, _| {
+                let (id, order) = self.node(child);
+                inputs.push((id, order, child.metadata().clone()));
+            },
+            &mut SeenTees::default(),
+        );
+        for (input, order, metadata) in &inputs {
+            self.input(input, order, metadata, &id, &label, None);
+        }
+    }
+
+    fn finish(mut self) -> HydroIr {
+        for (ident, sinks) in &self.cycle_sinks {
+            for source in self.cycle_sources.get(ident).into_iter().flatten() {
+                for sink in sinks {
+                    self.builder.edge(sink, source, &["Local", "Cycle"]);
+                }
+            }
+        }
+        let mut ir = self.builder.build();
+        ir.metadata = Some(serde_json::json!({ "hydroLangVersion": HYDRO_LANG_VERSION }));
+        ir
+    }
+}
+
+/// Convert the IR of a flow into the model every pass works on
+///
+/// The leaves are walked on a copy, since walking a tree needs it mutably.
+pub fn convert(leaves: &[HydroLeaf]) -> HydroIr {
+    let mut leaves = hydro_lang::ir::deep_clone(leaves);
+    let mut converter = Converter::new();
+    for leaf in &mut leaves {
+        converter.leaf(leaf);
+    }
+    converter.finish()
+}
+
+/// Analyze a flow in-process with the built-in passes
+///
+/// A `FlowBuilder` only exposes its IR once finalized, so this takes the
+/// result of `finalize()`, which can still be deployed afterwards.
+pub fn check_flow(flow: &BuiltFlow<'_>) -> Result<AnalysisResult> {
+    PassRegistry::with_builtin_passes().run(&convert(flow.ir()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hydro_lang::{Location, NoOrder, Process, Stream, Unbounded, q};
+
+    #[test]
+    fn test_labels_match_exports() {
+        assert_eq!(label_of("Map(| x | x + 1)"), "map");
+        assert_eq!(
+            label_of("FoldKeyed(| | 0, | acc, x | *acc += x)"),
+            "foldkeyed"
+        );
+        assert_eq!(
+            label_of("CrossSingleton(Map(f), Fold(i, a))"),
+            "crosssingleton"
+        );
+        assert_eq!(label_of("CycleSource(cycle_0)"), "cycle_source");
+        assert_eq!(label_of("Source(Iter([1, 2, 3]))"), "source_iter");
+        assert_eq!(label_of("Source(ExternalNetwork())"), "external_input");
+        assert_eq!(label_of("ForEach(| x | println!(\"{}\", x))"), "for_each");
+        assert_eq!(label_of("CycleSink(cycle_0)"), "cycle_sink");
+        assert_eq!(ports("antijoin"), &[Port::Pos, Port::Neg]);
+    }
+
+    #[test]
+    fn test_check_flow() {
+        let flow = FlowBuilder::new();
+        let leader = flow.process::<()>();
+        let follower = flow.process::<()>();
+        let (complete, cycled) =
+            follower.forward_ref::<Stream<u32, Process<'_, ()>, Unbounded, NoOrder>>();
+        let shared = leader
+            .source_iter(q!(vec![1u32, 2, 3]))
+            .send_bincode::<_, u32>(&follower)
+            .union(cycled)
+            .map(q!(|x| x + 1));
+        complete.complete(shared.clone().filter(q!(|x| *x < 10)));
+        let tick = follower.tick();
+        unsafe { shared.tick_batch(&tick) }
+            .fold_commutative(q!(|| 0u32), q!(|acc, x| *acc += x))
+            .into_stream()
+            .all_ticks()
+            .for_each(q!(|x| println!("{}", x)));
+        let built = flow.finalize();
+
+        let ir = convert(built.ir());
+        let with_label = |label: &str| -> Vec<&str> {
+            ir.nodes
+                .iter()
+                .filter(|n| n.label.as_deref() == Some(label))
+                .map(|n| n.id.as_str())
+                .collect()
+        };
+        let edge = |source: &str, target: &str| {
+            ir.edges
+                .iter()
+                .find(|e| e.source == source && e.target == target)
+                .unwrap()
+        };
+        let tags = |source: &str, target: &str| edge(source, target).semantic_tags.clone().unwrap();
+
+        // Both clones of `shared` read the one `map` through their own tee
+        let map = with_label("map")[0];
+        let tees = with_label("tee");
+        assert_eq!(with_label("map").len(), 1);
+        assert_eq!(tees.len(), 2);
+        for tee in &tees {
+            assert_eq!(edge(map, tee).label.as_deref(), Some("u32"));
+        }
+        // The cycle is closed by identifier
+        let (sink, source) = (with_label("cycle_sink")[0], with_label("cycle_source")[0]);
+        assert_eq!(tags(sink, source), vec!["Local", "Cycle"]);
+        // `union` is a chain of two tick batches
+        let chain = with_label("chain")[0];
+        let ports: Vec<_> = ir
+            .edges
+            .iter()
+            .filter(|e| e.target == chain)
+            .map(|e| e.label.clone().unwrap())
+            .collect();
+        assert_eq!(ports, vec!["first", "second"]);
+        assert!(ir.edges.iter().filter(|e| e.target == chain).all(|e| {
+            e.semantic_tags
+                .as_ref()
+                .unwrap()
+                .contains(&"Bounded".to_string())
+        }));
+        // Messages between two processes keep their order
+        let network = with_label("network")[0];
+        let send = &ir.edges.iter().find(|e| e.target == network).unwrap();
+        assert_eq!(
+            send.semantic_tags.clone().unwrap(),
+            vec!["Network", "TotalOrder", "Unbounded"]
+        );
+        assert_eq!(
+            tags(with_label("source_iter")[0], &send.source)[1],
+            "TotalOrder"
+        );
+
+        let results = check_flow(&built).unwrap();
+        assert_eq!(results.node_analyses.len(), ir.nodes.len());
+        assert_eq!(
+            results.edge_analyses[&send.id].pass_results["delivery"]["model"],
+            "fifo"
+        );
+        // Taint from the network flows around the cycle
+        assert!(results.node_analyses[source].env_dependent);
+        let sinks: Vec<_> = results.verdicts.iter().map(|v| v.label.as_str()).collect();
+        assert_eq!(sinks, vec!["cycle_sink", "for_each"]);
+    }
+
+    #[test]
+    fn test_labels_known_to_profile() {
+        let profile = crate::semantics::detect_profile(&Converter::new().finish());
+        assert_eq!(profile.name, "hydro_lang-0.14");
+        for printed in [
+            "Source(Iter(v))",
+            "Source(Stream(s))",
+            "Source(Spin())",
+            "Source(ExternalNetwork())",
+            "CycleSource(c)",
+            "ExternalInput()",
+            "Tee()",
+            "Persist()",
+            "Unpersist()",
+            "Delta()",
+            "Chain(a, b)",
+            "CrossProduct(a, b)",
+            "CrossSingleton(a, b)",
+            "Join(a, b)",
+            "Difference(a, b)",
+            "AntiJoin(a, b)",
+            "ResolveFutures()",
+            "ResolveFuturesOrdered()",
+            "Map(f)",
+            "FlatMap(f)",
+            "Filter(f)",
+            "FilterMap(f)",
+            "DeferTick()",
+            "Enumerate(false)",
+            "Inspect(f)",
+            "Unique()",
+            "Sort()",
+            "Fold(i, a)",
+            "Scan(i, a)",
+            "FoldKeyed(i, a)",
+            "Reduce(f)",
+            "ReduceKeyed(f)",
+            "Network()",
+            "Counter(t, d)",
+            "ForEach(f)",
+            "SendExternal",
+            "DestSink(s)",
+            "CycleSink(c)",
+        ] {
+            let label = label_of(printed);
+            assert!(profile.semantics_by_label(&label).is_some(), "{}", label);
+        }
+    }
+}
@@ -3,6 +3,29 @@
 
 pub mod analysis;
 pub mod annotate;
+pub mod backtrace;
+pub mod boundedness;
+pub mod builder;
+pub mod cache;
+pub mod config;
+pub mod convergence;
+pub mod delivery;
+pub mod environment;
+pub mod facts;
+pub mod hierarchy;
+pub mod html;
+#[cfg(feature = "hydro_lang")]
+pub mod hydro;
 pub mod model;
+pub mod parallel;
+pub mod pass;
+pub mod reach;
+pub mod render;
 pub mod report;
+pub mod rules;
 pub mod semantics;
+pub mod validate;
+pub mod verdict;
+
+#[cfg(feature = "hydro_lang")]
+pub use hydro::check_flow;
@@ -1,6 +1,11 @@
 // Data structures for Hydro IR JSON input/output
 
+use crate::backtrace::{Backtrace, FrameFilter};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Per-pass results attached to a node or edge, keyed by pass name then result key
+pub type PassResults = BTreeMap<String, BTreeMap<String, serde_json::Value>>;
 
 /// Input structure matching hydro_lang::viz output
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -19,6 +24,9 @@ pub struct HydroIr {
     pub node_type_config: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub legend: Option<serde_json::Value>,
+    /// Export metadata, e.g. `{"hydroLangVersion": "0.14.0"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,7 +50,8 @@ pub struct NodeData {
     pub location_id: Option<usize>,
     #[serde(rename = "locationType")]
     pub location_type: Option<String>,
-    pub backtrace: serde_json::Value,
+    #[serde(default)]
+    pub backtrace: Backtrace,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -63,9 +72,27 @@ pub struct Edge {
 pub struct NodeAnalysis {
     pub nd_effect: String,
     pub monotone: bool,
+    /// Provenance of the operator semantics, e.g. "label" or "node_type"
+    #[serde(default)]
+    pub semantics_source: String,
+    /// Downstream of an environment origin, whatever `nd_effect` says
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub env_dependent: bool,
+    /// Kinds of nondeterminism reaching this operator, e.g. "ordering"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nd_kinds: Vec<String>,
+    /// Why nondeterminism flowing into this operator stops here, if it does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed: Option<String>,
     pub issues: Vec<Issue>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_location: Option<SourceLocation>,
+    /// User frames of the operator's backtrace, innermost first; only
+    /// filled in with `full_backtrace` set in the configuration
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backtrace: Vec<SourceLocation>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pass_results: PassResults,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,80 +108,191 @@ pub struct EdgeAnalysis {
     pub is_lattice: bool,
     pub calm: String,
     pub issues: Vec<Issue>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pass_results: PassResults,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Issue {
     pub kind: String,
+    /// ID of the node or edge at fault
+    pub subject: String,
     pub message: String,
+    /// CALM-critical edges made unsafe by this root cause, in input order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub affected_critical_edges: Vec<String>,
+}
+
+impl Issue {
+    /// Order of issues in output: by kind, then by subject and message with
+    /// runs of digits compared as numbers, so issues naming edge `e2` come
+    /// before those naming `e10`
+    pub fn canonical_cmp(&self, other: &Issue) -> std::cmp::Ordering {
+        self.kind
+            .cmp(&other.kind)
+            .then_with(|| natural_cmp(&self.subject, &other.subject))
+            .then_with(|| natural_cmp(&self.message, &other.message))
+    }
+}
+
+/// Compare strings with runs of ASCII digits compared by numeric value
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (da, db) = (digits(a), digits(b));
+                let trim = |s: &[u8]| {
+                    let zeros = s.iter().take_while(|&&c| c == b'0').count();
+                    s[zeros..].to_vec()
+                };
+                let (na, nb) = (trim(&a[..da]), trim(&b[..db]));
+                let order = na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                // Equal values: fewer leading zeros first, so distinct strings never tie
+                if da != db {
+                    return da.cmp(&db);
+                }
+                a = &a[da..];
+                b = &b[db..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OverallAnalysis {
     pub deterministic: bool,
     pub calm_safe: bool,
+    /// No operator is locally nondeterministic (the program is not racy),
+    /// though outputs may still depend on the environment
+    #[serde(default)]
+    pub deterministic_modulo_environment: bool,
+    /// Some output depends on the environment (inputs, arrival order, timers)
+    #[serde(default)]
+    pub environment_dependent: bool,
+}
+
+/// Consistency guarantee of one output operator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SinkVerdict {
+    /// Node ID of the sink
+    pub sink: String,
+    pub label: String,
+    /// `Deterministic`, `EventuallyConsistent`, `EnvironmentDependent` or `Nondeterministic`
+    pub verdict: String,
+    pub reason: String,
+    /// Node IDs from the cause of the verdict to the sink
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub witness: Vec<String>,
+    /// Upstream operators that sealed nondeterminism on its way here
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sealed_by: Vec<String>,
+}
+
+/// Whether the replicas of one cluster location converge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocationConvergence {
+    pub location: usize,
+    /// `Converges`, `MayDiverge` or `NoReplicatedState`
+    pub verdict: String,
+    /// Node IDs of the network-fed state on this location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<String>,
+    /// Why replicas may diverge, one entry per offending state
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
 }
 
 impl Node {
-    /// Extract the most relevant source location from the backtrace.
-    /// Prefers user code over framework code (filters out hydro_lang internals).
+    /// Extract the most relevant source location from the backtrace
+    /// using the default frame filter (skips Hydro framework internals)
     pub fn extract_source_location(&self) -> Option<SourceLocation> {
-        let backtrace = self.data.as_ref()?.backtrace.as_array()?;
-
-        // First try to find user code (not in hydro_lang or std)
-        for frame in backtrace {
-            if let Some(file) = frame.get("file").and_then(|f| f.as_str()) {
-                // Skip framework internals
-                if file.contains("hydro_lang")
-                    || file.contains("dfir_")
-                    || file.contains("stageleft")
-                    || file.starts_with("src/")
-                        && (file.contains("location/")
-                            || file.contains("compile/")
-                            || file.contains("live_collections/"))
-                {
-                    continue;
-                }
+        self.extract_source_location_with(&FrameFilter::default())
+    }
 
-                let line = frame
-                    .get("line")
-                    .or_else(|| frame.get("lineNumber"))
-                    .and_then(|l| l.as_u64())
-                    .map(|l| l as u32)?;
-
-                let function = frame
-                    .get("function")
-                    .or_else(|| frame.get("fn"))
-                    .and_then(|f| f.as_str())
-                    .map(|s| s.to_string());
-
-                return Some(SourceLocation {
-                    file: file.to_string(),
-                    line,
-                    function,
-                });
-            }
+    /// Extract the most relevant source location using a custom frame filter
+    pub fn extract_source_location_with(&self, filter: &FrameFilter) -> Option<SourceLocation> {
+        filter.select(&self.data.as_ref()?.backtrace)
+    }
+
+    /// The user frames of the backtrace, innermost first
+    pub fn user_backtrace(&self, filter: &FrameFilter) -> Vec<SourceLocation> {
+        self.data
+            .as_ref()
+            .map(|d| filter.filter(&d.backtrace))
+            .unwrap_or_default()
+    }
+}
+
+/// Input port of a binary operator, named by the label of the edge feeding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Port {
+    Left,
+    Right,
+    First,
+    Second,
+    /// Positive input of a `difference` / `anti_join`
+    Pos,
+    /// Negated input of a `difference` / `anti_join`
+    Neg,
+}
+
+impl Port {
+    pub const ALL: [Port; 6] = [
+        Port::Left,
+        Port::Right,
+        Port::First,
+        Port::Second,
+        Port::Pos,
+        Port::Neg,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Port::Left => "left",
+            Port::Right => "right",
+            Port::First => "first",
+            Port::Second => "second",
+            Port::Pos => "pos",
+            Port::Neg => "neg",
         }
+    }
 
-        // Fallback: use the first frame if no user code found
-        let frame = backtrace.first()?;
-        let file = frame.get("file").and_then(|f| f.as_str())?.to_string();
-        let line = frame
-            .get("line")
-            .or_else(|| frame.get("lineNumber"))
-            .and_then(|l| l.as_u64())
-            .map(|l| l as u32)?;
-        let function = frame
-            .get("function")
-            .or_else(|| frame.get("fn"))
-            .and_then(|f| f.as_str())
-            .map(|s| s.to_string());
-
-        Some(SourceLocation {
-            file,
-            line,
-            function,
-        })
+    /// The port an edge label names, if it names one
+    pub fn parse(label: &str) -> Option<Port> {
+        Port::ALL.into_iter().find(|port| port.as_str() == label)
+    }
+}
+
+impl std::fmt::Display for Port {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Edge {
+    /// The input port of its target this edge feeds, from its label
+    ///
+    /// Labels that aren't port names (e.g. `to Some(2)` on network edges, or
+    /// a value type) give None.
+    pub fn port(&self) -> Option<Port> {
+        Port::parse(self.label.as_deref()?)
     }
 }
 
@@ -199,6 +337,10 @@ pub struct AnnotatedHydroIr {
     pub edges: Vec<AnnotatedEdge>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub overall: Option<OverallAnalysis>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verdicts: Vec<SinkVerdict>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub convergence: Vec<LocationConvergence>,
     #[serde(rename = "hierarchyChoices", skip_serializing_if = "Option::is_none")]
     pub hierarchy_choices: Option<serde_json::Value>,
     #[serde(rename = "nodeAssignments", skip_serializing_if = "Option::is_none")]
@@ -211,11 +353,15 @@ pub struct AnnotatedHydroIr {
     pub node_type_config: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub legend: Option<serde_json::Value>,
+    /// Export metadata, e.g. `{"hydroLangVersion": "0.14.0"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::backtrace::Frame;
     use proptest::prelude::*;
 
     // **Feature: hydro-static-analysis, Property 1: JSON Round-Trip Preservation**
@@ -235,7 +381,7 @@ pub mod tests {
             data: Some(NodeData {
                 location_id: Some(0),
                 location_type: Some("Process".to_string()),
-                backtrace: serde_json::json!([]),
+                backtrace: Backtrace::default(),
             }),
         }
     }
@@ -251,17 +397,52 @@ pub mod tests {
         }
     }
 
+    // Strategy for generating backtrace frames
+    pub fn arb_frame() -> impl Strategy<Value = Frame> {
+        (
+            prop::string::string_regex("[a-z_/]+\\.rs").unwrap(),
+            prop::option::of(prop::string::string_regex("[a-z_{}]+").unwrap()),
+            prop::option::of(any::<u32>()),
+            prop::option::of(any::<u32>()),
+        )
+            .prop_map(|(file, function, line, column)| Frame {
+                file,
+                function,
+                line,
+                column,
+            })
+    }
+
+    // Strategy for generating backtraces as found in exports: mostly valid
+    // frames, but also entries that are not frames and values that are not
+    // arrays, all of which must be accepted
+    pub fn arb_backtrace() -> impl Strategy<Value = Backtrace> {
+        let entry = prop_oneof![
+            3 => arb_frame().prop_map(|frame| serde_json::to_value(frame).unwrap()),
+            1 => any::<i32>().prop_map(|n| serde_json::json!(n)),
+            1 => prop::string::string_regex("[a-z_]+")
+                .unwrap()
+                .prop_map(|name| serde_json::json!({"fn": name})),
+        ];
+        prop_oneof![
+            4 => prop::collection::vec(entry, 0..3).prop_map(serde_json::Value::from),
+            1 => any::<i32>().prop_map(|n| serde_json::json!(n)),
+            1 => Just(serde_json::Value::Null),
+        ]
+        .prop_map(|value| serde_json::from_value(value).unwrap())
+    }
+
     // Strategy for generating valid NodeData
     fn arb_node_data() -> impl Strategy<Value = NodeData> {
         (
             prop::option::of(any::<usize>()),
             prop::option::of(prop::string::string_regex("[A-Za-z]+").unwrap()),
-            prop::collection::vec(any::<i32>(), 0..3),
+            arb_backtrace(),
         )
-            .prop_map(|(location_id, location_type, backtrace_data)| NodeData {
+            .prop_map(|(location_id, location_type, backtrace)| NodeData {
                 location_id,
                 location_type,
-                backtrace: serde_json::json!(backtrace_data),
+                backtrace,
             })
     }
 
@@ -375,6 +556,7 @@ pub mod tests {
                     edge_style_config,
                     node_type_config,
                     legend,
+                    metadata: None,
                 },
             )
     }
@@ -0,0 +1,45 @@
+// Optional data parallelism
+//
+// With the `parallel` feature, independent per-item work (checking CALM-critical
+// edges, classifying operators) runs on rayon's thread pool. Results are
+// always collected in input order and applied sequentially, so the output is
+// byte-identical to a sequential run.
+
+/// Map `f` over `items`, in parallel with the `parallel` feature; results are
+/// in the order of `items` either way
+#[cfg(feature = "parallel")]
+pub(crate) fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+/// Map `f` over `items`, in parallel with the `parallel` feature; results are
+/// in the order of `items` either way
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_par_map_keeps_input_order() {
+        let items: Vec<u64> = (0..10_000).collect();
+        assert_eq!(
+            par_map(&items, |x| x * x),
+            items.iter().map(|x| x * x).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,451 @@
+// Pluggable analysis pass framework
+//
+// Every analysis, including the built-in ND, CALM and issue passes, is an
+// `AnalysisPass` registered with a `PassRegistry`. Passes declare the passes
+// they depend on; the registry runs them in dependency order over a shared
+// `AnalysisResult`, so downstream passes can read what upstream passes wrote.
+
+use crate::analysis::{
+    AnalysisResult, CALM_SAFE, CalmPass, Graph, IssuePass, NdPass, is_calm_critical,
+};
+use crate::boundedness::BoundednessPass;
+use crate::cache::{AnalysisCache, Fingerprints};
+use crate::config::AnalysisConfig;
+use crate::convergence::ConvergencePass;
+use crate::delivery::DeliveryPass;
+use crate::model::{Edge, HydroIr, Node};
+use crate::reach::Reachability;
+use crate::semantics::Classifier;
+use crate::verdict::VerdictPass;
+use anyhow::{Result, bail};
+use std::collections::{HashMap, HashSet};
+
+/// Read-only view of the program shared by all passes
+pub struct PassContext<'a> {
+    pub ir: &'a HydroIr,
+    pub config: &'a AnalysisConfig,
+    /// Operator semantics in effect for this run
+    pub classifier: Classifier,
+    pub(crate) graph: Graph,
+    /// Upstream relation between nodes, computed once for all passes
+    pub(crate) reach: Reachability,
+    edge_id_to_idx: HashMap<&'a str, usize>,
+    /// Node fingerprints and the previous run's results, for incremental runs
+    cache: Option<(Fingerprints, Option<&'a AnalysisCache>)>,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn new(ir: &'a HydroIr, config: &'a AnalysisConfig, classifier: Classifier) -> Self {
+        let graph = Graph::build(&ir.nodes, &ir.edges);
+        PassContext {
+            ir,
+            config,
+            classifier,
+            reach: Reachability::build(&graph),
+            graph,
+            edge_id_to_idx: ir
+                .edges
+                .iter()
+                .enumerate()
+                .map(|(idx, edge)| (edge.id.as_str(), idx))
+                .collect(),
+            cache: None,
+        }
+    }
+
+    /// Fingerprint nodes so results can be cached, reusing those in `previous`
+    fn with_cache(mut self, previous: Option<&'a AnalysisCache>) -> Self {
+        let fingerprints = Fingerprints::build(self.ir, &self.graph, &self.reach);
+        self.cache = Some((fingerprints, previous));
+        self
+    }
+
+    /// Cache key of the CALM check of an edge, in incremental runs
+    pub(crate) fn calm_key(&self, edge: &Edge) -> Option<String> {
+        let (fingerprints, _) = self.cache.as_ref()?;
+        Some(fingerprints.edge(edge, self.graph.get_idx(&edge.target)?))
+    }
+
+    /// CALM safety of an edge recorded by the previous run, if its upstream is unchanged
+    pub(crate) fn cached_calm(&self, edge: &Edge) -> Option<bool> {
+        let (_, previous) = self.cache.as_ref()?;
+        previous.as_ref()?.calm(&self.calm_key(edge)?)
+    }
+
+    /// Look up a node by ID
+    pub fn node(&self, node_id: &str) -> Option<&'a Node> {
+        let ir: &'a HydroIr = self.ir;
+        self.graph.get_idx(node_id).map(|idx| &ir.nodes[idx])
+    }
+
+    /// Look up an edge by ID
+    pub fn edge(&self, edge_id: &str) -> Option<&'a Edge> {
+        let ir: &'a HydroIr = self.ir;
+        self.edge_id_to_idx.get(edge_id).map(|&idx| &ir.edges[idx])
+    }
+
+    /// Position of an edge in `ir.edges`
+    pub fn edge_index(&self, edge_id: &str) -> Option<usize> {
+        self.edge_id_to_idx.get(edge_id).copied()
+    }
+
+    /// Edges leaving a node
+    pub fn outgoing(&self, node_id: &str) -> Vec<&'a Edge> {
+        self.graph
+            .get_idx(node_id)
+            .map(|idx| {
+                self.graph.forward[idx]
+                    .iter()
+                    .filter_map(|(_, edge_id)| self.edge(edge_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Edges entering a node
+    pub fn incoming(&self, node_id: &str) -> Vec<&'a Edge> {
+        self.graph
+            .get_idx(node_id)
+            .map(|idx| {
+                self.graph.backward[idx]
+                    .iter()
+                    .filter_map(|(_, edge_id)| self.edge(edge_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// A single analysis over the graph
+///
+/// Passes record their findings by pushing `Issue`s onto node/edge analyses
+/// and by attaching structured values with `AnalysisResult::attach_node_result`
+/// / `attach_edge_result`, which namespaces them under the pass name.
+pub trait AnalysisPass {
+    /// Unique name, used for dependency declarations, CLI selection and result namespacing
+    fn name(&self) -> &str;
+
+    /// Names of passes that must run before this one
+    fn dependencies(&self) -> &[&str] {
+        &[]
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult);
+}
+
+/// Ordered collection of passes with per-pass enable/disable
+#[derive(Default)]
+pub struct PassRegistry {
+    passes: Vec<Box<dyn AnalysisPass>>,
+    disabled: HashSet<String>,
+    config: AnalysisConfig,
+}
+
+impl PassRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry containing the built-in `nd`, `calm`, `issues`, `delivery`,
+    /// `verdicts`, `convergence` and `boundedness` passes
+    pub fn with_builtin_passes() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(NdPass));
+        registry.register(Box::new(CalmPass));
+        registry.register(Box::new(IssuePass));
+        registry.register(Box::new(DeliveryPass));
+        registry.register(Box::new(VerdictPass));
+        registry.register(Box::new(ConvergencePass));
+        registry.register(Box::new(BoundednessPass));
+        registry
+    }
+
+    /// Add a pass, replacing any registered pass with the same name
+    pub fn register(&mut self, pass: Box<dyn AnalysisPass>) {
+        if let Some(existing) = self.passes.iter_mut().find(|p| p.name() == pass.name()) {
+            *existing = pass;
+        } else {
+            self.passes.push(pass);
+        }
+    }
+
+    /// Disable a registered pass by name
+    pub fn disable(&mut self, name: &str) -> Result<()> {
+        if !self.passes.iter().any(|p| p.name() == name) {
+            bail!(
+                "Unknown analysis pass '{}' (available: {})",
+                name,
+                self.pass_names().join(", ")
+            );
+        }
+        self.disabled.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Replace the configuration passed to every pass
+    pub fn set_config(&mut self, config: AnalysisConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    /// Names of all registered passes, in registration order
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    /// Enabled passes in an order that respects declared dependencies
+    ///
+    /// Registration order is preserved among passes that don't depend on each other.
+    pub fn schedule(&self) -> Result<Vec<&dyn AnalysisPass>> {
+        let enabled: Vec<&dyn AnalysisPass> = self
+            .passes
+            .iter()
+            .map(|p| p.as_ref())
+            .filter(|p| !self.disabled.contains(p.name()))
+            .collect();
+
+        for pass in &enabled {
+            for dep in pass.dependencies() {
+                if self.disabled.contains(*dep) {
+                    bail!(
+                        "Analysis pass '{}' depends on disabled pass '{}'",
+                        pass.name(),
+                        dep
+                    );
+                }
+                if !enabled.iter().any(|p| p.name() == *dep) {
+                    bail!(
+                        "Analysis pass '{}' depends on unknown pass '{}'",
+                        pass.name(),
+                        dep
+                    );
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(enabled.len());
+        let mut done: HashSet<&str> = HashSet::new();
+        while order.len() < enabled.len() {
+            let ready = enabled.iter().find(|p| {
+                !done.contains(p.name()) && p.dependencies().iter().all(|dep| done.contains(dep))
+            });
+            match ready {
+                Some(pass) => {
+                    done.insert(pass.name());
+                    order.push(*pass);
+                }
+                None => {
+                    let stuck: Vec<&str> = enabled
+                        .iter()
+                        .map(|p| p.name())
+                        .filter(|name| !done.contains(name))
+                        .collect();
+                    bail!(
+                        "Cyclic dependencies between analysis passes: {}",
+                        stuck.join(", ")
+                    );
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Run all enabled passes over the IR
+    pub fn run(&self, ir: &HydroIr) -> Result<AnalysisResult> {
+        let schedule = self.schedule()?;
+        let classifier = self.config.classifier(ir)?;
+        let ctx = PassContext::new(ir, &self.config, classifier);
+        let mut results = AnalysisResult::initial(ir, &self.config, classifier);
+
+        for pass in schedule {
+            pass.run(&ctx, &mut results);
+        }
+        results.canonicalize(ir);
+
+        Ok(results)
+    }
+
+    /// Run all enabled passes, reusing the CALM results in `cache` for
+    /// critical edges whose upstream is unchanged since it was written
+    ///
+    /// Returns the results together with the cache for the next run. A
+    /// cached status is reused only when the edge's fingerprint (the
+    /// operators upstream, including their backtraces, and the edges between
+    /// them) is unchanged and the cache was written by this version with the
+    /// same profile and environment model. The results match those of `run`
+    /// only as long as the fingerprint covers everything the CALM check reads.
+    pub fn run_incremental(
+        &self,
+        ir: &HydroIr,
+        cache: &AnalysisCache,
+    ) -> Result<(AnalysisResult, AnalysisCache)> {
+        let schedule = self.schedule()?;
+        let classifier = self.config.classifier(ir)?;
+        let previous = cache.applies_to(&classifier).then_some(cache);
+        let ctx = PassContext::new(ir, &self.config, classifier).with_cache(previous);
+        let mut results = AnalysisResult::initial(ir, &self.config, classifier);
+
+        let calm_ran = schedule.iter().any(|pass| pass.name() == "calm");
+        for pass in schedule {
+            pass.run(&ctx, &mut results);
+        }
+        results.canonicalize(ir);
+
+        let mut next = AnalysisCache::new(&classifier);
+        if calm_ran {
+            for edge in &ir.edges {
+                if is_calm_critical(edge, ctx.node(&edge.target))
+                    && let Some(key) = ctx.calm_key(edge)
+                {
+                    let safe = results.edge_analyses[&edge.id].calm == CALM_SAFE;
+                    next.insert_calm(key, safe);
+                }
+            }
+        }
+
+        Ok((results, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+    use crate::model::Issue;
+
+    /// Example of a team-specific check shipped outside `analysis.rs`
+    struct NoSendExternalFromCluster;
+
+    impl AnalysisPass for NoSendExternalFromCluster {
+        fn name(&self) -> &str {
+            "no_cluster_send_external"
+        }
+
+        fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+            for node in &ctx.ir.nodes {
+                let in_cluster =
+                    node.data.as_ref().and_then(|d| d.location_type.as_deref()) == Some("Cluster");
+                if node.label.as_deref() == Some("send_external") && in_cluster {
+                    results.attach_node_result(
+                        self.name(),
+                        &node.id,
+                        "violation",
+                        serde_json::json!(true),
+                    );
+                    if let Some(analysis) = results.node_analyses.get_mut(&node.id) {
+                        analysis.issues.push(Issue {
+                            kind: "ClusterSendExternal".to_string(),
+                            subject: node.id.clone(),
+                            message: format!(
+                                "Node '{}' sends to an external process from a Cluster",
+                                node.id
+                            ),
+                            affected_critical_edges: Vec::new(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the ND effect the `nd` pass computed for each sink
+    struct SinkNdSummary;
+
+    impl AnalysisPass for SinkNdSummary {
+        fn name(&self) -> &str {
+            "sink_nd"
+        }
+
+        fn dependencies(&self) -> &[&str] {
+            &["nd"]
+        }
+
+        fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+            for node in ctx.ir.nodes.iter().filter(|n| n.node_type == "Sink") {
+                let effect = results.node_analyses[&node.id].nd_effect.clone();
+                results.attach_node_result(self.name(), &node.id, "effect", effect.into());
+            }
+        }
+    }
+
+    fn cluster_ir() -> HydroIr {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_stream", 1, "Cluster");
+        let nondet = b.node_at("observenondet", 1, "Cluster");
+        let sink = b.node_at("send_external", 1, "Cluster");
+        b.edge(&src, &nondet, &["Local"]);
+        b.edge(&nondet, &sink, &["Local"]);
+        b.build()
+    }
+
+    #[test]
+    fn test_custom_pass_attaches_namespaced_results() {
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.register(Box::new(NoSendExternalFromCluster));
+        registry.register(Box::new(SinkNdSummary));
+
+        let results = registry.run(&cluster_ir()).unwrap();
+        let sink = &results.node_analyses["2"];
+
+        assert_eq!(
+            sink.pass_results["no_cluster_send_external"]["violation"],
+            serde_json::json!(true)
+        );
+        assert_eq!(
+            sink.pass_results["sink_nd"]["effect"],
+            serde_json::json!("LocallyNonDet")
+        );
+        assert!(
+            sink.issues
+                .iter()
+                .any(|issue| issue.kind == "ClusterSendExternal")
+        );
+    }
+
+    #[test]
+    fn test_schedule_respects_dependencies() {
+        let mut registry = PassRegistry::new();
+        // Registered before the passes it depends on
+        registry.register(Box::new(SinkNdSummary));
+        registry.register(Box::new(IssuePass));
+        registry.register(Box::new(CalmPass));
+        registry.register(Box::new(NdPass));
+
+        let order: Vec<&str> = registry
+            .schedule()
+            .unwrap()
+            .iter()
+            .map(|p| p.name())
+            .collect();
+        let position = |name| order.iter().position(|n| *n == name).unwrap();
+
+        assert!(position("nd") < position("sink_nd"));
+        assert!(position("nd") < position("issues"));
+        assert!(position("calm") < position("issues"));
+    }
+
+    #[test]
+    fn test_disabling_dependency_is_an_error() {
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.disable("calm").unwrap();
+        assert!(registry.run(&cluster_ir()).is_err());
+
+        // Disabling the dependents as well makes the schedule valid again
+        registry.disable("issues").unwrap();
+        registry.disable("verdicts").unwrap();
+        let results = registry.run(&cluster_ir()).unwrap();
+        assert_eq!(results.node_analyses["1"].nd_effect, "LocallyNonDet");
+        assert!(results.node_analyses["1"].issues.is_empty());
+    }
+
+    #[test]
+    fn test_disable_unknown_pass() {
+        let mut registry = PassRegistry::with_builtin_passes();
+        assert!(registry.disable("no_such_pass").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+// Shared reachability over the dataflow graph
+//
+// Several passes ask "which operators are upstream of this one?", once per
+// CALM-critical edge or per sink. Walking the graph for every question is
+// quadratic on large exports, so the graph is condensed into its strongly
+// connected components once per run and the transitive closure of the
+// condensation is stored as one bitset of upstream components each.
+
+use crate::analysis::Graph;
+
+/// Fixed-size set of small integers
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, bit: usize) {
+        self.words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    fn contains(&self, bit: usize) -> bool {
+        self.words[bit / 64] & (1 << (bit % 64)) != 0
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(idx, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| idx * 64 + bit)
+        })
+    }
+}
+
+/// Strongly connected components of a graph and the closure of their condensation
+///
+/// Components are numbered in topological order: every edge between two
+/// components goes from a lower number to a higher one.
+pub(crate) struct Reachability {
+    /// Component of each node
+    component: Vec<usize>,
+    /// Nodes of each component, in ascending order
+    members: Vec<Vec<usize>>,
+    /// Components upstream of each component, itself included
+    upstream: Vec<BitSet>,
+}
+
+impl Reachability {
+    pub(crate) fn build(graph: &Graph) -> Self {
+        let members = strongly_connected_components(graph);
+        let mut component = vec![0; graph.forward.len()];
+        for (comp, nodes) in members.iter().enumerate() {
+            for &node in nodes {
+                component[node] = comp;
+            }
+        }
+
+        // Predecessors have lower numbers, so one pass in order closes the relation
+        let mut upstream: Vec<BitSet> = Vec::with_capacity(members.len());
+        for (comp, nodes) in members.iter().enumerate() {
+            let mut set = BitSet::new(members.len());
+            set.insert(comp);
+            for &node in nodes {
+                for &(pred, _) in &graph.backward[node] {
+                    let pred = component[pred];
+                    if pred != comp {
+                        set.union_with(&upstream[pred]);
+                    }
+                }
+            }
+            upstream.push(set);
+        }
+
+        Reachability {
+            component,
+            members,
+            upstream,
+        }
+    }
+
+    /// Component containing a node
+    pub(crate) fn component(&self, node: usize) -> usize {
+        self.component[node]
+    }
+
+    /// Nodes of each component, in topological order of the components
+    pub(crate) fn components(&self) -> &[Vec<usize>] {
+        &self.members
+    }
+
+    /// Whether `to` is reachable from `from` (every node reaches itself)
+    pub(crate) fn reaches(&self, from: usize, to: usize) -> bool {
+        self.upstream[self.component[to]].contains(self.component[from])
+    }
+
+    /// Nodes with a path to `target`, `target` included, in ascending order
+    pub(crate) fn upstream(&self, target: usize) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self.upstream[self.component[target]]
+            .iter()
+            .flat_map(|comp| self.members[comp].iter().copied())
+            .collect();
+        nodes.sort_unstable();
+        nodes
+    }
+}
+
+/// Tarjan's algorithm without recursion, so deep pipelines don't overflow
+/// the stack. Returns components in topological order.
+fn strongly_connected_components(graph: &Graph) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let n = graph.forward.len();
+    let mut index = vec![UNVISITED; n];
+    let mut lowlink = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut components = Vec::new();
+
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // (node, position in its successor list)
+        let mut calls = vec![(root, 0)];
+        while let Some(&mut (node, ref mut next)) = calls.last_mut() {
+            if *next == 0 {
+                index[node] = next_index;
+                lowlink[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if let Some(&(succ, _)) = graph.forward[node].get(*next) {
+                *next += 1;
+                if index[succ] == UNVISITED {
+                    calls.push((succ, 0));
+                } else if on_stack[succ] {
+                    lowlink[node] = lowlink[node].min(index[succ]);
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                lowlink[parent] = lowlink[parent].min(lowlink[node]);
+            }
+            if lowlink[node] == index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+
+    // Tarjan emits components in reverse topological order
+    components.reverse();
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+
+    #[test]
+    fn test_reachability_through_cycles() {
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let head = b.node("chain");
+        let body = b.node("map");
+        let sink = b.node("for_each");
+        let other = b.node("source_iter");
+        b.edge(&src, &head, &[]);
+        b.edge(&head, &body, &[]);
+        b.edge(&body, &head, &[]);
+        b.edge(&body, &sink, &[]);
+        let ir = b.build();
+        let graph = Graph::build(&ir.nodes, &ir.edges);
+        let reach = Reachability::build(&graph);
+
+        assert_eq!(reach.component(1), reach.component(2));
+        assert_eq!(reach.components().len(), 4);
+        assert!(reach.component(0) < reach.component(1));
+        assert!(reach.component(2) < reach.component(3));
+        assert_eq!(reach.upstream(3), vec![0, 1, 2, 3]);
+        assert_eq!(reach.upstream(1), vec![0, 1, 2]);
+        assert!(reach.reaches(2, 1) && reach.reaches(4, 4));
+        assert!(!reach.reaches(3, 2) && !reach.reaches(other.parse().unwrap(), 3));
+    }
+}
@@ -0,0 +1,513 @@
+// Render analyzed graphs as Graphviz DOT or Mermaid
+//
+// Styling follows the semantic groups in `annotate::node_analysis_mappings`
+// and `annotate::edge_analysis_mappings`, so DOT/Mermaid output and
+// Hydroscope agree on what each color and line style means.
+
+use crate::analysis::{AnalysisResult, Graph};
+use crate::annotate::{
+    edge_analysis_mappings, edge_semantic_tags, node_analysis_mappings, node_semantic_tags,
+};
+use crate::hierarchy::parse_choices;
+use crate::model::{Edge, HydroIr, Node};
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+/// Textual graph format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for RenderFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(RenderFormat::Dot),
+            "mermaid" => Ok(RenderFormat::Mermaid),
+            other => bail!(
+                "Unknown render format '{}' (expected dot or mermaid)",
+                other
+            ),
+        }
+    }
+}
+
+/// Visual style resolved from an element's semantic tags
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Style {
+    pub(crate) color: Option<&'static str>,
+    pub(crate) width: Option<u64>,
+    pub(crate) dashed: bool,
+    pub(crate) badge: Option<String>,
+}
+
+/// Concrete colors for the Hydroscope color tokens used by the semantic groups
+fn token_color(token: &str) -> Option<&'static str> {
+    match token {
+        "warning" => Some("#f0ad4e"),
+        "warning-light" => Some("#fbe3bd"),
+        "info" => Some("#0277bd"),
+        "info-light" => Some("#b3e5fc"),
+        "success" => Some("#2e7d32"),
+        "danger" => Some("#c62828"),
+        _ => None,
+    }
+}
+
+/// Apply every semantic group mapping whose tag the element carries
+pub(crate) fn resolve_style(tags: &[String], mappings: &serde_json::Value) -> Style {
+    let mut style = Style::default();
+    let Some(groups) = mappings.as_object() else {
+        return style;
+    };
+    for group in groups.values() {
+        let Some(group) = group.as_object() else {
+            continue;
+        };
+        for tag in tags {
+            let Some(props) = group.get(tag).and_then(|p| p.as_object()) else {
+                continue;
+            };
+            for (key, value) in props {
+                match key.as_str() {
+                    "color-token" => style.color = value.as_str().and_then(token_color),
+                    "border-width" | "line-width" => style.width = value.as_u64(),
+                    "border-style" | "line-pattern" => {
+                        style.dashed = value.as_str() == Some("dashed")
+                    }
+                    "badge" => style.badge = value.as_str().map(str::to_string),
+                    _ => {}
+                }
+            }
+        }
+    }
+    style
+}
+
+/// Nodes relevant to the issues on a node or edge: everything upstream of
+/// it (where nondeterminism or non-monotonicity came from) and everything
+/// downstream (what it affects)
+pub fn issue_focus(ir: &HydroIr, results: &AnalysisResult, id: &str) -> Result<HashSet<String>> {
+    let (has_issues, upstream_from, downstream_from) =
+        if let Some(analysis) = results.node_analyses.get(id) {
+            (!analysis.issues.is_empty(), id, id)
+        } else if let Some(analysis) = results.edge_analyses.get(id) {
+            let edge = ir
+                .edges
+                .iter()
+                .find(|e| e.id == id)
+                .expect("edge analyses are keyed by edge ID");
+            (
+                !analysis.issues.is_empty(),
+                edge.source.as_str(),
+                edge.target.as_str(),
+            )
+        } else {
+            bail!("No node or edge with ID '{}'", id);
+        };
+    if !has_issues {
+        bail!("'{}' has no issues", id);
+    }
+
+    let graph = Graph::build(&ir.nodes, &ir.edges);
+    let mut focus = HashSet::new();
+    for (start, forward) in [(upstream_from, false), (downstream_from, true)] {
+        let Some(start) = graph.get_idx(start) else {
+            continue;
+        };
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(idx) = queue.pop_front() {
+            focus.insert(ir.nodes[idx].id.clone());
+            let adjacent = if forward {
+                &graph.forward[idx]
+            } else {
+                &graph.backward[idx]
+            };
+            for &(next, _) in adjacent {
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    Ok(focus)
+}
+
+/// A location cluster: display name plus member nodes in input order
+struct Cluster<'a> {
+    name: String,
+    nodes: Vec<&'a Node>,
+}
+
+/// Display names for locations, from the "location" hierarchy when present
+/// and otherwise e.g. "Cluster 1"
+pub(crate) struct LocationNames(BTreeMap<String, String>);
+
+impl LocationNames {
+    pub(crate) fn new(ir: &HydroIr) -> Self {
+        let names = parse_choices(ir)
+            .into_iter()
+            .filter(|choice| choice.id == "location")
+            .flat_map(|choice| choice.children)
+            .map(|loc| (loc.id, loc.name))
+            .collect();
+        LocationNames(names)
+    }
+
+    /// Location ID and display name of a node, if it has a location
+    pub(crate) fn of(&self, node: &Node) -> Option<(usize, String)> {
+        let data = node.data.as_ref()?;
+        let loc = data.location_id?;
+        let name = self
+            .0
+            .get(&format!("loc_{}", loc))
+            .cloned()
+            .unwrap_or_else(|| {
+                format!(
+                    "{} {}",
+                    data.location_type.as_deref().unwrap_or("Location"),
+                    loc
+                )
+            });
+        Some((loc, name))
+    }
+}
+
+/// Group nodes by location; nodes without a location are returned separately
+fn clusters<'a>(ir: &HydroIr, nodes: &[&'a Node]) -> (BTreeMap<usize, Cluster<'a>>, Vec<&'a Node>) {
+    let names = LocationNames::new(ir);
+    let mut clusters: BTreeMap<usize, Cluster> = BTreeMap::new();
+    let mut unplaced = Vec::new();
+    for &node in nodes {
+        match names.of(node) {
+            Some((loc, name)) => clusters
+                .entry(loc)
+                .or_insert_with(|| Cluster {
+                    name,
+                    nodes: Vec::new(),
+                })
+                .nodes
+                .push(node),
+            None => unplaced.push(node),
+        }
+    }
+    (clusters, unplaced)
+}
+
+fn node_text(node: &Node, style: &Style) -> String {
+    let label = node.label.as_deref().unwrap_or(&node.short_label);
+    match &style.badge {
+        Some(badge) => format!("{} {} {}", node.id, label, badge),
+        None => format!("{} {}", node.id, label),
+    }
+}
+
+/// Render the graph, optionally restricted to a set of node IDs
+pub fn render(
+    ir: &HydroIr,
+    results: &AnalysisResult,
+    format: RenderFormat,
+    focus: Option<&HashSet<String>>,
+) -> String {
+    let nodes: Vec<&Node> = ir
+        .nodes
+        .iter()
+        .filter(|n| focus.is_none_or(|f| f.contains(&n.id)))
+        .collect();
+    let edges: Vec<&Edge> = ir
+        .edges
+        .iter()
+        .filter(|e| focus.is_none_or(|f| f.contains(&e.source) && f.contains(&e.target)))
+        .collect();
+
+    let node_mappings = node_analysis_mappings();
+    let edge_mappings = edge_analysis_mappings();
+    let node_style =
+        |node: &Node| resolve_style(&node_semantic_tags(node, results), &node_mappings);
+    let edge_style = |edge: &Edge| {
+        resolve_style(
+            &edge_semantic_tags(edge, results.edge_analyses.get(&edge.id)),
+            &edge_mappings,
+        )
+    };
+
+    let (clusters, unplaced) = clusters(ir, &nodes);
+    match format {
+        RenderFormat::Dot => render_dot(&clusters, &unplaced, &edges, node_style, edge_style),
+        RenderFormat::Mermaid => {
+            render_mermaid(&clusters, &unplaced, &edges, node_style, edge_style)
+        }
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn render_dot(
+    clusters: &BTreeMap<usize, Cluster>,
+    unplaced: &[&Node],
+    edges: &[&Edge],
+    node_style: impl Fn(&Node) -> Style,
+    edge_style: impl Fn(&Edge) -> Style,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph hydro {{");
+    let _ = writeln!(
+        out,
+        "  node [shape=box, style=\"rounded,filled\", fillcolor=\"#ffffff\"];"
+    );
+
+    let write_node = |out: &mut String, node: &Node, indent: &str| {
+        let style = node_style(node);
+        let mut attrs = vec![format!(
+            "label=\"{}\"",
+            dot_escape(&node_text(node, &style))
+        )];
+        if let Some(color) = style.color {
+            attrs.push(format!("fillcolor=\"{}\"", color));
+        }
+        if let Some(width) = style.width {
+            attrs.push(format!("penwidth={}", width));
+        }
+        if style.dashed {
+            attrs.push("style=\"rounded,filled,dashed\"".to_string());
+        }
+        let _ = writeln!(
+            out,
+            "{}\"{}\" [{}];",
+            indent,
+            dot_escape(&node.id),
+            attrs.join(", ")
+        );
+    };
+
+    for (loc, cluster) in clusters {
+        let _ = writeln!(out, "  subgraph cluster_loc_{} {{", loc);
+        let _ = writeln!(out, "    label=\"{}\";", dot_escape(&cluster.name));
+        for node in &cluster.nodes {
+            write_node(&mut out, node, "    ");
+        }
+        let _ = writeln!(out, "  }}");
+    }
+    for node in unplaced {
+        write_node(&mut out, node, "  ");
+    }
+
+    for edge in edges {
+        let style = edge_style(edge);
+        let mut attrs = Vec::new();
+        if let Some(label) = &edge.label {
+            attrs.push(format!("label=\"{}\"", dot_escape(label)));
+        }
+        if let Some(color) = style.color {
+            attrs.push(format!("color=\"{}\"", color));
+        }
+        if let Some(width) = style.width {
+            attrs.push(format!("penwidth={}", width));
+        }
+        if style.dashed {
+            attrs.push("style=dashed".to_string());
+        }
+        let _ = writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [{}];",
+            dot_escape(&edge.source),
+            dot_escape(&edge.target),
+            attrs.join(", ")
+        );
+    }
+
+    let _ = writeln!(out, "}}");
+    out
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+fn render_mermaid(
+    clusters: &BTreeMap<usize, Cluster>,
+    unplaced: &[&Node],
+    edges: &[&Edge],
+    node_style: impl Fn(&Node) -> Style,
+    edge_style: impl Fn(&Edge) -> Style,
+) -> String {
+    let mut out = String::new();
+    let mut styles = String::new();
+    let _ = writeln!(out, "flowchart TD");
+
+    let mut write_node = |out: &mut String, node: &Node, indent: &str| {
+        let style = node_style(node);
+        let _ = writeln!(
+            out,
+            "{}n{}[\"{}\"]",
+            indent,
+            node.id,
+            mermaid_escape(&node_text(node, &style))
+        );
+        let mut props = Vec::new();
+        if let Some(color) = style.color {
+            props.push(format!("fill:{}", color));
+        }
+        if let Some(width) = style.width {
+            props.push(format!("stroke-width:{}px", width));
+        }
+        if style.dashed {
+            props.push("stroke-dasharray:5 5".to_string());
+        }
+        if !props.is_empty() {
+            let _ = writeln!(styles, "  style n{} {}", node.id, props.join(","));
+        }
+    };
+
+    for (loc, cluster) in clusters {
+        let _ = writeln!(
+            out,
+            "  subgraph loc_{}[\"{}\"]",
+            loc,
+            mermaid_escape(&cluster.name)
+        );
+        for node in &cluster.nodes {
+            write_node(&mut out, node, "    ");
+        }
+        let _ = writeln!(out, "  end");
+    }
+    for node in unplaced {
+        write_node(&mut out, node, "  ");
+    }
+
+    for (idx, edge) in edges.iter().enumerate() {
+        let style = edge_style(edge);
+        let arrow = if style.dashed { "-.->" } else { "-->" };
+        match &edge.label {
+            Some(label) => {
+                let _ = writeln!(
+                    out,
+                    "  n{} {}|\"{}\"| n{}",
+                    edge.source,
+                    arrow,
+                    mermaid_escape(label),
+                    edge.target
+                );
+            }
+            None => {
+                let _ = writeln!(out, "  n{} {} n{}", edge.source, arrow, edge.target);
+            }
+        }
+        let mut props = Vec::new();
+        if let Some(color) = style.color {
+            props.push(format!("stroke:{}", color));
+        }
+        if let Some(width) = style.width {
+            props.push(format!("stroke-width:{}px", width));
+        }
+        if !props.is_empty() {
+            let _ = writeln!(styles, "  linkStyle {} {}", idx, props.join(","));
+        }
+    }
+
+    out.push_str(&styles);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+
+    // source (Process 0) --network--> observenondet (Cluster 1) -> map -> for_each,
+    // plus an unrelated source -> for_each in Process 0
+    fn sample() -> (HydroIr, AnalysisResult) {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_stream", 0, "Process");
+        let nondet = b.node_at("observenondet", 1, "Cluster");
+        let map = b.node_at("map", 1, "Cluster");
+        let sink = b.node_at("for_each", 1, "Cluster");
+        let other_src = b.node_at("source_iter", 0, "Process");
+        let other_sink = b.node_at("for_each", 0, "Process");
+        b.edge(&src, &nondet, &["Network", "Unbounded"]);
+        b.edge(&nondet, &map, &["Local"]);
+        b.typed_edge(&map, &sink, &["Local"], "String");
+        b.edge(&other_src, &other_sink, &["Local"]);
+        let ir = b.build();
+        let results = run_analysis(&ir);
+        (ir, results)
+    }
+
+    #[test]
+    fn test_render_dot() {
+        let (ir, results) = sample();
+        let dot = render(&ir, &results, RenderFormat::Dot, None);
+
+        assert!(dot.starts_with("digraph hydro {"));
+        assert!(dot.contains("subgraph cluster_loc_0 {"));
+        assert!(dot.contains("label=\"Cluster 1\";"));
+        // ND root and inherited taint use the NondeterminismGroup colors
+        assert!(
+            dot.contains("\"1\" [label=\"1 observenondet ⚠\", fillcolor=\"#f0ad4e\", penwidth=3];")
+        );
+        assert!(dot.contains(
+            "\"2\" [label=\"2 map ✓\", fillcolor=\"#fbe3bd\", style=\"rounded,filled,dashed\"];"
+        ));
+        // The network edge is CALM-unsafe: it feeds nondeterminism into a sink
+        let network = dot.lines().find(|l| l.contains("\"0\" -> \"1\"")).unwrap();
+        assert!(network.contains("style=dashed"), "{}", network);
+        assert!(dot.contains("\"2\" -> \"3\" [label=\"String\""));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_render_mermaid() {
+        let (ir, results) = sample();
+        let mermaid = render(&ir, &results, RenderFormat::Mermaid, None);
+
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains("  subgraph loc_1[\"Cluster 1\"]\n"));
+        assert!(mermaid.contains("    n1[\"1 observenondet ⚠\"]\n"));
+        assert!(mermaid.contains("  n0 -.-> n1\n"));
+        assert!(
+            mermaid.contains("  n2 -->|\"String\"| n3\n")
+                || mermaid.contains("  n2 -.->|\"String\"| n3\n")
+        );
+        assert!(mermaid.contains("  style n1 fill:#f0ad4e,stroke-width:3px\n"));
+    }
+
+    #[test]
+    fn test_issue_focus() {
+        let (ir, results) = sample();
+        assert!(!results.node_analyses["1"].issues.is_empty());
+
+        let focus = issue_focus(&ir, &results, "1").unwrap();
+        let mut ids: Vec<&str> = focus.iter().map(|s| s.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["0", "1", "2", "3"]);
+
+        let dot = render(&ir, &results, RenderFormat::Dot, Some(&focus));
+        assert!(!dot.contains("source_iter"));
+        assert!(!dot.contains("\"4\" -> \"5\""));
+
+        assert!(issue_focus(&ir, &results, "4").is_err());
+        assert!(issue_focus(&ir, &results, "missing").is_err());
+    }
+
+    #[test]
+    fn test_cluster_names_from_hierarchy() {
+        let (mut ir, results) = sample();
+        ir.hierarchy_choices = Some(serde_json::json!([{
+            "id": "location",
+            "name": "Location",
+            "children": [{"id": "loc_1", "name": "kvs::Replica", "children": []}]
+        }]));
+        let dot = render(&ir, &results, RenderFormat::Dot, None);
+        assert!(dot.contains("label=\"kvs::Replica\";"));
+        assert!(dot.contains("label=\"Process 0\";"));
+    }
+}
@@ -1,7 +1,10 @@
 // Report generation for analysis results
 
-use crate::analysis::AnalysisResult;
+use crate::analysis::{AnalysisResult, CALM_UNSAFE, ND_LOCALLY_NONDET};
+use crate::hierarchy::{CallSiteHierarchy, CallTreeNode, format_issue_counts};
 use crate::model::HydroIr;
+use crate::semantics::{SemanticsSource, coverage_gaps};
+use std::collections::BTreeMap;
 
 // Report formatting constants
 const MAX_REPORT_OPERATIONS: usize = 20;
@@ -24,30 +27,58 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
         }
     ));
     report.push_str(&format!(
-        "  CALM Safe: {}\n\n",
+        "  Deterministic modulo environment: {}\n",
+        if results.overall.deterministic_modulo_environment {
+            "✓ YES"
+        } else {
+            "✗ NO"
+        }
+    ));
+    report.push_str(&format!(
+        "  CALM Safe: {}\n",
         if results.overall.calm_safe {
             "✓ YES"
         } else {
             "✗ NO"
         }
     ));
+    report.push_str(&format!(
+        "  Depends on environment: {}\n\n",
+        if results.overall.environment_dependent {
+            "YES"
+        } else {
+            "NO"
+        }
+    ));
+
+    push_verdicts(&mut report, ir, results);
 
     // Count root causes (not inherited issues)
-    use crate::semantics::{Monotonicity, NdEffect, get_node_semantics};
+    use crate::semantics::NdEffect;
 
     let mut nondet_root_count = 0;
+    let mut env_root_count = 0;
     let mut nonmonotone_root_count = 0;
     let mut nonlattice_root_count = 0;
 
+    // Non-monotone operators only count if they make a CALM-critical edge
+    // unsafe; each has one NonMonotone issue however many edges it affects
+    let has_issue = |id: &str, kind: &str| {
+        results
+            .node_analyses
+            .get(id)
+            .is_some_and(|a| a.issues.iter().any(|i| i.kind == kind))
+    };
+
     // Count root cause nodes (intrinsically non-deterministic or non-monotone)
     for node in &ir.nodes {
-        let semantics = get_node_semantics(node);
-
-        if semantics.nd != NdEffect::Deterministic {
-            nondet_root_count += 1;
+        match results.semantics(node).nd {
+            NdEffect::LocallyNonDet => nondet_root_count += 1,
+            NdEffect::ExternalNonDet => env_root_count += 1,
+            NdEffect::Deterministic => {}
         }
 
-        if semantics.monotone == Monotonicity::Never {
+        if has_issue(&node.id, "NonMonotone") {
             nonmonotone_root_count += 1;
         }
     }
@@ -71,6 +102,7 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
         "  Non-monotone operations: {}\n",
         nonmonotone_root_count
     ));
+    report.push_str(&format!("  Environment inputs: {}\n", env_root_count));
     report.push_str(&format!(
         "  Non-lattice edges: {}\n\n",
         nonlattice_root_count
@@ -87,15 +119,13 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
 
         // Find root cause nodes (intrinsically non-deterministic or non-monotone)
         for node in &ir.nodes {
-            let semantics = get_node_semantics(node);
-
             // Check if node is intrinsically non-deterministic (not just tainted)
-            if semantics.nd != NdEffect::Deterministic {
+            if results.semantics(node).nd == NdEffect::LocallyNonDet {
                 nondet_nodes.push(node);
             }
 
-            // Check if node is intrinsically non-monotone
-            if semantics.monotone == Monotonicity::Never {
+            // Check if node is non-monotone on a CALM-critical path
+            if has_issue(&node.id, "NonMonotone") {
                 nonmonotone_nodes.push(node);
             }
         }
@@ -280,7 +310,7 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
 
             if (is_network || targets_sink)
                 && let Some(analysis) = results.edge_analyses.get(&edge.id)
-                && analysis.calm == "CalmUnsafe"
+                && analysis.calm == CALM_UNSAFE
             {
                 // Note: using string literal to match EdgeAnalysis.calm field
                 let source_node = ir
@@ -359,6 +389,28 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
         }
     }
 
+    // Issues grouped by the user function that created the operators
+    if let Some(hierarchy) = CallSiteHierarchy::from_ir(ir) {
+        let tree: Vec<CallTreeNode> = hierarchy
+            .issue_tree(ir, results)
+            .into_iter()
+            .filter_map(CallTreeNode::prune)
+            .collect();
+        if !tree.is_empty() {
+            report.push_str("ISSUES BY CALL SITE:\n");
+            for node in &tree {
+                push_call_tree(&mut report, node, 1);
+            }
+            report.push('\n');
+        }
+    }
+
+    push_sink_nd_kinds(&mut report, ir, results);
+    push_convergence(&mut report, results);
+    push_sealing(&mut report, ir, results);
+    push_environment(&mut report, ir, results);
+    push_coverage(&mut report, ir, results);
+
     if results.overall.deterministic && results.overall.calm_safe {
         report.push_str("\n✓ No issues found! Your dataflow is deterministic and CALM-safe.\n\n");
     }
@@ -367,3 +419,188 @@ pub fn generate_report(ir: &HydroIr, results: &AnalysisResult) -> String {
 
     report
 }
+
+/// One verdict per output, with its reason and witness path
+fn push_verdicts(report: &mut String, ir: &HydroIr, results: &AnalysisResult) {
+    if results.verdicts.is_empty() {
+        return;
+    }
+    let describe = |id: &str| match ir.nodes.iter().find(|n| n.id == id) {
+        Some(node) => format!(
+            "{} ({})",
+            node.label.as_ref().unwrap_or(&node.short_label),
+            id
+        ),
+        None => id.to_string(),
+    };
+
+    report.push_str("OUTPUT VERDICTS:\n");
+    for verdict in &results.verdicts {
+        report.push_str(&format!(
+            "  {}: {}\n    {}\n",
+            describe(&verdict.sink),
+            verdict.verdict,
+            verdict.reason
+        ));
+        if !verdict.witness.is_empty() {
+            let path: Vec<String> = verdict.witness.iter().map(|id| describe(id)).collect();
+            report.push_str(&format!("    witness: {}\n", path.join(" → ")));
+        }
+        if !verdict.sealed_by.is_empty() {
+            let seals: Vec<String> = verdict.sealed_by.iter().map(|id| describe(id)).collect();
+            report.push_str(&format!("    sealed by: {}\n", seals.join(", ")));
+        }
+    }
+    report.push('\n');
+}
+
+/// Show which kinds of nondeterminism reach each sink
+fn push_sink_nd_kinds(report: &mut String, ir: &HydroIr, results: &AnalysisResult) {
+    let sinks: Vec<(&crate::model::Node, &crate::model::NodeAnalysis)> = ir
+        .nodes
+        .iter()
+        .filter(|node| node.node_type == "Sink")
+        .filter_map(|node| Some((node, results.node_analyses.get(&node.id)?)))
+        .filter(|(_, analysis)| !analysis.nd_kinds.is_empty())
+        .collect();
+    if sinks.is_empty() {
+        return;
+    }
+
+    report.push_str("NONDETERMINISM AT SINKS:\n");
+    for (node, analysis) in sinks {
+        report.push_str(&format!(
+            "  {} ({}): {} - {}\n",
+            node.label.as_ref().unwrap_or(&node.short_label),
+            node.id,
+            analysis.nd_effect,
+            analysis.nd_kinds.join(", ")
+        ));
+    }
+    report.push('\n');
+}
+
+/// Replica convergence verdict per cluster location
+fn push_convergence(report: &mut String, results: &AnalysisResult) {
+    if results.convergence.is_empty() {
+        return;
+    }
+
+    report.push_str("REPLICA CONVERGENCE:\n");
+    for location in &results.convergence {
+        report.push_str(&format!(
+            "  Cluster {}: {} ({} network-fed state operators)\n",
+            location.location,
+            location.verdict,
+            location.states.len()
+        ));
+        for reason in &location.reasons {
+            report.push_str(&format!("    {}\n", reason));
+        }
+    }
+    report.push('\n');
+}
+
+/// List the operators that stopped nondeterminism from propagating
+fn push_sealing(report: &mut String, ir: &HydroIr, results: &AnalysisResult) {
+    let sealed: Vec<(&crate::model::Node, &str)> = ir
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let reason = results.node_analyses.get(&node.id)?.sealed.as_deref()?;
+            Some((node, reason))
+        })
+        .collect();
+    if sealed.is_empty() {
+        return;
+    }
+
+    report.push_str("NONDETERMINISM SEALED:\n");
+    for (node, reason) in sealed {
+        report.push_str(&format!(
+            "  {} ({}): {}\n",
+            node.label.as_ref().unwrap_or(&node.short_label),
+            node.id,
+            reason
+        ));
+    }
+    report.push('\n');
+}
+
+/// Summarize which outputs depend on the environment rather than on races
+fn push_environment(report: &mut String, ir: &HydroIr, results: &AnalysisResult) {
+    if !results.overall.environment_dependent {
+        return;
+    }
+    let mut origins: BTreeMap<&str, usize> = BTreeMap::new();
+    for node in &ir.nodes {
+        if let SemanticsSource::Environment(category) = results.classifier.classify(node).source {
+            *origins.entry(category).or_default() += 1;
+        }
+    }
+    let dependent: Vec<_> = results
+        .node_analyses
+        .values()
+        .filter(|a| a.env_dependent)
+        .collect();
+    let racy = dependent
+        .iter()
+        .filter(|a| a.nd_effect == ND_LOCALLY_NONDET)
+        .count();
+
+    report.push_str("ENVIRONMENT DEPENDENCE:\n");
+    report.push_str(&format!(
+        "  Origins: {}\n",
+        origins
+            .iter()
+            .map(|(category, count)| format!("{} {}", count, category))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    report.push_str(&format!(
+        "  Operators depending on the environment: {} ({} also locally nondeterministic)\n\n",
+        dependent.len(),
+        racy
+    ));
+}
+
+/// Summarize how operators were classified, listing those the label table
+/// doesn't cover
+fn push_coverage(report: &mut String, ir: &HydroIr, results: &AnalysisResult) {
+    let gaps = coverage_gaps(ir, &results.classifier);
+    let unclassified: usize = gaps.iter().map(|g| g.count).sum();
+
+    report.push_str("SEMANTICS COVERAGE:\n");
+    report.push_str(&format!("  Profile: {}\n", results.classifier.profile.name));
+    report.push_str(&format!(
+        "  Classified by label: {} of {} operators\n",
+        ir.nodes.len() - unclassified,
+        ir.nodes.len()
+    ));
+    if !gaps.is_empty() {
+        report.push_str("  Unclassified operators:\n");
+        for gap in &gaps {
+            let fallback = match gap.source {
+                SemanticsSource::Default => "conservative default",
+                _ => "nodeType fallback",
+            };
+            report.push_str(&format!(
+                "    {} × {} ({}, {})\n",
+                gap.count, gap.label, gap.node_type, fallback
+            ));
+        }
+    }
+    report.push('\n');
+}
+
+fn push_call_tree(report: &mut String, node: &CallTreeNode, depth: usize) {
+    report.push_str(&format!(
+        "{}{}: {}\n",
+        "  ".repeat(depth),
+        node.short_name(),
+        format_issue_counts(&node.issues)
+    ));
+    for child in &node.children {
+        push_call_tree(report, child, depth + 1);
+    }
+}
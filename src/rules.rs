@@ -0,0 +1,1063 @@
+// Declarative lint rules over the graph
+//
+// A small Datalog dialect for ad-hoc structural checks. Rules query the
+// relations in `facts::BASE_RELATIONS`, may define their own (recursive)
+// relations, and report findings by deriving `node_issue(Kind, Node, Message)`
+// or `edge_issue(Kind, Edge, Message)` facts:
+//
+//     // Nodes reachable from a node without passing through a persist
+//     unpersisted(X, Y) :- edge(_, X, Y), !node(Y, "persist", _, _).
+//     unpersisted(X, Z) :- unpersisted(X, Y), edge(_, Y, Z), !node(Z, "persist", _, _).
+//
+//     node_issue("UnpersistedNonDet", N, "observenondet fed by an external input") :-
+//         node(S, "external_input", _, _), unpersisted(S, N), node(N, "observenondet", _, _).
+//
+// Syntax: variables start with an uppercase letter, `_` is a wildcard,
+// constants are quoted strings, numbers or lowercase identifiers. Body
+// literals are atoms, negated atoms (`!atom`), and `X = Y` / `X != Y`.
+// Comments start with `//` or `%`. Negation must be stratified.
+
+use crate::analysis::AnalysisResult;
+use crate::facts::{Tuple, base_arity, extract_facts};
+use crate::model::Issue;
+use crate::pass::{AnalysisPass, PassContext};
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+const NODE_ISSUE: &str = "node_issue";
+const EDGE_ISSUE: &str = "edge_issue";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Var(usize),
+    Const(String),
+    Wildcard,
+}
+
+#[derive(Debug, Clone)]
+struct Atom {
+    pred: String,
+    args: Vec<Term>,
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Pos(Atom),
+    Neg(Atom),
+    Eq(Term, Term),
+    Neq(Term, Term),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    head: Atom,
+    /// Body literals, ordered so that every literal's variables are bound
+    /// by the time it is evaluated
+    body: Vec<Literal>,
+    num_vars: usize,
+    origin: String,
+}
+
+/// A validated, stratified set of rules
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    /// Derived predicates grouped into strata, in evaluation order
+    strata: Vec<Vec<String>>,
+}
+
+impl RuleSet {
+    /// Parse and validate rules from source text; `origin` is used in diagnostics
+    pub fn parse(source: &str, origin: &str) -> Result<Self> {
+        let rules = Parser::new(source, origin).parse_program()?;
+        Self::from_rules(rules)
+    }
+
+    /// Load and validate rules from one or more files as a single program
+    pub fn load_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut rules = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let source = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read rules file: {}", path.display()))?;
+            rules.extend(Parser::new(&source, &path.display().to_string()).parse_program()?);
+        }
+        Self::from_rules(rules)
+    }
+
+    /// Number of rules in the program
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn from_rules(rules: Vec<Rule>) -> Result<Self> {
+        // Arity of every derived predicate, checked for consistency
+        let mut arities: HashMap<&str, usize> = HashMap::new();
+        for rule in &rules {
+            let head = &rule.head;
+            if base_arity(&head.pred).is_some() {
+                bail!(
+                    "{}: cannot define rules for built-in relation '{}'",
+                    rule.origin,
+                    head.pred
+                );
+            }
+            if let Some(&arity) = arities.get(head.pred.as_str())
+                && arity != head.args.len()
+            {
+                bail!(
+                    "{}: '{}' used with {} arguments, previously {}",
+                    rule.origin,
+                    head.pred,
+                    head.args.len(),
+                    arity
+                );
+            }
+            arities.insert(&head.pred, head.args.len());
+        }
+        for (pred, expected) in [(NODE_ISSUE, 3), (EDGE_ISSUE, 3)] {
+            if let Some(&arity) = arities.get(pred)
+                && arity != expected
+            {
+                bail!(
+                    "'{}' must have {} arguments (Kind, Subject, Message)",
+                    pred,
+                    expected
+                );
+            }
+        }
+        for rule in &rules {
+            for literal in &rule.body {
+                if let Literal::Pos(atom) | Literal::Neg(atom) = literal {
+                    let expected =
+                        base_arity(&atom.pred).or(arities.get(atom.pred.as_str()).copied());
+                    match expected {
+                        None => bail!("{}: unknown relation '{}'", rule.origin, atom.pred),
+                        Some(arity) if arity != atom.args.len() => bail!(
+                            "{}: '{}' expects {} arguments, got {}",
+                            rule.origin,
+                            atom.pred,
+                            arity,
+                            atom.args.len()
+                        ),
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+
+        let strata = stratify(&rules)?;
+        Ok(RuleSet { rules, strata })
+    }
+
+    /// Evaluate the program over a set of base facts, returning all derived relations
+    pub fn evaluate(&self, base: &BTreeMap<&str, Vec<Tuple>>) -> BTreeMap<String, Vec<Tuple>> {
+        let mut db: HashMap<String, Relation> = HashMap::new();
+        for (name, tuples) in base {
+            let mut relation = Relation::default();
+            for tuple in tuples {
+                relation.insert(tuple.clone());
+            }
+            db.insert(name.to_string(), relation);
+        }
+
+        for stratum in &self.strata {
+            let in_stratum: HashSet<&str> = stratum.iter().map(|s| s.as_str()).collect();
+            let rules: Vec<&Rule> = self
+                .rules
+                .iter()
+                .filter(|r| in_stratum.contains(r.head.pred.as_str()))
+                .collect();
+            for pred in stratum {
+                db.entry(pred.clone()).or_default();
+            }
+
+            // Initial round over full relations, then semi-naive rounds over deltas
+            let mut delta: HashMap<String, Relation> = HashMap::new();
+            for rule in &rules {
+                for tuple in eval_rule(rule, &db, None) {
+                    add_new(&mut delta, &db, &rule.head.pred, tuple);
+                }
+            }
+            merge_delta(&mut db, &delta);
+
+            while delta.values().any(|r| !r.tuples.is_empty()) {
+                let mut next: HashMap<String, Relation> = HashMap::new();
+                for rule in &rules {
+                    for (pos, literal) in rule.body.iter().enumerate() {
+                        if let Literal::Pos(atom) = literal
+                            && let Some(changed) = delta.get(&atom.pred)
+                            && !changed.tuples.is_empty()
+                        {
+                            for tuple in eval_rule(rule, &db, Some((pos, changed))) {
+                                add_new(&mut next, &db, &rule.head.pred, tuple);
+                            }
+                        }
+                    }
+                }
+                merge_delta(&mut db, &next);
+                delta = next;
+            }
+        }
+
+        let mut derived = BTreeMap::new();
+        for pred in self.strata.iter().flatten() {
+            let mut tuples = db.remove(pred).map(|r| r.tuples).unwrap_or_default();
+            tuples.sort();
+            derived.insert(pred.clone(), tuples);
+        }
+        derived
+    }
+}
+
+/// Assign derived predicates to strata so negated predicates are fully computed first
+fn stratify(rules: &[Rule]) -> Result<Vec<Vec<String>>> {
+    let derived: Vec<&str> = {
+        let mut seen = HashSet::new();
+        rules
+            .iter()
+            .map(|r| r.head.pred.as_str())
+            .filter(|p| seen.insert(*p))
+            .collect()
+    };
+    let mut stratum: HashMap<&str, usize> = derived.iter().map(|p| (*p, 0)).collect();
+
+    // Bellman-Ford style relaxation; a stratum beyond the predicate count means
+    // a cycle through negation
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in rules {
+            let head = rule.head.pred.as_str();
+            for literal in &rule.body {
+                let (atom, strict) = match literal {
+                    Literal::Pos(atom) => (atom, false),
+                    Literal::Neg(atom) => (atom, true),
+                    _ => continue,
+                };
+                let Some(&body_stratum) = stratum.get(atom.pred.as_str()) else {
+                    continue; // built-in relation
+                };
+                let required = body_stratum + usize::from(strict);
+                if stratum[head] < required {
+                    if required > derived.len() {
+                        bail!(
+                            "{}: '{}' depends negatively on itself through '{}'; negation must be stratified",
+                            rule.origin,
+                            head,
+                            atom.pred
+                        );
+                    }
+                    stratum.insert(head, required);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let max = stratum.values().copied().max().unwrap_or(0);
+    let mut strata = vec![Vec::new(); if derived.is_empty() { 0 } else { max + 1 }];
+    for pred in derived {
+        strata[stratum[pred]].push(pred.to_string());
+    }
+    Ok(strata)
+}
+
+#[derive(Debug, Default)]
+struct Relation {
+    tuples: Vec<Tuple>,
+    set: HashSet<Tuple>,
+    /// Per-column index from value to tuple positions
+    index: Vec<HashMap<String, Vec<usize>>>,
+}
+
+impl Relation {
+    fn insert(&mut self, tuple: Tuple) -> bool {
+        if self.set.contains(&tuple) {
+            return false;
+        }
+        if self.index.len() < tuple.len() {
+            self.index.resize_with(tuple.len(), HashMap::new);
+        }
+        let pos = self.tuples.len();
+        for (col, value) in tuple.iter().enumerate() {
+            self.index[col].entry(value.clone()).or_default().push(pos);
+        }
+        self.set.insert(tuple.clone());
+        self.tuples.push(tuple);
+        true
+    }
+
+    fn contains(&self, tuple: &Tuple) -> bool {
+        self.set.contains(tuple)
+    }
+
+    /// Tuples agreeing with every bound position of `pattern`
+    fn matching(&self, pattern: &[Option<&str>]) -> Vec<&Tuple> {
+        let fits = |tuple: &&Tuple| {
+            pattern
+                .iter()
+                .zip(tuple.iter())
+                .all(|(want, have)| want.is_none_or(|w| w == have))
+        };
+        // Probe the index on the first bound column, then filter on the rest
+        match pattern.iter().position(|p| p.is_some()) {
+            Some(col) => self
+                .index
+                .get(col)
+                .and_then(|idx| idx.get(pattern[col].unwrap_or_default()))
+                .into_iter()
+                .flatten()
+                .map(|&pos| &self.tuples[pos])
+                .filter(fits)
+                .collect(),
+            None => self.tuples.iter().filter(fits).collect(),
+        }
+    }
+}
+
+fn add_new(
+    out: &mut HashMap<String, Relation>,
+    db: &HashMap<String, Relation>,
+    pred: &str,
+    tuple: Tuple,
+) {
+    if db.get(pred).is_some_and(|r| r.contains(&tuple)) {
+        return;
+    }
+    out.entry(pred.to_string()).or_default().insert(tuple);
+}
+
+fn merge_delta(db: &mut HashMap<String, Relation>, delta: &HashMap<String, Relation>) {
+    for (pred, relation) in delta {
+        let target = db.entry(pred.clone()).or_default();
+        for tuple in &relation.tuples {
+            target.insert(tuple.clone());
+        }
+    }
+}
+
+/// Evaluate one rule; if `delta` is given, the positive literal at that body
+/// position ranges over the delta relation instead of the full one
+fn eval_rule(
+    rule: &Rule,
+    db: &HashMap<String, Relation>,
+    delta: Option<(usize, &Relation)>,
+) -> Vec<Tuple> {
+    let mut out = Vec::new();
+    let mut bindings: Vec<Option<String>> = vec![None; rule.num_vars];
+    eval_body(rule, 0, db, delta, &mut bindings, &mut out);
+    out
+}
+
+fn resolve<'b>(term: &'b Term, bindings: &'b [Option<String>]) -> Option<&'b str> {
+    match term {
+        Term::Const(c) => Some(c),
+        Term::Var(v) => bindings[*v].as_deref(),
+        Term::Wildcard => None,
+    }
+}
+
+fn eval_body(
+    rule: &Rule,
+    pos: usize,
+    db: &HashMap<String, Relation>,
+    delta: Option<(usize, &Relation)>,
+    bindings: &mut Vec<Option<String>>,
+    out: &mut Vec<Tuple>,
+) {
+    let Some(literal) = rule.body.get(pos) else {
+        let tuple = rule
+            .head
+            .args
+            .iter()
+            .map(|t| resolve(t, bindings).unwrap_or_default().to_string())
+            .collect();
+        out.push(tuple);
+        return;
+    };
+
+    match literal {
+        Literal::Pos(atom) => {
+            let relation = match delta {
+                Some((delta_pos, rel)) if delta_pos == pos => Some(rel),
+                _ => db.get(&atom.pred),
+            };
+            let Some(relation) = relation else { return };
+            let pattern: Vec<Option<&str>> =
+                atom.args.iter().map(|t| resolve(t, bindings)).collect();
+            let matches = relation.matching(&pattern);
+            for tuple in matches {
+                let mut newly_bound = Vec::new();
+                let mut consistent = true;
+                for (term, value) in atom.args.iter().zip(tuple) {
+                    if let Term::Var(v) = term {
+                        match &bindings[*v] {
+                            Some(bound) if bound != value => {
+                                consistent = false;
+                                break;
+                            }
+                            Some(_) => {}
+                            None => {
+                                bindings[*v] = Some(value.clone());
+                                newly_bound.push(*v);
+                            }
+                        }
+                    }
+                }
+                if consistent {
+                    eval_body(rule, pos + 1, db, delta, bindings, out);
+                }
+                for v in newly_bound {
+                    bindings[v] = None;
+                }
+            }
+        }
+        Literal::Neg(atom) => {
+            let pattern: Vec<Option<&str>> =
+                atom.args.iter().map(|t| resolve(t, bindings)).collect();
+            let present = db
+                .get(&atom.pred)
+                .is_some_and(|rel| !rel.matching(&pattern).is_empty());
+            if !present {
+                eval_body(rule, pos + 1, db, delta, bindings, out);
+            }
+        }
+        Literal::Eq(lhs, rhs) => {
+            match (
+                resolve(lhs, bindings).map(str::to_string),
+                resolve(rhs, bindings).map(str::to_string),
+            ) {
+                (Some(a), Some(b)) => {
+                    if a == b {
+                        eval_body(rule, pos + 1, db, delta, bindings, out);
+                    }
+                }
+                (Some(value), None) | (None, Some(value)) => {
+                    let var = match (lhs, rhs) {
+                        (Term::Var(v), _) if bindings[*v].is_none() => *v,
+                        (_, Term::Var(v)) => *v,
+                        _ => return,
+                    };
+                    bindings[var] = Some(value);
+                    eval_body(rule, pos + 1, db, delta, bindings, out);
+                    bindings[var] = None;
+                }
+                (None, None) => {}
+            }
+        }
+        Literal::Neq(lhs, rhs) => {
+            if resolve(lhs, bindings) != resolve(rhs, bindings) {
+                eval_body(rule, pos + 1, db, delta, bindings, out);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Var(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Implies,
+    Bang,
+    Eq,
+    Neq,
+}
+
+struct Parser<'s> {
+    chars: std::iter::Peekable<std::str::CharIndices<'s>>,
+    source: &'s str,
+    origin: &'s str,
+    line: usize,
+    col: usize,
+    peeked: Option<(Token, usize, usize)>,
+}
+
+impl<'s> Parser<'s> {
+    fn new(source: &'s str, origin: &'s str) -> Self {
+        Parser {
+            chars: source.char_indices().peekable(),
+            source,
+            origin,
+            line: 1,
+            col: 1,
+            peeked: None,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn error<T>(&self, line: usize, col: usize, msg: impl std::fmt::Display) -> Result<T> {
+        bail!("{}:{}:{}: {}", self.origin, line, col, msg)
+    }
+
+    /// Next token with its starting line and column, or None at end of input
+    fn lex(&mut self) -> Result<Option<(Token, usize, usize)>> {
+        loop {
+            match self.chars.peek().map(|&(_, c)| c) {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('%') => {
+                    while self.chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                        self.bump();
+                    }
+                }
+                Some('/') => {
+                    let (start, _) = *self.chars.peek().unwrap_or(&(0, '/'));
+                    if self.source[start..].starts_with("//") {
+                        while self.chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                            self.bump();
+                        }
+                    } else {
+                        return self.error(self.line, self.col, "unexpected '/'");
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let (line, col) = (self.line, self.col);
+        let Some(&(start, c)) = self.chars.peek() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '(' => {
+                self.bump();
+                Token::LParen
+            }
+            ')' => {
+                self.bump();
+                Token::RParen
+            }
+            ',' => {
+                self.bump();
+                Token::Comma
+            }
+            '.' => {
+                self.bump();
+                Token::Dot
+            }
+            '=' => {
+                self.bump();
+                Token::Eq
+            }
+            '!' => {
+                self.bump();
+                if self.chars.peek().is_some_and(|&(_, c)| c == '=') {
+                    self.bump();
+                    Token::Neq
+                } else {
+                    Token::Bang
+                }
+            }
+            ':' => {
+                self.bump();
+                if self.chars.peek().is_some_and(|&(_, c)| c == '-') {
+                    self.bump();
+                    Token::Implies
+                } else {
+                    return self.error(line, col, "expected ':-'");
+                }
+            }
+            '"' => {
+                self.bump();
+                let mut value = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some('n') => value.push('\n'),
+                            Some(other) => value.push(other),
+                            None => return self.error(line, col, "unterminated string"),
+                        },
+                        Some(other) => value.push(other),
+                        None => return self.error(line, col, "unterminated string"),
+                    }
+                }
+                Token::Str(value)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start;
+                while let Some(&(idx, c)) = self.chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = idx + c.len_utf8();
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                let word = &self.source[start..end];
+                if word.starts_with(|c: char| c.is_uppercase() || c == '_') {
+                    Token::Var(word.to_string())
+                } else {
+                    Token::Ident(word.to_string())
+                }
+            }
+            other => return self.error(line, col, format!("unexpected character '{}'", other)),
+        };
+        Ok(Some((token, line, col)))
+    }
+
+    fn peek(&mut self) -> Result<Option<&(Token, usize, usize)>> {
+        if self.peeked.is_none() {
+            self.peeked = self.lex()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<(Token, usize, usize)>> {
+        self.peek()?;
+        Ok(self.peeked.take())
+    }
+
+    fn expect(&mut self, want: Token, what: &str) -> Result<()> {
+        match self.next()? {
+            Some((tok, _, _)) if tok == want => Ok(()),
+            Some((tok, line, col)) => {
+                self.error(line, col, format!("expected {}, found {:?}", what, tok))
+            }
+            None => self.error(
+                self.line,
+                self.col,
+                format!("expected {}, found end of input", what),
+            ),
+        }
+    }
+
+    fn parse_program(mut self) -> Result<Vec<Rule>> {
+        let mut rules = Vec::new();
+        while self.peek()?.is_some() {
+            rules.push(self.parse_rule()?);
+        }
+        Ok(rules)
+    }
+
+    fn parse_rule(&mut self) -> Result<Rule> {
+        let (line, col) = match self.peek()? {
+            Some(&(_, line, col)) => (line, col),
+            None => (self.line, self.col),
+        };
+        let origin = format!("{}:{}:{}", self.origin, line, col);
+        let mut vars: HashMap<String, usize> = HashMap::new();
+
+        let head = self.parse_atom(&mut vars)?;
+        let mut body = Vec::new();
+        match self.next()? {
+            Some((Token::Dot, _, _)) => {}
+            Some((Token::Implies, _, _)) => loop {
+                body.push(self.parse_literal(&mut vars)?);
+                match self.next()? {
+                    Some((Token::Comma, _, _)) => continue,
+                    Some((Token::Dot, _, _)) => break,
+                    Some((tok, l, c)) => {
+                        return self.error(l, c, format!("expected ',' or '.', found {:?}", tok));
+                    }
+                    None => return self.error(self.line, self.col, "expected '.' at end of rule"),
+                }
+            },
+            Some((tok, l, c)) => {
+                return self.error(l, c, format!("expected ':-' or '.', found {:?}", tok));
+            }
+            None => return self.error(self.line, self.col, "expected '.' at end of rule"),
+        }
+
+        if head.args.contains(&Term::Wildcard) {
+            bail!("{}: wildcard '_' is not allowed in a rule head", origin);
+        }
+
+        // Positive atoms bind variables; equalities may bind from bound terms;
+        // negations and inequalities only test, so they run last
+        let mut ordered: Vec<Literal> = body
+            .iter()
+            .filter(|l| matches!(l, Literal::Pos(_)))
+            .cloned()
+            .collect();
+        let mut bound: HashSet<usize> = HashSet::new();
+        for literal in &ordered {
+            if let Literal::Pos(atom) = literal {
+                bound.extend(atom.args.iter().filter_map(|t| match t {
+                    Term::Var(v) => Some(*v),
+                    _ => None,
+                }));
+            }
+        }
+        let is_bound = |t: &Term, bound: &HashSet<usize>| match t {
+            Term::Var(v) => bound.contains(v),
+            Term::Const(_) => true,
+            Term::Wildcard => false,
+        };
+        let mut pending: Vec<&Literal> = body
+            .iter()
+            .filter(|l| matches!(l, Literal::Eq(..)))
+            .collect();
+        while !pending.is_empty() {
+            let before = pending.len();
+            pending.retain(|literal| {
+                let Literal::Eq(lhs, rhs) = literal else {
+                    return false;
+                };
+                if is_bound(lhs, &bound) || is_bound(rhs, &bound) {
+                    for t in [lhs, rhs] {
+                        if let Term::Var(v) = t {
+                            bound.insert(*v);
+                        }
+                    }
+                    ordered.push((*literal).clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            if pending.len() == before {
+                bail!("{}: equality between unbound variables", origin);
+            }
+        }
+        ordered.extend(
+            body.iter()
+                .filter(|l| matches!(l, Literal::Neg(_) | Literal::Neq(..)))
+                .cloned(),
+        );
+
+        let var_name = |v: usize| {
+            vars.iter()
+                .find(|&(_, &idx)| idx == v)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default()
+        };
+        for literal in &ordered {
+            let terms: Vec<&Term> = match literal {
+                Literal::Neg(atom) => atom.args.iter().collect(),
+                Literal::Neq(lhs, rhs) => vec![lhs, rhs],
+                _ => continue,
+            };
+            for term in terms {
+                if let Term::Var(v) = term
+                    && !bound.contains(v)
+                {
+                    bail!(
+                        "{}: variable '{}' must appear in a positive body atom",
+                        origin,
+                        var_name(*v)
+                    );
+                }
+            }
+        }
+        for term in &head.args {
+            if let Term::Var(v) = term
+                && !bound.contains(v)
+            {
+                bail!(
+                    "{}: head variable '{}' must appear in a positive body atom",
+                    origin,
+                    var_name(*v)
+                );
+            }
+        }
+
+        Ok(Rule {
+            head,
+            body: ordered,
+            num_vars: vars.len(),
+            origin,
+        })
+    }
+
+    fn parse_literal(&mut self, vars: &mut HashMap<String, usize>) -> Result<Literal> {
+        if matches!(self.peek()?, Some((Token::Bang, _, _))) {
+            self.next()?;
+            return Ok(Literal::Neg(self.parse_atom(vars)?));
+        }
+        if matches!(self.peek()?, Some((Token::Ident(_), _, _))) {
+            // An identifier followed by '(' is an atom; otherwise it's a constant term
+            let (tok, line, col) = self.next()?.expect("peeked");
+            if matches!(self.peek()?, Some((Token::LParen, _, _))) {
+                let Token::Ident(pred) = tok else {
+                    unreachable!()
+                };
+                return self.parse_atom_args(pred, vars);
+            }
+            let Token::Ident(word) = tok else {
+                unreachable!()
+            };
+            return self.parse_comparison(Term::Const(word), vars, line, col);
+        }
+        let (line, col) = match self.peek()? {
+            Some(&(_, line, col)) => (line, col),
+            None => (self.line, self.col),
+        };
+        let lhs = self.parse_term(vars)?;
+        self.parse_comparison(lhs, vars, line, col)
+    }
+
+    fn parse_comparison(
+        &mut self,
+        lhs: Term,
+        vars: &mut HashMap<String, usize>,
+        line: usize,
+        col: usize,
+    ) -> Result<Literal> {
+        match self.next()? {
+            Some((Token::Eq, _, _)) => Ok(Literal::Eq(lhs, self.parse_term(vars)?)),
+            Some((Token::Neq, _, _)) => Ok(Literal::Neq(lhs, self.parse_term(vars)?)),
+            _ => self.error(line, col, "expected an atom or a comparison"),
+        }
+    }
+
+    fn parse_atom(&mut self, vars: &mut HashMap<String, usize>) -> Result<Atom> {
+        match self.next()? {
+            Some((Token::Ident(pred), _, _)) => match self.parse_atom_args(pred, vars)? {
+                Literal::Pos(atom) => Ok(atom),
+                _ => unreachable!(),
+            },
+            Some((tok, line, col)) => self.error(
+                line,
+                col,
+                format!("expected a relation name, found {:?}", tok),
+            ),
+            None => self.error(self.line, self.col, "expected a relation name"),
+        }
+    }
+
+    fn parse_atom_args(
+        &mut self,
+        pred: String,
+        vars: &mut HashMap<String, usize>,
+    ) -> Result<Literal> {
+        self.expect(Token::LParen, "'('")?;
+        let mut args = vec![self.parse_term(vars)?];
+        loop {
+            match self.next()? {
+                Some((Token::Comma, _, _)) => args.push(self.parse_term(vars)?),
+                Some((Token::RParen, _, _)) => break,
+                Some((tok, line, col)) => {
+                    return self.error(line, col, format!("expected ',' or ')', found {:?}", tok));
+                }
+                None => return self.error(self.line, self.col, "expected ')'"),
+            }
+        }
+        Ok(Literal::Pos(Atom { pred, args }))
+    }
+
+    fn parse_term(&mut self, vars: &mut HashMap<String, usize>) -> Result<Term> {
+        match self.next()? {
+            Some((Token::Var(name), _, _)) if name == "_" => Ok(Term::Wildcard),
+            Some((Token::Var(name), _, _)) => {
+                let next = vars.len();
+                Ok(Term::Var(*vars.entry(name).or_insert(next)))
+            }
+            Some((Token::Str(value), _, _)) | Some((Token::Ident(value), _, _)) => {
+                Ok(Term::Const(value))
+            }
+            Some((tok, line, col)) => {
+                self.error(line, col, format!("expected a term, found {:?}", tok))
+            }
+            None => self.error(self.line, self.col, "expected a term"),
+        }
+    }
+}
+
+/// Runs a rule program and turns derived `node_issue`/`edge_issue` facts into issues
+pub struct RulePass {
+    rules: RuleSet,
+}
+
+impl RulePass {
+    pub fn new(rules: RuleSet) -> Self {
+        RulePass { rules }
+    }
+}
+
+impl AnalysisPass for RulePass {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    fn dependencies(&self) -> &[&str] {
+        &["nd", "calm"]
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let base = extract_facts(ctx.ir, results);
+        let derived = self.rules.evaluate(&base);
+
+        for tuple in derived.get(NODE_ISSUE).into_iter().flatten() {
+            if let [kind, node_id, message] = tuple.as_slice()
+                && let Some(analysis) = results.node_analyses.get_mut(node_id)
+            {
+                analysis.issues.push(Issue {
+                    kind: kind.clone(),
+                    subject: node_id.clone(),
+                    message: message.clone(),
+                    affected_critical_edges: Vec::new(),
+                });
+            }
+        }
+        for tuple in derived.get(EDGE_ISSUE).into_iter().flatten() {
+            if let [kind, edge_id, message] = tuple.as_slice()
+                && let Some(analysis) = results.edge_analyses.get_mut(edge_id)
+            {
+                analysis.issues.push(Issue {
+                    kind: kind.clone(),
+                    subject: edge_id.clone(),
+                    message: message.clone(),
+                    affected_critical_edges: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+    use crate::pass::PassRegistry;
+
+    fn run_rules(ir: &crate::model::HydroIr, source: &str) -> AnalysisResult {
+        let mut registry = PassRegistry::with_builtin_passes();
+        registry.register(Box::new(RulePass::new(
+            RuleSet::parse(source, "test.dl").unwrap(),
+        )));
+        registry.run(ir).unwrap()
+    }
+
+    #[test]
+    fn test_recursive_rule_with_negation() {
+        // external_input -> map -> observenondet, and external_input -> persist -> observenondet
+        let mut b = IrBuilder::new();
+        let input = b.node("external_input");
+        let map = b.node("map");
+        let direct = b.node("observenondet");
+        let persist = b.node("persist");
+        let guarded = b.node("observenondet");
+        b.edge(&input, &map, &["Local"]);
+        b.edge(&map, &direct, &["Local"]);
+        b.edge(&input, &persist, &["Local"]);
+        b.edge(&persist, &guarded, &["Local"]);
+        let ir = b.build();
+
+        let results = run_rules(
+            &ir,
+            r#"
+            // Paths that never pass through a persist
+            unpersisted(X, Y) :- edge(_, X, Y), !node(Y, persist, _, _).
+            unpersisted(X, Z) :- unpersisted(X, Y), edge(_, Y, Z), !node(Z, persist, _, _).
+
+            node_issue("UnpersistedNonDet", N, "observenondet fed by an external input") :-
+                node(S, external_input, _, _), unpersisted(S, N), node(N, observenondet, _, _).
+            "#,
+        );
+
+        let kinds = |id: &str| -> Vec<String> {
+            results.node_analyses[id]
+                .issues
+                .iter()
+                .map(|i| i.kind.clone())
+                .collect()
+        };
+        assert!(kinds(&direct).contains(&"UnpersistedNonDet".to_string()));
+        assert!(!kinds(&guarded).contains(&"UnpersistedNonDet".to_string()));
+    }
+
+    #[test]
+    fn test_rules_see_analysis_facts() {
+        let mut b = IrBuilder::new();
+        let src = b.node_at("source_stream", 0, "Process");
+        let sink = b.node_at("for_each", 1, "Cluster");
+        let e = b.edge(&src, &sink, &["Network", "Unbounded"]);
+        let ir = b.build();
+
+        let results = run_rules(
+            &ir,
+            r#"
+            edge_issue("UnsafeIntoCluster", E, "CALM-unsafe edge into a cluster") :-
+                calm_critical(E), calm(E, "CalmUnsafe"), edge(E, _, D),
+                node(D, _, _, L), location(L, "Cluster").
+            "#,
+        );
+        assert!(
+            results.edge_analyses[&e]
+                .issues
+                .iter()
+                .any(|i| i.kind == "UnsafeIntoCluster")
+        );
+    }
+
+    #[test]
+    fn test_equality_and_inequality() {
+        let rules = RuleSet::parse(
+            r#"
+            same(X, Y) :- node(X, L, _, _), node(Y, M, _, _), L = M, X != Y.
+            "#,
+            "test.dl",
+        )
+        .unwrap();
+        let mut b = IrBuilder::new();
+        b.node("map");
+        b.node("map");
+        b.node("filter");
+        let ir = b.build();
+        let results = crate::analysis::run_analysis(&ir);
+        let derived = rules.evaluate(&extract_facts(&ir, &results));
+        assert_eq!(
+            derived["same"],
+            vec![
+                vec!["0".to_string(), "1".to_string()],
+                vec!["1".to_string(), "0".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_programs() {
+        let cases = [
+            ("p(X) :- q(X).", "unknown relation"),
+            ("p(X) :- node(X, _, _).", "expects 4 arguments"),
+            ("node(X, a, b, c) :- edge(X, _, _).", "built-in relation"),
+            ("p(X, Y) :- edge(X, _, _).", "head variable 'Y'"),
+            ("p(X) :- edge(X, _, _), !edge(_, Y, _).", "variable 'Y'"),
+            (
+                "p(X) :- edge(X, _, _), !q(X).\nq(X) :- edge(X, _, _), !p(X).",
+                "stratified",
+            ),
+            ("p(X) :- edge(X, _, _)", "expected '.'"),
+            ("p(X) :- edge(X, _, _), @.", "unexpected character"),
+        ];
+        for (source, expected) in cases {
+            let err = RuleSet::parse(source, "bad.dl").unwrap_err().to_string();
+            assert!(
+                err.contains(expected),
+                "error for {:?} should mention {:?}, got {:?}",
+                source,
+                expected,
+                err
+            );
+            assert!(
+                err.starts_with("bad.dl:"),
+                "error should carry a location: {}",
+                err
+            );
+        }
+    }
+}
@@ -1,5 +1,9 @@
 // Operator semantics classification for Hydro operators
 
+use crate::backtrace::Backtrace;
+use crate::environment::EnvironmentModel;
+use crate::model::Port;
+
 /// Nondeterminism effect classification
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NdEffect {
@@ -8,81 +12,233 @@ pub enum NdEffect {
     ExternalNonDet,
 }
 
+/// What a nondeterministic operator's output depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NdKind {
+    /// Arrival order: `network`, or `observe_non_det` over a `NoOrder` stream
+    Ordering,
+    /// Where batch boundaries fall: `batch`, or `observe_non_det` over a `Bounded` batch
+    Batching,
+    /// Wall-clock time: `timeout`, `sample_every`, `source_interval`
+    Timing,
+    /// Which cluster members are up: `source_cluster_members`
+    Membership,
+    /// What external clients send: `external_input`, `source_stream`
+    Input,
+    /// Nondeterministic for no reason hydrolysis can name
+    Unknown,
+}
+
+impl NdKind {
+    pub const ALL: [NdKind; 6] = [
+        NdKind::Ordering,
+        NdKind::Batching,
+        NdKind::Timing,
+        NdKind::Membership,
+        NdKind::Input,
+        NdKind::Unknown,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NdKind::Ordering => "ordering",
+            NdKind::Batching => "batching",
+            NdKind::Timing => "timing",
+            NdKind::Membership => "membership",
+            NdKind::Input => "input",
+            NdKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for NdKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A set of ND kinds, ordered by inclusion; the join of two sets is their union
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NdKinds(u8);
+
+impl NdKinds {
+    pub const EMPTY: NdKinds = NdKinds(0);
+    pub const ALL: NdKinds = NdKinds((1 << NdKind::ALL.len()) - 1);
+
+    pub fn single(kind: NdKind) -> Self {
+        NdKinds(1 << kind as u8)
+    }
+
+    pub fn contains(self, kind: NdKind) -> bool {
+        self.0 & Self::single(kind).0 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn join(self, other: NdKinds) -> Self {
+        NdKinds(self.0 | other.0)
+    }
+
+    /// The kinds in `self` that are not in `other`
+    pub fn without(self, other: NdKinds) -> Self {
+        NdKinds(self.0 & !other.0)
+    }
+
+    /// The kinds in the set, in `NdKind` order
+    pub fn iter(self) -> impl Iterator<Item = NdKind> {
+        NdKind::ALL
+            .into_iter()
+            .filter(move |kind| self.contains(*kind))
+    }
+}
+
+impl FromIterator<NdKind> for NdKinds {
+    fn from_iter<I: IntoIterator<Item = NdKind>>(iter: I) -> Self {
+        iter.into_iter().fold(NdKinds::EMPTY, |kinds, kind| {
+            kinds.join(NdKinds::single(kind))
+        })
+    }
+}
+
 /// Monotonicity classification
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Monotonicity {
     Always,
     Never,
     Depends,
+    /// Output shrinks as the input grows; only used for single input ports
+    Antitone,
 }
 
 /// Operator semantics combining ND effect and monotonicity
 #[derive(Debug, Clone, Copy)]
 pub struct OpSemantics {
     pub nd: NdEffect,
+    /// Monotonicity in all inputs together
     pub monotone: Monotonicity,
+    /// Monotonicity in individual input ports, for operators that treat
+    /// their inputs differently; empty when `monotone` applies to every input
+    pub ports: &'static [(Port, Monotonicity)],
+}
+
+impl OpSemantics {
+    /// Monotonicity in the input feeding `port`, if the operator distinguishes it
+    pub fn port_monotone(&self, port: Port) -> Option<Monotonicity> {
+        self.ports
+            .iter()
+            .find(|(p, _)| *p == port)
+            .map(|&(_, monotone)| monotone)
+    }
 }
 
+/// Set difference and anti-join grow with their positive input and shrink
+/// as the negated one grows; exports name the ports `pos`/`neg` or `left`/`right`
+const DIFFERENCE_PORTS: &[(Port, Monotonicity)] = &[
+    (Port::Pos, Monotonicity::Always),
+    (Port::Left, Monotonicity::Always),
+    (Port::Neg, Monotonicity::Antitone),
+    (Port::Right, Monotonicity::Antitone),
+];
+
+/// `cross_singleton` pairs every element of its stream (`left`) with the
+/// current value of its singleton (`right`): replacing the singleton retracts
+/// the pairs made with the old value
+const CROSS_SINGLETON_PORTS: &[(Port, Monotonicity)] = &[
+    (Port::Left, Monotonicity::Always),
+    (Port::Right, Monotonicity::Never),
+];
+
+/// Node types emitted by `hydro_lang::viz`
+pub const NODE_TYPES: &[&str] = &[
+    "Source",
+    "Transform",
+    "Join",
+    "Aggregation",
+    "Network",
+    "Sink",
+    "Tee",
+    "NonDeterministic",
+];
+
 /// Lookup operator semantics by node type
 pub fn get_semantics(node_type: &str) -> OpSemantics {
     match node_type {
         "Source" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "Transform" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "Join" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "Aggregation" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Depends,
+            ports: &[],
         },
         "Network" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "Sink" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "Tee" => OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         },
         "NonDeterministic" => OpSemantics {
             nd: NdEffect::LocallyNonDet,
             monotone: Monotonicity::Never,
+            ports: &[],
         },
         // Conservative default for unknown types
         _ => OpSemantics {
             nd: NdEffect::LocallyNonDet,
             monotone: Monotonicity::Never,
+            ports: &[],
         },
     }
 }
 
-/// Finer-grained semantics lookup by operator label
+/// Finer-grained semantics lookup by operator label, accepting the spellings
+/// of every known Hydro release (the `compat` profile)
 ///
 /// Returns None only for truly unknown operators - caller should handle this explicitly
 pub fn get_semantics_by_label(label: &str) -> Option<OpSemantics> {
-    match label.to_lowercase().as_str() {
+    COMPAT.semantics_by_label(label)
+}
+
+/// Semantics of an operator by its canonical (method) name
+fn canonical_semantics(name: &str) -> Option<OpSemantics> {
+    match name {
         // === MONOTONE TRANSFORMS ===
         // Simple element-wise transformations
-        "map" | "flat_map" | "flatmap" | "filter" | "filter_map" | "filtermap" | "inspect"
-        | "enumerate" | "cloned" => Some(OpSemantics {
+        // `counter` only measures throughput and passes its input through
+        "map" | "flat_map" | "filter" | "filter_map" | "inspect" | "enumerate" | "cloned"
+        | "counter" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // Type conversions and structural operations
         "cast"
         | "chain"
-        | "chainfirst"
+        | "chain_first"
         | "into_keyed"
         | "keys"
         | "resolve_futures"
@@ -95,23 +251,29 @@ pub fn get_semantics_by_label(label: &str) -> Option<OpSemantics> {
         | "atomic" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // === MONOTONE JOINS ===
-        "join"
-        | "cross_product"
-        | "crossproduct"
-        | "cross_singleton"
-        | "cross_product_nested_loop" => Some(OpSemantics {
+        "join" | "cross_product" | "cross_product_nested_loop" => Some(OpSemantics {
+            nd: NdEffect::Deterministic,
+            monotone: Monotonicity::Always,
+            ports: &[],
+        }),
+
+        "cross_singleton" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: CROSS_SINGLETON_PORTS,
         }),
 
         // === NON-MONOTONE OPERATIONS ===
-        // Set difference and anti-join require retractions
-        "difference" | "anti_join" | "antijoin" | "filter_not_in" => Some(OpSemantics {
+        // Set difference and anti-join require retractions, but only when
+        // the negated input grows
+        "difference" | "anti_join" | "filter_not_in" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Never,
+            ports: DIFFERENCE_PORTS,
         }),
 
         // Unique is monotone: adding input can only add to cumulative output, never retract.
@@ -119,6 +281,7 @@ pub fn get_semantics_by_label(label: &str) -> Option<OpSemantics> {
         "unique" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // === AGGREGATIONS (DEPENDS ON FUNCTION) ===
@@ -129,95 +292,308 @@ pub fn get_semantics_by_label(label: &str) -> Option<OpSemantics> {
         | "reduce_idempotent_commutative" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // Fold/reduce/scan - monotonicity depends on the aggregation function
         "fold"
         | "fold_keyed"
-        | "foldkeyed"
         | "fold_commutative"
         | "fold_idempotent"
         | "reduce"
         | "reduce_keyed"
-        | "reducekeyed"
         | "reduce_commutative"
         | "reduce_idempotent"
         | "reduce_keyed_watermark"
         | "scan" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Depends,
+            ports: &[],
         }),
 
         // Sort is non-monotone (requires seeing all elements)
         "sort" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Never,
+            ports: &[],
         }),
 
         // Min/max/count/first/last - depends on whether they're over lattices
         "min" | "max" | "count" | "first" | "last" | "collect_vec" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Depends,
+            ports: &[],
         }),
 
         // === NETWORK OPERATIONS ===
+        // Where batch boundaries fall is up to the runtime; a batch created by
+        // the networking layer is refined to deterministic by `network_batch`
         "batch" | "batch_atomic" => Some(OpSemantics {
-            nd: NdEffect::Deterministic,
+            nd: NdEffect::LocallyNonDet,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         "network" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // === NONDETERMINISTIC OPERATIONS ===
-        "observe_non_det" | "observenondet" | "nondet" => Some(OpSemantics {
+        "observe_non_det" | "nondet" => Some(OpSemantics {
             nd: NdEffect::LocallyNonDet,
             monotone: Monotonicity::Never,
+            ports: &[],
         }),
 
         // Sampling operations are nondeterministic
         "sample_every" | "timeout" => Some(OpSemantics {
             nd: NdEffect::LocallyNonDet,
             monotone: Monotonicity::Never,
+            ports: &[],
         }),
 
         // === STATE OPERATIONS ===
-        // Persist accumulates items and replays them each tick - monotone (only adds, never removes)
-        "persist" => Some(OpSemantics {
+        // Persist accumulates items and replays them each tick - monotone (only adds, never removes);
+        // unpersist undoes it, emitting each item once
+        "persist" | "unpersist" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
+        }),
+
+        // Delta emits only what is new since the last tick, so an item
+        // present in one tick's output is missing from the next
+        "delta" => Some(OpSemantics {
+            nd: NdEffect::Deterministic,
+            monotone: Monotonicity::Never,
+            ports: &[],
         }),
 
         // === STRUCTURAL OPERATIONS ===
         "tee" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // === SOURCES AND SINKS ===
-        "source_stream" | "source_iter" | "external_input" | "cycle_source"
+        "source_stream" | "source_iter" | "source_spin" | "external_input" | "cycle_source"
         | "singleton_source" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         "for_each" | "send_external" | "cycle_sink" | "dest_sink" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         // === CONDITIONAL OPERATIONS ===
         "filter_if_some" | "filter_if_none" => Some(OpSemantics {
             nd: NdEffect::Deterministic,
             monotone: Monotonicity::Always,
+            ports: &[],
         }),
 
         _ => None,
     }
 }
 
+/// The operator label table of a specific `hydro_lang` release
+///
+/// All releases share the canonical table in `canonical_semantics`, keyed by
+/// method name; a profile lists the spellings its release emits instead.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SemanticsProfile {
+    pub name: &'static str,
+    /// Release this profile describes (e.g. "0.14"), or None for `compat`
+    pub hydro_lang: Option<&'static str>,
+    pub description: &'static str,
+    /// Label spellings used in place of a canonical name
+    pub renames: &'static [(&'static str, &'static str)],
+    /// Whether a renamed operator's canonical spelling is rejected
+    pub exclusive: bool,
+}
+
+/// Spellings of `hydro_lang` 0.14 exports, which use lowercased IR node names
+const HYDRO_LANG_0_14_RENAMES: &[(&str, &str)] = &[
+    ("flatmap", "flat_map"),
+    ("filtermap", "filter_map"),
+    ("chainfirst", "chain_first"),
+    ("crossproduct", "cross_product"),
+    ("crosssingleton", "cross_singleton"),
+    ("antijoin", "anti_join"),
+    ("foldkeyed", "fold_keyed"),
+    ("reducekeyed", "reduce_keyed"),
+    ("observenondet", "observe_non_det"),
+    ("resolvefutures", "resolve_futures"),
+    ("resolvefuturesordered", "resolve_futures_ordered"),
+    ("defertick", "defer_tick"),
+    ("beginatomic", "begin_atomic"),
+    ("endatomic", "end_atomic"),
+];
+
+/// Accepts the spellings of every known release
+pub static COMPAT: SemanticsProfile = SemanticsProfile {
+    name: "compat",
+    hydro_lang: None,
+    description: "spellings of all known hydro_lang releases",
+    renames: HYDRO_LANG_0_14_RENAMES,
+    exclusive: false,
+};
+
+pub static HYDRO_LANG_0_13: SemanticsProfile = SemanticsProfile {
+    name: "hydro_lang-0.13",
+    hydro_lang: Some("0.13"),
+    description: "operators labelled by method name (filter_map, cross_product)",
+    renames: &[],
+    exclusive: true,
+};
+
+pub static HYDRO_LANG_0_14: SemanticsProfile = SemanticsProfile {
+    name: "hydro_lang-0.14",
+    hydro_lang: Some("0.14"),
+    description: "operators labelled by lowercased IR node (filtermap, crossproduct)",
+    renames: HYDRO_LANG_0_14_RENAMES,
+    exclusive: true,
+};
+
+/// All built-in profiles, `compat` first
+pub static PROFILES: &[&SemanticsProfile] = &[&COMPAT, &HYDRO_LANG_0_13, &HYDRO_LANG_0_14];
+
+/// Look up a built-in profile by name
+pub fn profile(name: &str) -> Option<&'static SemanticsProfile> {
+    PROFILES.iter().copied().find(|p| p.name == name)
+}
+
+/// Pick the profile for an export
+///
+/// Uses `metadata.hydroLangVersion` when it names a known release. Otherwise
+/// picks the release whose spellings classify the most operator labels, and
+/// falls back to `compat` when no release is a clear winner.
+pub fn detect_profile(ir: &crate::model::HydroIr) -> &'static SemanticsProfile {
+    if let Some(version) = ir
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get("hydroLangVersion"))
+        .and_then(|v| v.as_str())
+        && let Some(profile) = PROFILES
+            .iter()
+            .copied()
+            .find(|p| p.matches_version(version))
+    {
+        return profile;
+    }
+
+    let mut scores: Vec<(usize, &'static SemanticsProfile)> = PROFILES
+        .iter()
+        .copied()
+        .filter(|p| p.hydro_lang.is_some())
+        .map(|p| {
+            let known = ir
+                .nodes
+                .iter()
+                .filter_map(|n| n.label.as_deref())
+                .filter(|label| p.semantics_by_label(label).is_some())
+                .count();
+            (known, p)
+        })
+        .collect();
+    scores.sort_by_key(|(known, _)| std::cmp::Reverse(*known));
+    match scores.as_slice() {
+        [(best, profile), (second, _), ..] if best > second => profile,
+        [(_, profile)] => profile,
+        _ => &COMPAT,
+    }
+}
+
+impl SemanticsProfile {
+    /// Whether `version` (e.g. "0.14.2") belongs to this profile's release
+    pub fn matches_version(&self, version: &str) -> bool {
+        self.hydro_lang.is_some_and(|release| {
+            version
+                .strip_prefix(release)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    }
+
+    /// Canonical operator name for a label, or None if this release doesn't
+    /// spell any operator that way
+    pub fn canonical_label(&self, label: &str) -> Option<String> {
+        let label = label.to_lowercase();
+        if let Some((_, canonical)) = self.renames.iter().find(|(spelling, _)| *spelling == label) {
+            return Some(canonical.to_string());
+        }
+        if self.exclusive
+            && self
+                .renames
+                .iter()
+                .any(|(_, canonical)| *canonical == label)
+        {
+            return None;
+        }
+        Some(label)
+    }
+
+    /// Semantics of an operator label under this profile
+    pub fn semantics_by_label(&self, label: &str) -> Option<OpSemantics> {
+        canonical_semantics(&self.canonical_label(label)?)
+    }
+
+    /// Classify a node, recording where its semantics came from
+    ///
+    /// In order of preference:
+    /// - Special-case rules for batch, fold/reduce and observe_non_det that
+    ///   inspect the backtrace (all of them make the operator deterministic
+    ///   and monotone)
+    /// - Environment origins under `environment`, which are `ExternalNonDet`
+    ///   and keep the monotonicity from the label table
+    /// - Label-based lookup for finer-grained classification
+    /// - Node type lookup when the label is missing or unknown
+    /// - The conservative default when the node type is unknown too
+    pub fn classify(
+        &self,
+        node: &crate::model::Node,
+        environment: &EnvironmentModel,
+    ) -> Classification {
+        if let Some(canonical) = node.label.as_deref().and_then(|l| self.canonical_label(l)) {
+            let backtrace = node.data.as_ref().map(|d| &d.backtrace);
+            if let Some(rule) = special_case(&canonical, backtrace) {
+                return Classification {
+                    semantics: OpSemantics {
+                        nd: NdEffect::Deterministic,
+                        monotone: Monotonicity::Always,
+                        ports: &[],
+                    },
+                    source: SemanticsSource::SpecialCase(rule),
+                };
+            }
+            if let Some(category) = environment.origin(&canonical) {
+                let base = canonical_semantics(&canonical);
+                return Classification {
+                    semantics: OpSemantics {
+                        nd: NdEffect::ExternalNonDet,
+                        monotone: base.map_or(Monotonicity::Always, |s| s.monotone),
+                        ports: base.map_or(&[], |s| s.ports),
+                    },
+                    source: SemanticsSource::Environment(category),
+                };
+            }
+            if let Some(semantics) = canonical_semantics(&canonical) {
+                return Classification {
+                    semantics,
+                    source: SemanticsSource::Label,
+                };
+            }
+        }
+        classify_node_type(&node.node_type)
+    }
+}
+
 /// Detect lattice types from edge labels.
 ///
 /// Ideally this would check for Hydro's `Merge` trait implementation, but since we only
@@ -252,121 +628,417 @@ pub fn is_lattice_type(label: Option<&str>) -> bool {
     })
 }
 
-/// Check if a batch operator is from a network operator (structural ND) vs manual use (semantic ND)
-pub fn is_network_batch(backtrace: &serde_json::Value) -> bool {
-    // Check if the backtrace contains network-related files
-    if let Some(frames) = backtrace.as_array() {
-        for frame in frames {
-            if let Some(file) = frame.get("file").and_then(|f| f.as_str()) {
-                // Network batching appears in networking.rs or location/mod.rs
-                if file.contains("networking.rs") || file.contains("location/mod.rs") {
-                    return true;
+/// Split an operator label into identifiers, `::` and single punctuation
+fn label_tokens(label: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = label.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if c.is_alphanumeric() || c == '_' {
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
                 }
+                end = i + c.len_utf8();
+                chars.next();
             }
+        } else if c == ':' && chars.peek().map(|&(_, c)| c) == Some(':') {
+            chars.next();
+            end += 1;
         }
+        tokens.push(&label[start..end]);
     }
-    false
+    tokens
 }
 
-/// Check if a fold/reduce is actually a commutative+idempotent variant by inspecting backtrace
-fn is_commutative_idempotent_fold(backtrace: &serde_json::Value) -> bool {
-    if let Some(frames) = backtrace.as_array() {
-        for frame in frames {
-            if let Some(func) = frame.get("function").and_then(|f| f.as_str()) {
-                if func.contains("commutative_idempotent") || func.contains("idempotent_commutative")
-                {
-                    return true;
+/// Check if an operator's label applies its updates as lattice merges
+///
+/// Matches a call of `Merge::merge` (also `lattices::Merge::merge` and
+/// `<T as Merge>::merge`) or a `.merge(..)` method call on the first
+/// parameter of a closure, which for a fold is the accumulator. Other
+/// functions named `merge`, like `HashMap::merge`, don't count.
+pub fn is_merge_combinator(full_label: Option<&str>) -> bool {
+    let Some(label) = full_label else {
+        return false;
+    };
+    let tokens = label_tokens(label);
+    let is_ident = |t: &str| t.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+
+    // First parameters of closures: `|` opening a parameter list, then the
+    // first identifier other than `mut`
+    let mut accumulators = Vec::new();
+    for (i, &token) in tokens.iter().enumerate() {
+        let opens =
+            token == "|" && (i == 0 || matches!(tokens[i - 1], "(" | "," | "{" | "=" | "move"));
+        if opens
+            && let Some(&param) = tokens[i + 1..].iter().find(|&&t| !matches!(t, "mut" | "&"))
+            && is_ident(param)
+        {
+            accumulators.push(param);
+        }
+    }
+
+    (2..tokens.len()).any(|i| {
+        if tokens[i] != "merge" {
+            return false;
+        }
+        match tokens[i - 1] {
+            // `<T as Merge<..>>::merge`: the trait named after `as`
+            "::" if tokens[i - 2] == ">" => {
+                let mut depth = 0;
+                let mut start = i - 2;
+                loop {
+                    match tokens[start] {
+                        ">" => depth += 1,
+                        "<" => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 || start == 0 {
+                        break;
+                    }
+                    start -= 1;
                 }
+                let path = &tokens[start..i - 1];
+                path.iter().position(|&t| t == "as").is_some_and(|as_idx| {
+                    path[as_idx + 1..]
+                        .iter()
+                        .take_while(|&&t| t != "<" && t != ">")
+                        .last()
+                        == Some(&"Merge")
+                })
             }
+            "::" => tokens[i - 2] == "Merge",
+            "." => tokens.get(i + 1) == Some(&"(") && accumulators.contains(&tokens[i - 2]),
+            _ => false,
         }
+    })
+}
+
+/// Check if a batch operator is from a network operator (structural ND) vs manual use (semantic ND)
+pub fn is_network_batch(backtrace: &Backtrace) -> bool {
+    // Network batching appears in networking.rs or location/mod.rs
+    backtrace.has_file_containing("networking.rs")
+        || backtrace.has_file_containing("location/mod.rs")
+}
+
+/// Check if a fold/reduce is actually a commutative+idempotent variant by inspecting backtrace
+fn is_commutative_idempotent_fold(backtrace: &Backtrace) -> bool {
+    backtrace.has_function_containing("commutative_idempotent")
+        || backtrace.has_function_containing("idempotent_commutative")
+}
+
+/// Where a node's semantics came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SemanticsSource {
+    /// Exact match in the label table
+    Label,
+    /// Label refined by a backtrace-based rule (the rule name)
+    SpecialCase(&'static str),
+    /// Label observes the environment (the `EnvironmentModel` category)
+    Environment(&'static str),
+    /// Label missing or not in the table; classified by a known `nodeType`
+    NodeType,
+    /// Neither label nor `nodeType` is known; assumed nondeterministic and non-monotone
+    Default,
+}
+
+impl SemanticsSource {
+    /// Whether this classification comes from the label (possibly refined)
+    pub fn is_label_based(&self) -> bool {
+        matches!(
+            self,
+            SemanticsSource::Label
+                | SemanticsSource::SpecialCase(_)
+                | SemanticsSource::Environment(_)
+        )
     }
-    false
 }
 
-/// Get semantics for a node, using label-based lookup with network batch detection
-///
-/// This is the canonical way to determine node semantics, handling:
-/// - Label-based lookup for finer-grained classification
-/// - Special case for batch operators (network vs manual)
-/// - Special case for fold/reduce (check if commutative+idempotent via backtrace)
-/// - Special case for observenondet (check if inside commutative+idempotent fold)
-/// - Node type lookup when no label is present
+impl std::fmt::Display for SemanticsSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SemanticsSource::Label => write!(f, "label"),
+            SemanticsSource::SpecialCase(rule) => write!(f, "special_case:{}", rule),
+            SemanticsSource::Environment(category) => write!(f, "environment:{}", category),
+            SemanticsSource::NodeType => write!(f, "node_type"),
+            SemanticsSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Semantics of a node together with their provenance
+#[derive(Debug, Clone, Copy)]
+pub struct Classification {
+    pub semantics: OpSemantics,
+    pub source: SemanticsSource,
+}
+
+/// Classify a bare node type, without looking at the label
+pub fn classify_node_type(node_type: &str) -> Classification {
+    let source = if NODE_TYPES.contains(&node_type) {
+        SemanticsSource::NodeType
+    } else {
+        SemanticsSource::Default
+    };
+    Classification {
+        semantics: get_semantics(node_type),
+        source,
+    }
+}
+
+/// Backtrace-based refinements of label semantics, returning the rule name
 ///
-/// Panics if an unknown label is encountered (fail fast, don't hide bugs)
-pub fn get_node_semantics(node: &crate::model::Node) -> OpSemantics {
-    if let Some(label) = &node.label {
-        // Special handling for batch: check if it's network batching
-        if label == "batch" {
-            let is_network = node
-                .data
-                .as_ref()
-                .map(|d| is_network_batch(&d.backtrace))
-                .unwrap_or(false);
-
-            if is_network {
-                // Network batching is deterministic and monotone
-                OpSemantics {
-                    nd: NdEffect::Deterministic,
-                    monotone: Monotonicity::Always,
+/// - `network_batch`: a `batch` created by the networking layer is structural
+///   rather than semantic nondeterminism
+/// - `commutative_idempotent_fold`: a fold/reduce whose backtrace shows a
+///   commutative+idempotent variant is CALM-safe
+/// - `commutative_idempotent_observe`: an `observe_non_det` inside such a fold
+///   is its internal batching
+fn special_case(canonical: &str, backtrace: Option<&Backtrace>) -> Option<&'static str> {
+    let backtrace = backtrace?;
+    match canonical {
+        "batch" if is_network_batch(backtrace) => Some("network_batch"),
+        "fold" | "fold_keyed" | "reduce" | "reduce_keyed"
+            if is_commutative_idempotent_fold(backtrace) =>
+        {
+            Some("commutative_idempotent_fold")
+        }
+        "observe_non_det" if is_commutative_idempotent_fold(backtrace) => {
+            Some("commutative_idempotent_observe")
+        }
+        _ => None,
+    }
+}
+
+/// An operator whose output is deterministic even when its input is not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seal {
+    /// `commutative_idempotent_fold`, `lattice_merge` or `sorted_collect`
+    pub reason: &'static str,
+    /// Only arrival-order nondeterminism is removed
+    pub order_only: bool,
+}
+
+impl Seal {
+    /// The ND kinds that do not propagate past the sealing operator
+    ///
+    /// Merging makes a result independent of how its input was ordered and
+    /// batched, but not of which timers fired, which members were up or what
+    /// was sent, so those kinds always pass.
+    pub fn stops(&self) -> NdKinds {
+        if self.order_only {
+            NdKinds::single(NdKind::Ordering)
+        } else {
+            [NdKind::Ordering, NdKind::Batching].into_iter().collect()
+        }
+    }
+}
+
+/// A semantics profile together with an environment model: everything
+/// needed to classify a node
+#[derive(Debug, Clone, Copy)]
+pub struct Classifier {
+    pub profile: &'static SemanticsProfile,
+    pub environment: EnvironmentModel,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier {
+            profile: &COMPAT,
+            environment: EnvironmentModel::default(),
+        }
+    }
+}
+
+impl Classifier {
+    /// Classify a node; see `SemanticsProfile::classify`
+    pub fn classify(&self, node: &crate::model::Node) -> Classification {
+        self.profile.classify(node, &self.environment)
+    }
+
+    pub fn semantics(&self, node: &crate::model::Node) -> OpSemantics {
+        self.classify(node).semantics
+    }
+
+    /// The kinds of nondeterminism a node introduces, given its incoming
+    /// edges and their source nodes; empty unless it is nondeterministic
+    pub fn nd_kinds(
+        &self,
+        node: &crate::model::Node,
+        inputs: &[(&crate::model::Edge, &crate::model::Node)],
+    ) -> NdKinds {
+        let classification = self.classify(node);
+        if classification.semantics.nd == NdEffect::Deterministic {
+            return NdKinds::EMPTY;
+        }
+        let kind = match classification.source {
+            SemanticsSource::Environment(category) => match category {
+                "network_order" => NdKind::Ordering,
+                "timers" | "sampling" => NdKind::Timing,
+                "membership" => NdKind::Membership,
+                "external_inputs" => NdKind::Input,
+                _ => NdKind::Unknown,
+            },
+            SemanticsSource::Label => {
+                let canonical = node
+                    .label
+                    .as_deref()
+                    .and_then(|label| self.profile.canonical_label(label))
+                    .unwrap_or_default();
+                match canonical.as_str() {
+                    "batch" | "batch_atomic" => NdKind::Batching,
+                    "sample_every" | "timeout" | "source_interval" => NdKind::Timing,
+                    "observe_non_det" | "nondet" => {
+                        let input_tag = |name: &str| {
+                            inputs.iter().any(|(edge, _)| {
+                                edge.semantic_tags.iter().flatten().any(|tag| tag == name)
+                            })
+                        };
+                        let mut kinds = NdKinds::EMPTY;
+                        if input_tag("NoOrder") {
+                            kinds = kinds.join(NdKinds::single(NdKind::Ordering));
+                        }
+                        if input_tag("Bounded") {
+                            kinds = kinds.join(NdKinds::single(NdKind::Batching));
+                        }
+                        if kinds.is_empty() {
+                            return NdKinds::single(NdKind::Unknown);
+                        }
+                        return kinds;
+                    }
+                    _ => NdKind::Unknown,
                 }
-            } else {
-                // Manual batch is semantic nondeterminism
-                get_semantics_by_label(label).expect("batch should be in label lookup table")
             }
-        } else if matches!(
-            label.as_str(),
-            "fold" | "foldkeyed" | "fold_keyed" | "reduce" | "reducekeyed" | "reduce_keyed"
-        ) {
-            // Check if this is actually a commutative+idempotent variant
-            let is_ci = node
-                .data
-                .as_ref()
-                .map(|d| is_commutative_idempotent_fold(&d.backtrace))
-                .unwrap_or(false);
-
-            if is_ci {
-                // Commutative+idempotent folds are CALM-safe
-                OpSemantics {
-                    nd: NdEffect::Deterministic,
-                    monotone: Monotonicity::Always,
-                }
-            } else {
-                // Regular fold/reduce - depends on function
-                get_semantics_by_label(label).expect("fold/reduce should be in label lookup table")
+            _ => NdKind::Unknown,
+        };
+        NdKinds::single(kind)
+    }
+
+    /// Whether a node seals nondeterminism flowing into it, given its
+    /// incoming edges and their source nodes
+    ///
+    /// - `commutative_idempotent_fold`: the result of an ACI fold doesn't
+    ///   depend on how its input is ordered, batched or duplicated, though it
+    ///   still depends on which items arrive
+    /// - `lattice_merge`: a fold/reduce that merges `Unbounded` lattice-typed
+    ///   inputs reaches the same value whatever the arrival order
+    /// - `sorted_collect`: `collect_vec` fed only by `sort` sees its input in
+    ///   a fixed order, which seals arrival order but nothing else
+    pub fn seal(
+        &self,
+        node: &crate::model::Node,
+        inputs: &[(&crate::model::Edge, &crate::model::Node)],
+    ) -> Option<Seal> {
+        let canonical = |n: &crate::model::Node| {
+            n.label
+                .as_deref()
+                .and_then(|label| self.profile.canonical_label(label))
+        };
+        let name = canonical(node)?;
+        let seal = |reason, order_only| Some(Seal { reason, order_only });
+
+        if self.classify(node).source == SemanticsSource::SpecialCase("commutative_idempotent_fold")
+            || matches!(
+                name.as_str(),
+                "fold_commutative_idempotent"
+                    | "fold_idempotent_commutative"
+                    | "reduce_commutative_idempotent"
+                    | "reduce_idempotent_commutative"
+            )
+        {
+            return seal("commutative_idempotent_fold", false);
+        }
+        if inputs.is_empty() {
+            return None;
+        }
+        match name.as_str() {
+            "fold" | "fold_keyed" | "fold_commutative" | "fold_idempotent" | "reduce"
+            | "reduce_keyed" | "reduce_commutative" | "reduce_idempotent"
+                if is_merge_combinator(node.full_label.as_deref())
+                    && inputs.iter().all(|(edge, _)| {
+                        is_lattice_type(edge.label.as_deref())
+                            && edge
+                                .semantic_tags
+                                .iter()
+                                .flatten()
+                                .any(|tag| tag == "Unbounded")
+                    }) =>
+            {
+                seal("lattice_merge", false)
             }
-        } else if label == "observenondet" {
-            // Check if this observenondet is inside a commutative+idempotent fold
-            // If so, it's structural (batching implementation) not semantic nondeterminism
-            let is_ci_internal = node
-                .data
-                .as_ref()
-                .map(|d| is_commutative_idempotent_fold(&d.backtrace))
-                .unwrap_or(false);
-
-            if is_ci_internal {
-                // Internal batching in CALM-safe fold - treat as deterministic
-                OpSemantics {
-                    nd: NdEffect::Deterministic,
-                    monotone: Monotonicity::Always,
-                }
-            } else {
-                // Actual semantic nondeterminism
-                get_semantics_by_label(label).expect("observenondet should be in label lookup table")
+            "collect_vec"
+                if inputs
+                    .iter()
+                    .all(|(_, source)| canonical(source).as_deref() == Some("sort")) =>
+            {
+                seal("sorted_collect", true)
             }
-        } else {
-            // Try label-based lookup first
-            get_semantics_by_label(label).unwrap_or_else(|| {
-                // If label is unknown, use node type as fallback
-                // This is expected for generic operators
-                get_semantics(&node.node_type)
-            })
+            _ => None,
         }
-    } else {
-        // No label, use node type
-        get_semantics(&node.node_type)
     }
 }
 
+/// Classify a node under the `compat` profile and default environment model
+pub fn classify_node(node: &crate::model::Node) -> Classification {
+    Classifier::default().classify(node)
+}
+
+/// Get semantics for a node under the `compat` profile and default
+/// environment model
+///
+/// Never panics: operators that hydrolysis doesn't know get the conservative
+/// defaults. Analysis code should use the `Classifier` in `PassContext` or
+/// `AnalysisResult` instead, so that `--profile` and the configured
+/// environment model are respected.
+pub fn get_node_semantics(node: &crate::model::Node) -> OpSemantics {
+    classify_node(node).semantics
+}
+
+/// Operators not classified by their label, with counts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageGap {
+    /// The operator label, or the short label if the node has none
+    pub label: String,
+    pub node_type: String,
+    pub source: SemanticsSource,
+    pub count: usize,
+}
+
+/// Operators whose semantics did not come from the profile's label table
+///
+/// Sorted by descending count, then label. A non-empty result after a Hydro
+/// upgrade usually means new operators or spellings that need an entry in
+/// `canonical_semantics` or a new profile.
+pub fn coverage_gaps(ir: &crate::model::HydroIr, classifier: &Classifier) -> Vec<CoverageGap> {
+    let mut counts: std::collections::BTreeMap<(String, String, SemanticsSource), usize> =
+        std::collections::BTreeMap::new();
+    for node in &ir.nodes {
+        let source = classifier.classify(node).source;
+        if source.is_label_based() {
+            continue;
+        }
+        let label = node.label.as_ref().unwrap_or(&node.short_label).clone();
+        *counts
+            .entry((label, node.node_type.clone(), source))
+            .or_default() += 1;
+    }
+    let mut gaps: Vec<CoverageGap> = counts
+        .into_iter()
+        .map(|((label, node_type, source), count)| CoverageGap {
+            label,
+            node_type,
+            source,
+            count,
+        })
+        .collect();
+    gaps.sort_by(|a, b| b.count.cmp(&a.count).then(a.label.cmp(&b.label)));
+    gaps
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -392,7 +1064,10 @@ mod tests {
 
             // Verify that we get a valid Monotonicity
             match semantics.monotone {
-                Monotonicity::Always | Monotonicity::Never | Monotonicity::Depends => {},
+                Monotonicity::Always
+                | Monotonicity::Never
+                | Monotonicity::Depends
+                | Monotonicity::Antitone => {},
             }
 
             // Additional check: NonDeterministic nodes should be classified as LocallyNonDet
@@ -467,6 +1142,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_merge_combinator() {
+        for label in [
+            "fold(q!(| | MapUnionHashMap :: default ()), q!(| acc , x | { lattices :: Merge :: merge (acc , x) ; }))",
+            "reduce(q!(Merge :: merge))",
+            "reduce(q!(< SetUnionHashSet < u32 > as Merge < _ >> :: merge))",
+            "fold(q!(| | Max :: new (0)), q!(| mut state , x | { state . merge (x) ; }))",
+        ] {
+            assert!(is_merge_combinator(Some(label)), "{}", label);
+        }
+        for label in [
+            "fold(q!(| | HashMap :: new ()), q!(| acc , x | { HashMap :: merge (acc , x) ; }))",
+            "fold(q!(| | HashSet :: new ()), q!(| acc , x | { acc . insert (x) ; }))",
+            "fold(q!(| | Vec :: new ()), q!(| acc , x | { x . merge (other) ; }))",
+            "map(q!(| x | merge (x)))",
+        ] {
+            assert!(!is_merge_combinator(Some(label)), "{}", label);
+        }
+        assert!(!is_merge_combinator(None));
+    }
+
     #[test]
     fn test_none_label_not_lattice() {
         // Property: None label should always return false
@@ -475,4 +1171,102 @@ mod tests {
             "None label should not be a lattice type"
         );
     }
+
+    #[test]
+    fn test_classification_provenance() {
+        use crate::backtrace::Frame;
+        use crate::builder::IrBuilder;
+
+        let mut b = IrBuilder::new();
+        b.node("map");
+        let batch = b.node("batch");
+        b.node("frobnicate");
+        b.node("mystery");
+        b.set_backtrace(
+            &batch,
+            vec![Frame::new("hydro_lang/src/networking.rs", "send", 10)],
+        );
+        let mut ir = b.build();
+        ir.nodes[3].node_type = "Mystery".to_string();
+
+        let sources: Vec<SemanticsSource> =
+            ir.nodes.iter().map(|n| classify_node(n).source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                SemanticsSource::Label,
+                SemanticsSource::SpecialCase("network_batch"),
+                SemanticsSource::NodeType,
+                SemanticsSource::Default,
+            ]
+        );
+        assert_eq!(
+            classify_node(&ir.nodes[3]).semantics.nd,
+            NdEffect::LocallyNonDet
+        );
+
+        let gaps = coverage_gaps(&ir, &Classifier::default());
+        assert_eq!(
+            gaps.iter()
+                .map(|g| (g.label.as_str(), g.source, g.count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("frobnicate", SemanticsSource::NodeType, 1),
+                ("mystery", SemanticsSource::Default, 1),
+            ]
+        );
+    }
+
+    /// Sample exports per profile; each must be detected as that profile and
+    /// fully classified by label under it
+    const PROFILE_SAMPLES: &[(&str, &[&str])] = &[(
+        "hydro_lang-0.14",
+        &["hydro_graph.json", "replicated_kvs.json"],
+    )];
+
+    #[test]
+    fn test_profiles_against_sample_exports() {
+        for (name, samples) in PROFILE_SAMPLES {
+            let expected = profile(name).unwrap();
+            for sample in *samples {
+                let path = format!("{}/test-data/{}", env!("CARGO_MANIFEST_DIR"), sample);
+                let ir: crate::model::HydroIr =
+                    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+                assert_eq!(detect_profile(&ir).name, *name, "{}", sample);
+                let classifier = Classifier {
+                    profile: expected,
+                    ..Default::default()
+                };
+                assert_eq!(coverage_gaps(&ir, &classifier), Vec::new(), "{}", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn test_profile_spellings_and_detection() {
+        use crate::builder::IrBuilder;
+
+        assert!(HYDRO_LANG_0_14.semantics_by_label("filtermap").is_some());
+        assert!(HYDRO_LANG_0_14.semantics_by_label("filter_map").is_none());
+        assert!(HYDRO_LANG_0_13.semantics_by_label("filter_map").is_some());
+        assert!(HYDRO_LANG_0_13.semantics_by_label("filtermap").is_none());
+        assert!(COMPAT.semantics_by_label("filtermap").is_some());
+        assert!(COMPAT.semantics_by_label("filter_map").is_some());
+
+        let mut b = IrBuilder::new();
+        b.node("map");
+        b.node("filter_map");
+        let mut ir = b.build();
+        assert_eq!(detect_profile(&ir).name, "hydro_lang-0.13");
+
+        // Explicit metadata wins over spellings
+        ir.metadata = Some(serde_json::json!({"hydroLangVersion": "0.14.2"}));
+        assert_eq!(detect_profile(&ir).name, "hydro_lang-0.14");
+        assert!(!HYDRO_LANG_0_14.matches_version("0.140"));
+
+        // Nothing distinguishes the releases
+        let mut b = IrBuilder::new();
+        b.node("map");
+        assert_eq!(detect_profile(&b.build()).name, "compat");
+    }
 }
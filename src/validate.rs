@@ -0,0 +1,355 @@
+// Structural validation of Hydro IR input
+//
+// The analysis is lenient about malformed graphs: `Graph::build` drops edges
+// whose endpoints are unknown, a duplicate node id silently replaces the
+// earlier node, and duplicate edge ids collide in the result maps. This
+// module reports those problems (and a few suspicious-but-legal shapes) with
+// JSON paths into the input so they can be fixed at the source.
+
+use crate::model::HydroIr;
+use crate::semantics::NODE_TYPES;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Suspicious, but the analysis still sees the graph as written
+    Warning,
+    /// The analysis silently drops or overwrites part of the input
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single validation finding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier, e.g. "dangling-edge"
+    pub code: &'static str,
+    /// JSON path of the offending value, e.g. `$.edges[3].target`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            self.severity, self.code, self.path, self.message
+        )
+    }
+}
+
+/// Check the IR for structural problems
+///
+/// Diagnostics are ordered by check, then by position in the input.
+pub fn validate(ir: &HydroIr) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut push = |severity, code, path: String, message: String| {
+        diagnostics.push(Diagnostic {
+            severity,
+            code,
+            path,
+            message,
+        })
+    };
+
+    // Duplicate ids: the first occurrence wins for reporting purposes
+    let mut node_index: HashMap<&str, usize> = HashMap::new();
+    for (i, node) in ir.nodes.iter().enumerate() {
+        if let Some(&first) = node_index.get(node.id.as_str()) {
+            push(
+                Severity::Error,
+                "duplicate-node-id",
+                format!("$.nodes[{}].id", i),
+                format!("node id '{}' already used by $.nodes[{}]", node.id, first),
+            );
+        } else {
+            node_index.insert(&node.id, i);
+        }
+    }
+
+    let mut edge_index: HashMap<&str, usize> = HashMap::new();
+    for (i, edge) in ir.edges.iter().enumerate() {
+        if let Some(&first) = edge_index.get(edge.id.as_str()) {
+            push(
+                Severity::Error,
+                "duplicate-edge-id",
+                format!("$.edges[{}].id", i),
+                format!("edge id '{}' already used by $.edges[{}]", edge.id, first),
+            );
+        } else {
+            edge_index.insert(&edge.id, i);
+        }
+    }
+
+    for (i, edge) in ir.edges.iter().enumerate() {
+        for (field, endpoint) in [("source", &edge.source), ("target", &edge.target)] {
+            if !node_index.contains_key(endpoint.as_str()) {
+                push(
+                    Severity::Error,
+                    "dangling-edge",
+                    format!("$.edges[{}].{}", i, field),
+                    format!(
+                        "edge '{}' refers to unknown node '{}' and is ignored by the analysis",
+                        edge.id, endpoint
+                    ),
+                );
+            }
+        }
+    }
+
+    for (i, edge) in ir.edges.iter().enumerate() {
+        if edge.source == edge.target {
+            push(
+                Severity::Warning,
+                "self-loop",
+                format!("$.edges[{}]", i),
+                format!(
+                    "edge '{}' connects node '{}' to itself",
+                    edge.id, edge.source
+                ),
+            );
+        }
+    }
+
+    for (i, node) in ir.nodes.iter().enumerate() {
+        if !NODE_TYPES.contains(&node.node_type.as_str()) {
+            push(
+                Severity::Warning,
+                "unknown-node-type",
+                format!("$.nodes[{}].nodeType", i),
+                format!(
+                    "unknown node type '{}' on node '{}'; treated as nondeterministic and non-monotone",
+                    node.node_type, node.id
+                ),
+            );
+        }
+    }
+
+    for (i, node) in ir.nodes.iter().enumerate() {
+        if node.short_label.trim().is_empty() {
+            push(
+                Severity::Warning,
+                "unlabelled-node",
+                format!("$.nodes[{}].shortLabel", i),
+                format!(
+                    "node '{}' has no operator label; only its node type is used",
+                    node.id
+                ),
+            );
+        }
+    }
+
+    for (i, node) in ir.nodes.iter().enumerate() {
+        let Some(data) = &node.data else {
+            continue;
+        };
+        for (frame, error) in data.backtrace.problems() {
+            let path = match frame {
+                Some(j) => format!("$.nodes[{}].data.backtrace[{}]", i, j),
+                None => format!("$.nodes[{}].data.backtrace", i),
+            };
+            push(
+                Severity::Warning,
+                "invalid-frame",
+                path,
+                format!(
+                    "{} on node '{}'; ignored for source locations and classification",
+                    error, node.id
+                ),
+            );
+        }
+    }
+
+    for component in minor_components(ir, &node_index) {
+        let first = component[0];
+        push(
+            Severity::Warning,
+            "disconnected-component",
+            format!("$.nodes[{}]", first),
+            format!(
+                "{} node(s) starting at '{}' are not connected to the rest of the graph",
+                component.len(),
+                ir.nodes[first].id
+            ),
+        );
+    }
+
+    diagnostics
+}
+
+/// Whether any diagnostic is an error
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// Weakly connected components other than the largest, as node indices
+///
+/// Duplicate and dangling ids are ignored here since they are already
+/// reported. The largest component is the earliest one on ties.
+fn minor_components(ir: &HydroIr, node_index: &HashMap<&str, usize>) -> Vec<Vec<usize>> {
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+        x
+    }
+
+    let mut parent: Vec<usize> = (0..ir.nodes.len()).collect();
+    for edge in &ir.edges {
+        if let (Some(&a), Some(&b)) = (
+            node_index.get(edge.source.as_str()),
+            node_index.get(edge.target.as_str()),
+        ) {
+            let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+            parent[ra.max(rb)] = ra.min(rb);
+        }
+    }
+
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    let mut component_of: HashMap<usize, usize> = HashMap::new();
+    for (i, node) in ir.nodes.iter().enumerate() {
+        if node_index.get(node.id.as_str()) != Some(&i) {
+            continue;
+        }
+        let root = find(&mut parent, i);
+        let c = *component_of.entry(root).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[c].push(i);
+    }
+
+    if components.len() <= 1 {
+        return Vec::new();
+    }
+    let largest = components
+        .iter()
+        .enumerate()
+        .max_by(|(ia, a), (ib, b)| a.len().cmp(&b.len()).then(ib.cmp(ia)))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    components.remove(largest);
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::IrBuilder;
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(&'static str, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.code, d.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_graph() {
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let map = b.node("map");
+        let sink = b.node("for_each");
+        b.edge(&src, &map, &[]);
+        b.edge(&map, &sink, &[]);
+        assert!(validate(&b.build()).is_empty());
+    }
+
+    #[test]
+    fn test_reports_each_problem_with_path() {
+        let mut b = IrBuilder::new();
+        let src = b.node("source_iter");
+        let map = b.node("map");
+        let lonely = b.node("for_each");
+        b.edge(&src, &map, &[]);
+        b.edge(&map, &map, &[]);
+        let mut ir = b.build();
+
+        ir.nodes[1].node_type = "Mystery".to_string();
+        ir.nodes[1].short_label = String::new();
+        let mut dup = ir.nodes[0].clone();
+        dup.short_label = "source_stream".to_string();
+        ir.nodes.push(dup);
+        let mut dangling = ir.edges[0].clone();
+        dangling.target = "missing".to_string();
+        ir.edges.push(dangling);
+
+        let diagnostics = validate(&ir);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("duplicate-node-id", "$.nodes[3].id"),
+                ("duplicate-edge-id", "$.edges[2].id"),
+                ("dangling-edge", "$.edges[2].target"),
+                ("self-loop", "$.edges[1]"),
+                ("unknown-node-type", "$.nodes[1].nodeType"),
+                ("unlabelled-node", "$.nodes[1].shortLabel"),
+                ("disconnected-component", "$.nodes[2]"),
+            ]
+        );
+        assert!(has_errors(&diagnostics));
+        assert!(diagnostics[6].message.contains(&lonely));
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "error[dangling-edge] $.edges[2].target: edge 'e0' refers to unknown node \
+             'missing' and is ignored by the analysis"
+        );
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        let ir: HydroIr = serde_json::from_value(serde_json::json!({
+            "nodes": [
+                {"id": "0", "nodeType": "Source", "shortLabel": "source_iter",
+                 "data": {"locationId": 0, "locationType": "Process", "backtrace": [
+                     {"file": "src/a.rs", "fn": "main", "line": 3},
+                     {"fn": "anonymous", "line": 7},
+                 ]}},
+                {"id": "1", "nodeType": "Sink", "shortLabel": "for_each",
+                 "data": {"locationId": 0, "locationType": "Process", "backtrace": "elided"}},
+            ],
+            "edges": [{"id": "e0", "source": "0", "target": "1"}],
+        }))
+        .unwrap();
+
+        let diagnostics = validate(&ir);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("invalid-frame", "$.nodes[0].data.backtrace[1]"),
+                ("invalid-frame", "$.nodes[1].data.backtrace"),
+            ]
+        );
+        assert!(diagnostics[0].message.contains("no `file` or `filename`"));
+        assert!(!has_errors(&diagnostics));
+        // The valid frame is still used
+        assert_eq!(
+            ir.nodes[0].extract_source_location().unwrap().file,
+            "src/a.rs"
+        );
+    }
+
+    #[test]
+    fn test_warnings_only() {
+        let mut b = IrBuilder::new();
+        let a = b.node("source_iter");
+        b.edge(&a, &a, &[]);
+        b.node("source_iter");
+        let diagnostics = validate(&b.build());
+        assert_eq!(diagnostics.len(), 2);
+        assert!(!has_errors(&diagnostics));
+    }
+}
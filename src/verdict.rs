@@ -0,0 +1,333 @@
+// Per-output consistency verdicts
+//
+// `OverallAnalysis` answers for the whole program, but guarantees are made
+// per output: responses on one `send_external` may be eventually consistent
+// while a `for_each` log elsewhere is racy. Each sink gets a verdict that
+// combines the ND taint reaching it, the CALM status of the critical edges
+// upstream of it and the sealing operators in between, together with the
+// reason and a witness path.
+
+use crate::analysis::{
+    AnalysisResult, CALM_UNSAFE, ND_LOCALLY_NONDET, is_calm_critical, witness_path,
+};
+use crate::model::{Edge, Node, SinkVerdict};
+use crate::pass::{AnalysisPass, PassContext};
+use crate::semantics::{NdEffect, NdKind, NdKinds, Seal};
+
+pub const DETERMINISTIC: &str = "Deterministic";
+pub const EVENTUALLY_CONSISTENT: &str = "EventuallyConsistent";
+pub const ENVIRONMENT_DEPENDENT: &str = "EnvironmentDependent";
+pub const NONDETERMINISTIC: &str = "Nondeterministic";
+
+fn describe(node: &Node) -> String {
+    format!(
+        "{} ({})",
+        node.label.as_ref().unwrap_or(&node.short_label),
+        node.id
+    )
+}
+
+/// The seal of node `idx`, if it has one
+fn seal_of(ctx: &PassContext<'_>, idx: usize) -> Option<Seal> {
+    let inputs: Vec<(&Edge, &Node)> = ctx.graph.backward[idx]
+        .iter()
+        .filter_map(|(source, edge_id)| Some((ctx.edge(edge_id)?, &ctx.ir.nodes[*source])))
+        .collect();
+    ctx.classifier.seal(&ctx.ir.nodes[idx], &inputs)
+}
+
+/// Verdict for every `Sink` node as a registered pass
+///
+/// - `Nondeterministic`: local nondeterminism reaches the sink; the witness
+///   starts at the nearest locally nondeterministic operator
+/// - `EnvironmentDependent`: no local nondeterminism reaches it, but a
+///   CALM-critical edge upstream is `CalmUnsafe` and either the sink depends
+///   on the environment or nondeterminism reaches that edge, so the output
+///   depends on arrival order or timing; the witness starts at that edge
+/// - `EventuallyConsistent`: only environment nondeterminism reaches it and
+///   every CALM-critical edge upstream is `CalmSafe`; the witness starts at
+///   the nearest environment origin
+/// - `Deterministic`: no unsealed nondeterminism reaches it and no
+///   nondeterminism reaches a `CalmUnsafe` CALM-critical edge upstream
+///
+/// Witness paths only pass through tainted operators, and not into a seal
+/// that stops every kind reaching the operator before it.
+pub struct VerdictPass;
+
+impl AnalysisPass for VerdictPass {
+    fn name(&self) -> &str {
+        "verdicts"
+    }
+
+    fn dependencies(&self) -> &[&str] {
+        &["nd", "calm"]
+    }
+
+    fn run(&self, ctx: &PassContext<'_>, results: &mut AnalysisResult) {
+        let ir = ctx.ir;
+        let mut verdicts = Vec::new();
+
+        for (sink_idx, sink) in ir.nodes.iter().enumerate() {
+            if sink.node_type != "Sink" {
+                continue;
+            }
+            let Some(analysis) = results.node_analyses.get(&sink.id) else {
+                continue;
+            };
+            let kinds_at = |idx: usize| -> NdKinds {
+                let names = &results.node_analyses[&ir.nodes[idx].id].nd_kinds;
+                NdKind::ALL
+                    .into_iter()
+                    .filter(|kind| names.iter().any(|name| name == kind.as_str()))
+                    .collect()
+            };
+            let unsealed = |pred: usize, node: usize| {
+                seal_of(ctx, node)
+                    .is_none_or(|seal| !kinds_at(pred).without(seal.stops()).is_empty())
+            };
+            let path_from = |is_root: &dyn Fn(&Node) -> bool,
+                             can_step: &dyn Fn(usize, usize) -> bool| {
+                witness_path(ir, &ctx.graph, sink_idx, is_root, can_step).unwrap_or_default()
+            };
+            let analysis_at = |idx: usize| &results.node_analyses[&ir.nodes[idx].id];
+            let kinds = analysis.nd_kinds.join(", ");
+
+            let sealed_by: Vec<String> = ir
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(idx, node)| {
+                    ctx.reach.reaches(*idx, sink_idx)
+                        && results
+                            .node_analyses
+                            .get(&node.id)
+                            .is_some_and(|a| a.sealed.is_some())
+                })
+                .map(|(_, node)| node.id.clone())
+                .collect();
+
+            // A CALM-unsafe edge only matters if the sink depends on the
+            // environment or nondeterminism reaches the edge itself
+            let tainted = |id: &str| {
+                results
+                    .node_analyses
+                    .get(id)
+                    .is_some_and(|a| !a.nd_kinds.is_empty())
+            };
+            let unsafe_edge = ir.edges.iter().find(|edge| {
+                ctx.graph
+                    .get_idx(&edge.target)
+                    .is_some_and(|idx| ctx.reach.reaches(idx, sink_idx))
+                    && (analysis.env_dependent || tainted(&edge.source))
+                    && is_calm_critical(edge, ctx.node(&edge.target))
+                    && results
+                        .edge_analyses
+                        .get(&edge.id)
+                        .is_some_and(|a| a.calm == CALM_UNSAFE)
+            });
+
+            let (verdict, reason, witness) = if analysis.nd_effect == ND_LOCALLY_NONDET {
+                let path = path_from(
+                    &|n| ctx.classifier.semantics(n).nd == NdEffect::LocallyNonDet,
+                    &|pred, node| {
+                        analysis_at(pred).nd_effect == ND_LOCALLY_NONDET && unsealed(pred, node)
+                    },
+                );
+                let reason = match path.first() {
+                    Some(&root) => format!(
+                        "locally nondeterministic via {}; kinds reaching it: {}",
+                        describe(&ir.nodes[root]),
+                        kinds
+                    ),
+                    None => format!("locally nondeterministic; kinds reaching it: {}", kinds),
+                };
+                (NONDETERMINISTIC, reason, path)
+            } else if let Some(edge) = unsafe_edge {
+                let mut path = path_from(&|n| n.id == edge.target, &|_, _| true);
+                if let Some(source) = ctx.graph.get_idx(&edge.source) {
+                    path.insert(0, source);
+                }
+                let reason = if analysis.env_dependent {
+                    format!(
+                        "depends on {} from the environment and CALM-critical edge '{}' is not CALM-safe",
+                        kinds, edge.id
+                    )
+                } else {
+                    format!(
+                        "no unsealed nondeterminism reaches it, but CALM-critical edge '{}' is not CALM-safe, so it depends on arrival order",
+                        edge.id
+                    )
+                };
+                (ENVIRONMENT_DEPENDENT, reason, path)
+            } else if analysis.env_dependent {
+                (
+                    EVENTUALLY_CONSISTENT,
+                    format!(
+                        "depends on {} from the environment, but every CALM-critical edge upstream is CALM-safe",
+                        kinds
+                    ),
+                    path_from(
+                        &|n| ctx.classifier.semantics(n).nd == NdEffect::ExternalNonDet,
+                        &|pred, node| analysis_at(pred).env_dependent && unsealed(pred, node),
+                    ),
+                )
+            } else if sealed_by.is_empty() {
+                (
+                    DETERMINISTIC,
+                    "no nondeterminism reaches this output".to_string(),
+                    Vec::new(),
+                )
+            } else {
+                (
+                    DETERMINISTIC,
+                    "all nondeterminism upstream is sealed before this output".to_string(),
+                    Vec::new(),
+                )
+            };
+
+            verdicts.push(SinkVerdict {
+                sink: sink.id.clone(),
+                label: sink.label.as_ref().unwrap_or(&sink.short_label).clone(),
+                verdict: verdict.to_string(),
+                reason,
+                witness: witness
+                    .iter()
+                    .map(|&idx| ir.nodes[idx].id.clone())
+                    .collect(),
+                sealed_by,
+            });
+        }
+
+        results.verdicts = verdicts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::run_analysis;
+    use crate::builder::IrBuilder;
+
+    #[test]
+    fn test_verdict_per_sink() {
+        let mut b = IrBuilder::new();
+        let input = b.node("source_stream");
+        // Monotone path of lattice values from the environment: eventually
+        // consistent
+        let map = b.node("map");
+        let log = b.node("for_each");
+        b.typed_edge(&input, &map, &["Local"], "SetUnion<u32>");
+        b.typed_edge(&map, &log, &["Local"], "SetUnion<u32>");
+        // Non-monotone path over the network: depends on arrival order
+        let network = b.node("network");
+        let sort = b.node("sort");
+        let reply = b.node("send_external");
+        b.edge(&input, &network, &["Network"]);
+        b.edge(&network, &sort, &["Local"]);
+        b.edge(&sort, &reply, &["Local"]);
+        // Batching sealed by an ACI fold over lattice values, and not sealed
+        let constant = b.node("source_iter");
+        let racy = b.node("batch");
+        let fold = b.node("fold_commutative_idempotent");
+        let sealed_sink = b.node("for_each");
+        let racy_sink = b.node("for_each");
+        b.typed_edge(&constant, &racy, &["Local"], "SetUnion<u32>");
+        b.typed_edge(&racy, &fold, &["Local"], "SetUnion<u32>");
+        b.typed_edge(&fold, &sealed_sink, &["Local"], "SetUnion<u32>");
+        b.edge(&racy, &racy_sink, &["Local"]);
+        let ir = b.build();
+
+        let results = run_analysis(&ir);
+        let verdict = |id: &str| results.verdicts.iter().find(|v| v.sink == id).unwrap();
+
+        assert_eq!(results.verdicts.len(), 4);
+        assert_eq!(verdict(&log).verdict, EVENTUALLY_CONSISTENT);
+        assert_eq!(verdict(&log).witness, vec![input.clone(), map, log.clone()]);
+        assert_eq!(verdict(&reply).verdict, ENVIRONMENT_DEPENDENT);
+        // The network edge carries plain values, so it is the first unsafe one
+        assert!(verdict(&reply).reason.contains("'e2'"));
+        assert_eq!(
+            verdict(&reply).witness,
+            vec![input, network, sort, reply.clone()]
+        );
+        assert_eq!(verdict(&sealed_sink).verdict, DETERMINISTIC);
+        assert_eq!(verdict(&sealed_sink).sealed_by, vec![fold]);
+        assert_eq!(verdict(&racy_sink).verdict, NONDETERMINISTIC);
+        assert_eq!(verdict(&racy_sink).witness, vec![racy, racy_sink.clone()]);
+    }
+
+    #[test]
+    fn test_verdict_checks_calm_and_seals() {
+        let mut b = IrBuilder::new();
+        // `sort` sees arrival order over the network before the fold seals it
+        let network = b.node("network");
+        let sort = b.node("sort");
+        let ordered = b.node("fold_commutative_idempotent");
+        let reply = b.node("send_external");
+        b.edge(&network, &sort, &["Network"]);
+        b.edge(&sort, &ordered, &["Local"]);
+        b.edge(&ordered, &reply, &["Local"]);
+        // What clients send is not sealed by a fold
+        let input = b.node("source_stream");
+        let counted = b.node("fold_commutative_idempotent");
+        let log = b.node("for_each");
+        b.edge(&input, &counted, &["Local"]);
+        b.edge(&counted, &log, &["Local"]);
+        // Two racy roots into a fold that only seals the first one
+        let constant = b.node("source_iter");
+        let sealed_root = b.node("observenondet");
+        let unknown_root = b.node("observenondet");
+        let merged = b.node("fold_commutative_idempotent");
+        let racy_sink = b.node("for_each");
+        b.edge(
+            &constant,
+            &sealed_root,
+            &["Local", "Stream", "NoOrder", "Bounded"],
+        );
+        b.edge(&sealed_root, &merged, &["Local"]);
+        b.edge(&unknown_root, &merged, &["Local"]);
+        b.edge(&merged, &racy_sink, &["Local"]);
+        let ir = b.build();
+
+        let results = run_analysis(&ir);
+        let verdict = |id: &str| results.verdicts.iter().find(|v| v.sink == id).unwrap();
+
+        assert!(!results.node_analyses[&reply].env_dependent);
+        assert_eq!(verdict(&reply).verdict, ENVIRONMENT_DEPENDENT);
+        assert!(
+            verdict(&reply)
+                .reason
+                .contains("no unsealed nondeterminism")
+        );
+        assert_eq!(verdict(&reply).sealed_by, vec![ordered]);
+
+        assert!(results.node_analyses[&log].env_dependent);
+        assert_ne!(verdict(&log).verdict, DETERMINISTIC);
+
+        assert_eq!(verdict(&racy_sink).verdict, NONDETERMINISTIC);
+        assert_eq!(
+            verdict(&racy_sink).witness,
+            vec![unknown_root, merged, racy_sink.clone()]
+        );
+    }
+
+    #[test]
+    fn test_deterministic_pipeline_is_deterministic() {
+        let mut b = IrBuilder::new();
+        // `sort` is not CALM-safe, but nothing upstream of it reorders anything
+        let src = b.node("source_iter");
+        let map = b.node("map");
+        let sort = b.node("sort");
+        let sink = b.node("for_each");
+        b.edge(&src, &map, &["Local"]);
+        b.edge(&map, &sort, &["Local"]);
+        b.edge(&sort, &sink, &["Local"]);
+        let ir = b.build();
+
+        let results = run_analysis(&ir);
+        assert!(results.overall.deterministic);
+        assert!(!results.overall.environment_dependent);
+        assert_eq!(results.verdicts[0].verdict, DETERMINISTIC);
+        assert!(results.verdicts[0].witness.is_empty());
+    }
+}